@@ -0,0 +1,25 @@
+//! Parsing arbitrary bytes, strictly and leniently, must fail cleanly
+//! rather than panic.
+
+#![no_main]
+
+use hex_reader::hex::TrailingPolicy;
+use hex_reader::Context;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    for lenient in [false, true] {
+        let mut ctx = Context::new(data)
+            .lenient(lenient)
+            .allow_missing_eof(true)
+            .trailing(TrailingPolicy::Ignore);
+        if let Err(e) = ctx.parse() {
+            let _ = e.to_string();
+        }
+        for warning in ctx.into_warnings() {
+            let _ = warning.to_string();
+        }
+        let _ = Context::new(data).lenient(lenient).parse_images();
+        let _ = Context::new(data).lenient(lenient).address_ranges();
+    }
+});
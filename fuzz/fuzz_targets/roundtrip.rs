@@ -0,0 +1,20 @@
+//! Whatever parses must come back the same after being written out and
+//! parsed again.
+
+#![no_main]
+
+use hex_reader::{Context, WriteOptions};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(hex) = Context::new(data).parse() else {
+        return;
+    };
+    let mut written = Vec::new();
+    hex.write(&mut written, &WriteOptions::default())
+        .expect("writing a parsed image");
+    let reparsed = Context::new(&written[..])
+        .parse()
+        .expect("parsing a written image");
+    assert!(hex.same_content(&reparsed));
+});
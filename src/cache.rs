@@ -0,0 +1,143 @@
+//! A binary cache of a parsed image, which loads far faster than the text
+//! it was parsed from.
+//!
+//! The data records are kept as they were parsed, so an image written back
+//! out from the cache has the same records. Alongside goes a fingerprint
+//! of the source, chosen by the caller (such as its size and modification
+//! time), telling whether the cache is still current.
+//!
+//! All integers are little-endian:
+//!
+//! ```text
+//! "HEXCACHE" version:u32
+//! fingerprint_len:u32 fingerprint
+//! start: 0 | 1 cs:u16 ip:u16 | 2 addr:u32
+//! record_count:u32 (addr:u32 len:u32 bytes)*
+//! ```
+
+use std::io::{self, Read};
+
+use eyre::{eyre, Context};
+
+use crate::hex::{Data, HexFile, Record, StartAddr, StartSegmentAddr};
+
+const MAGIC: &[u8; 8] = b"HEXCACHE";
+const VERSION: u32 = 1;
+
+/// Whether `data` starts like a cache.
+pub fn is_cache(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Writes `hex` to `w` along with the `fingerprint` of its source.
+pub fn write<W: io::Write>(mut w: W, hex: &HexFile, fingerprint: &[u8]) -> eyre::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    w.write_all(&len_u32(fingerprint.len())?.to_le_bytes())?;
+    w.write_all(fingerprint)?;
+    match hex.start() {
+        None => w.write_all(&[0])?,
+        Some(StartAddr::Segment(s)) => {
+            w.write_all(&[1])?;
+            w.write_all(&s.cs.to_le_bytes())?;
+            w.write_all(&s.ip.to_le_bytes())?;
+        }
+        Some(StartAddr::Linear(addr)) => {
+            w.write_all(&[2])?;
+            w.write_all(&addr.to_le_bytes())?;
+        }
+    }
+    w.write_all(&len_u32(hex.data().len())?.to_le_bytes())?;
+    for d in hex.data() {
+        w.write_all(&d.addr_range().start.to_le_bytes())?;
+        w.write_all(&len_u32(d.len())?.to_le_bytes())?;
+        w.write_all(&d.to_bytes())?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Reads an image written by [`write`], returning the fingerprint of its
+/// source along with it.
+pub fn read<R: Read>(mut r: R) -> eyre::Result<(Vec<u8>, HexFile)> {
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)
+        .context("Reading the cache header")?;
+    if &magic != MAGIC {
+        return Err(eyre!("Not a hex-reader cache"));
+    }
+    let version = read_u32(&mut r)?;
+    if version != VERSION {
+        return Err(eyre!(
+            "Unsupported cache version {}, expected {}",
+            version,
+            VERSION
+        ));
+    }
+    let len = read_u32(&mut r)?;
+    let fingerprint = read_bytes(&mut r, len)?;
+
+    let mut kind = [0];
+    r.read_exact(&mut kind).context("Truncated cache")?;
+    let start = match kind[0] {
+        0 => None,
+        1 => Some(StartAddr::Segment(StartSegmentAddr {
+            cs: read_u16(&mut r)?,
+            ip: read_u16(&mut r)?,
+        })),
+        2 => Some(StartAddr::Linear(read_u32(&mut r)?)),
+        kind => return Err(eyre!("Unknown start address kind {} in the cache", kind)),
+    };
+    let mut records = start
+        .map(|start| match start {
+            StartAddr::Segment(s) => Record::StartSegmentAddr(s),
+            StartAddr::Linear(addr) => Record::StartLinearAddr(addr),
+        })
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    let count = read_u32(&mut r)?;
+    for _ in 0..count {
+        let addr = read_u32(&mut r)?;
+        let len = read_u32(&mut r)?;
+        if len == 0 || addr.checked_add(len - 1).is_none() {
+            return Err(eyre!(
+                "Cached record of {} bytes at 0x{:08X} doesn't fit the address space",
+                len,
+                addr
+            ));
+        }
+        records.push(Record::Data(Data::new(addr, read_bytes(&mut r, len)?)));
+    }
+    if r.read(&mut [0])? != 0 {
+        return Err(eyre!("Trailing bytes after the cached image"));
+    }
+    Ok((fingerprint, HexFile::from_records(records)))
+}
+
+fn len_u32(len: usize) -> eyre::Result<u32> {
+    u32::try_from(len).map_err(|_| eyre!("{} is too large for the cache", len))
+}
+
+fn read_u16<R: Read>(r: &mut R) -> eyre::Result<u16> {
+    let mut buf = [0; 2];
+    r.read_exact(&mut buf).context("Truncated cache")?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u32<R: Read>(r: &mut R) -> eyre::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf).context("Truncated cache")?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+/// Reads `len` bytes, growing the buffer only as they arrive so a corrupt
+/// length can't allocate more than the input holds.
+fn read_bytes<R: Read>(r: &mut R, len: u32) -> eyre::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(eyre!("Truncated cache"));
+    }
+    Ok(bytes)
+}
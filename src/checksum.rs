@@ -0,0 +1,262 @@
+use std::str::FromStr;
+
+/// A checksum or digest algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Crc32,
+    /// CRC-16/CCITT-FALSE: polynomial 0x1021, initial value 0xFFFF.
+    Crc16Ccitt,
+    Sum8,
+    Sum16,
+    Sum32,
+    Sha256,
+}
+
+impl Algorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Crc32 => "crc32",
+            Algorithm::Crc16Ccitt => "crc16-ccitt",
+            Algorithm::Sum8 => "sum8",
+            Algorithm::Sum16 => "sum16",
+            Algorithm::Sum32 => "sum32",
+            Algorithm::Sha256 => "sha256",
+        }
+    }
+
+    fn hasher(&self) -> Box<dyn Hasher> {
+        match self {
+            Algorithm::Crc32 => Box::new(Crc32::new()),
+            Algorithm::Crc16Ccitt => Box::new(Crc16Ccitt::new()),
+            Algorithm::Sum8 => Box::new(Sum::<1>::default()),
+            Algorithm::Sum16 => Box::new(Sum::<2>::default()),
+            Algorithm::Sum32 => Box::new(Sum::<4>::default()),
+            Algorithm::Sha256 => Box::new(Sha256::new()),
+        }
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "crc32" => Ok(Algorithm::Crc32),
+            "crc16-ccitt" | "crc16" => Ok(Algorithm::Crc16Ccitt),
+            "sum8" | "sum" => Ok(Algorithm::Sum8),
+            "sum16" => Ok(Algorithm::Sum16),
+            "sum32" => Ok(Algorithm::Sum32),
+            "sha256" | "sha-256" => Ok(Algorithm::Sha256),
+            _ => Err(format!("Unknown checksum algorithm: {}", s)),
+        }
+    }
+}
+
+trait Hasher {
+    fn update(&mut self, data: &[u8]);
+    fn digest(&self) -> Vec<u8>;
+}
+
+/// Computes all `algos` in a single pass over `chunks`, returning the
+/// big-endian digests in the same order.
+pub fn compute(
+    algos: &[Algorithm],
+    chunks: impl IntoIterator<Item = impl AsRef<[u8]>>,
+) -> Vec<Vec<u8>> {
+    let mut hashers = algos.iter().map(|a| a.hasher()).collect::<Vec<_>>();
+    for chunk in chunks {
+        for hasher in &mut hashers {
+            hasher.update(chunk.as_ref());
+        }
+    }
+    hashers.iter().map(|h| h.digest()).collect()
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+struct Crc32 {
+    crc: u32,
+}
+
+impl Crc32 {
+    fn new() -> Self {
+        Self { crc: 0xFFFF_FFFF }
+    }
+}
+
+impl Hasher for Crc32 {
+    fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.crc = CRC32_TABLE[((self.crc ^ b as u32) & 0xFF) as usize] ^ (self.crc >> 8);
+        }
+    }
+
+    fn digest(&self) -> Vec<u8> {
+        (!self.crc).to_be_bytes().to_vec()
+    }
+}
+
+struct Crc16Ccitt {
+    crc: u16,
+}
+
+impl Crc16Ccitt {
+    fn new() -> Self {
+        Self { crc: 0xFFFF }
+    }
+}
+
+impl Hasher for Crc16Ccitt {
+    fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.crc ^= (b as u16) << 8;
+            for _ in 0..8 {
+                self.crc = if self.crc & 0x8000 != 0 {
+                    (self.crc << 1) ^ 0x1021
+                } else {
+                    self.crc << 1
+                };
+            }
+        }
+    }
+
+    fn digest(&self) -> Vec<u8> {
+        self.crc.to_be_bytes().to_vec()
+    }
+}
+
+/// Sum of all bytes, truncated to `N` bytes.
+#[derive(Default)]
+struct Sum<const N: usize> {
+    sum: u32,
+}
+
+impl<const N: usize> Hasher for Sum<N> {
+    fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.sum = self.sum.wrapping_add(b as u32);
+        }
+    }
+
+    fn digest(&self) -> Vec<u8> {
+        self.sum.to_be_bytes()[4 - N..].to_vec()
+    }
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// SHA-256 as specified in FIPS 180-4.
+struct Sha256 {
+    state: [u32; 8],
+    block: Vec<u8>,
+    len: u64,
+}
+
+impl Sha256 {
+    fn new() -> Self {
+        Self {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            block: Vec::with_capacity(64),
+            len: 0,
+        }
+    }
+
+    fn compress(state: &mut [u32; 8], block: &[u8]) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+}
+
+impl Hasher for Sha256 {
+    fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+        while !data.is_empty() {
+            let take = (64 - self.block.len()).min(data.len());
+            self.block.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.block.len() == 64 {
+                Self::compress(&mut self.state, &self.block);
+                self.block.clear();
+            }
+        }
+    }
+
+    fn digest(&self) -> Vec<u8> {
+        let mut state = self.state;
+        let mut tail = self.block.clone();
+        tail.push(0x80);
+        let padded = if tail.len() > 56 { 128 } else { 64 };
+        tail.resize(padded - 8, 0);
+        tail.extend_from_slice(&(self.len * 8).to_be_bytes());
+        for block in tail.chunks_exact(64) {
+            Self::compress(&mut state, block);
+        }
+        state.iter().flat_map(|s| s.to_be_bytes()).collect()
+    }
+}
@@ -0,0 +1,218 @@
+//! Memory maps of common microcontrollers, selected with `--chip` instead of
+//! writing a memory map file for them.
+//!
+//! Each [`Chip`] gives the regions of the largest part of its family, with
+//! system memory, OTP and option bytes marked reserved so that nothing is
+//! moved onto them. Part numbers match the longest name they start with,
+//! so `stm32f407vgt6` picks `stm32f407`.
+
+use eyre::eyre;
+
+use crate::config::Profile;
+use crate::hex::AddrRange;
+use crate::memory_map::{Access, MemoryMap, Region};
+
+const R: Access = Access {
+    write: false,
+    exec: false,
+};
+const RW: Access = Access {
+    write: true,
+    exec: false,
+};
+const RX: Access = Access {
+    write: false,
+    exec: true,
+};
+const RWX: Access = Access {
+    write: true,
+    exec: true,
+};
+
+/// A region of a [`Chip`]: name, start, size, access and ELF section, if
+/// not the one [`Region::section`] picks.
+type ChipRegion = (&'static str, u32, u32, Access, Option<&'static str>);
+
+#[derive(Debug)]
+pub struct Chip {
+    pub name: &'static str,
+    /// As taken by `--machine`.
+    pub machine: &'static str,
+    /// Flash erase page, or sector for parts with uneven sectors, in bytes.
+    pub page_size: u32,
+    regions: &'static [ChipRegion],
+}
+
+impl Chip {
+    pub fn memory_map(&self) -> MemoryMap {
+        let regions = self
+            .regions
+            .iter()
+            .map(|&(name, start, size, access, section)| Region {
+                name: name.to_string(),
+                range: AddrRange {
+                    start,
+                    end: start + (size - 1),
+                },
+                access,
+                section: section.map(str::to_string),
+                reserved: access == R,
+            })
+            .collect();
+        MemoryMap { regions }
+    }
+
+    /// The chip's regions, machine and page size, as a profile giving them
+    /// would.
+    pub fn profile(&self) -> Profile {
+        Profile {
+            #[cfg(feature = "elf")]
+            machine: self.machine.parse().ok(),
+            page_size: Some(self.page_size),
+            memory_map: self.memory_map(),
+            ..Default::default()
+        }
+    }
+}
+
+pub const CHIPS: &[Chip] = &[
+    Chip {
+        name: "stm32f401",
+        machine: "arm",
+        page_size: 0x4000,
+        regions: &[
+            ("FLASH", 0x0800_0000, 0x8_0000, RX, None),
+            ("SYSTEM", 0x1FFF_0000, 0x7800, R, None),
+            ("OTP", 0x1FFF_7800, 0x210, R, None),
+            ("OPTION_BYTES", 0x1FFF_C000, 0x10, R, None),
+            ("SRAM", 0x2000_0000, 0x1_8000, RW, None),
+        ],
+    },
+    Chip {
+        name: "stm32f407",
+        machine: "arm",
+        page_size: 0x4000,
+        regions: &[
+            ("FLASH", 0x0800_0000, 0x10_0000, RX, None),
+            ("CCMRAM", 0x1000_0000, 0x1_0000, RW, Some(".ccmram")),
+            ("SYSTEM", 0x1FFF_0000, 0x7800, R, None),
+            ("OTP", 0x1FFF_7800, 0x210, R, None),
+            ("OPTION_BYTES", 0x1FFF_C000, 0x10, R, None),
+            ("SRAM", 0x2000_0000, 0x2_0000, RW, None),
+            ("BKPSRAM", 0x4002_4000, 0x1000, RW, Some(".bkpsram")),
+        ],
+    },
+    Chip {
+        name: "stm32f429",
+        machine: "arm",
+        page_size: 0x4000,
+        regions: &[
+            ("FLASH", 0x0800_0000, 0x20_0000, RX, None),
+            ("CCMRAM", 0x1000_0000, 0x1_0000, RW, Some(".ccmram")),
+            ("SYSTEM", 0x1FFF_0000, 0x7800, R, None),
+            ("OTP", 0x1FFF_7800, 0x210, R, None),
+            ("OPTION_BYTES", 0x1FFF_C000, 0x10, R, None),
+            ("SRAM", 0x2000_0000, 0x3_0000, RW, None),
+            ("BKPSRAM", 0x4002_4000, 0x1000, RW, Some(".bkpsram")),
+        ],
+    },
+    Chip {
+        name: "stm32h743",
+        machine: "arm",
+        page_size: 0x2_0000,
+        regions: &[
+            ("ITCM", 0x0000_0000, 0x1_0000, RWX, Some(".itcm")),
+            ("FLASH", 0x0800_0000, 0x20_0000, RX, None),
+            ("SYSTEM", 0x1FF0_0000, 0x2_0000, R, None),
+            ("DTCM", 0x2000_0000, 0x2_0000, RW, None),
+            ("AXI_SRAM", 0x2400_0000, 0x8_0000, RW, Some(".axisram")),
+            ("SRAM1_3", 0x3000_0000, 0x4_8000, RW, Some(".sram1")),
+            ("SRAM4", 0x3800_0000, 0x1_0000, RW, Some(".sram4")),
+            ("BKPSRAM", 0x3880_0000, 0x1000, RW, Some(".bkpsram")),
+        ],
+    },
+    Chip {
+        name: "nrf52832",
+        machine: "arm",
+        page_size: 0x1000,
+        regions: &[
+            ("FLASH", 0x0000_0000, 0x8_0000, RX, None),
+            ("FICR", 0x1000_0000, 0x1000, R, None),
+            ("UICR", 0x1000_1000, 0x1000, RW, Some(".uicr")),
+            ("RAM", 0x2000_0000, 0x1_0000, RW, None),
+        ],
+    },
+    Chip {
+        name: "nrf52840",
+        machine: "arm",
+        page_size: 0x1000,
+        regions: &[
+            ("FLASH", 0x0000_0000, 0x10_0000, RX, None),
+            ("FICR", 0x1000_0000, 0x1000, R, None),
+            ("UICR", 0x1000_1000, 0x1000, RW, Some(".uicr")),
+            ("RAM", 0x2000_0000, 0x4_0000, RW, None),
+        ],
+    },
+    Chip {
+        name: "rp2040",
+        machine: "arm",
+        page_size: 0x1000,
+        regions: &[
+            ("BOOTROM", 0x0000_0000, 0x4000, R, None),
+            ("FLASH", 0x1000_0000, 0x20_0000, RX, None),
+            ("SRAM", 0x2000_0000, 0x4_2000, RW, None),
+        ],
+    },
+    Chip {
+        name: "lpc1768",
+        machine: "arm",
+        page_size: 0x1000,
+        regions: &[
+            ("FLASH", 0x0000_0000, 0x8_0000, RX, None),
+            ("SRAM", 0x1000_0000, 0x8000, RW, None),
+            ("BOOTROM", 0x1FFF_0000, 0x2000, R, None),
+            ("AHBSRAM", 0x2007_C000, 0x8000, RW, Some(".ahbsram")),
+        ],
+    },
+    Chip {
+        name: "lpc55s69",
+        machine: "arm",
+        page_size: 0x200,
+        regions: &[
+            ("FLASH", 0x0000_0000, 0x9_8000, RX, None),
+            ("PFR", 0x0009_E000, 0x2000, R, None),
+            ("BOOTROM", 0x0300_0000, 0x2_0000, R, None),
+            ("SRAM", 0x2000_0000, 0x4_4000, RW, None),
+        ],
+    },
+    Chip {
+        name: "samd21",
+        machine: "arm",
+        page_size: 0x100,
+        regions: &[
+            ("FLASH", 0x0000_0000, 0x4_0000, RX, None),
+            ("NVM_USER", 0x0080_4000, 0x100, R, None),
+            ("SRAM", 0x2000_0000, 0x8000, RW, None),
+        ],
+    },
+];
+
+/// Looks up the chip `part` names, ignoring case.
+pub fn find(part: &str) -> eyre::Result<&'static Chip> {
+    let part = part.to_ascii_lowercase();
+    CHIPS
+        .iter()
+        .filter(|chip| part.starts_with(chip.name))
+        .max_by_key(|chip| chip.name.len())
+        .ok_or_else(|| {
+            eyre!(
+                "Unknown chip {}, known chips are {}",
+                part,
+                CHIPS
+                    .iter()
+                    .map(|chip| chip.name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+}
@@ -0,0 +1,163 @@
+//! `map`: draws how an image fills the address space as ASCII bars, one per
+//! memory-map region, followed by its used ranges and the gaps between.
+
+use hex_reader::hex::AddrRange;
+use hex_reader::memory_map::Region;
+
+use std::io::{self, Write};
+
+use super::run::size_text;
+
+/// Drawn for a cell of the bar holding data throughout.
+const FULL: char = '#';
+/// Drawn for a cell of the bar holding some data.
+const PARTIAL: char = '+';
+/// Drawn for a cell of the bar holding none.
+const EMPTY: char = '.';
+
+/// How [`render`] lays out its chart.
+pub(super) struct MapOptions {
+    /// Cells in each bar.
+    pub width: usize,
+    /// Print sizes as byte counts instead of KiB/MiB.
+    pub bytes: bool,
+}
+
+/// Charts the sorted `ranges` holding data inside `regions`, or inside
+/// `span` if there are none.
+pub(super) fn render<W: Write>(
+    mut w: W,
+    ranges: &[AddrRange],
+    regions: &[Region],
+    span: AddrRange,
+    opts: &MapOptions,
+) -> io::Result<()> {
+    let bars = if regions.is_empty() {
+        vec![("image", span)]
+    } else {
+        regions.iter().map(|r| (r.name.as_str(), r.range)).collect()
+    };
+    let name_width = bars.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for &(name, range) in &bars {
+        let used = used_in(ranges, range);
+        writeln!(
+            w,
+            "{:<name_width$}  {}  [{}]  {} of {} used ({:.1}%)",
+            name,
+            range_text(range),
+            bar(ranges, range, opts.width),
+            size_text(used, opts.bytes),
+            size_text(range.size(), opts.bytes),
+            used as f64 * 100.0 / range.size() as f64
+        )?;
+    }
+    if !regions.is_empty() {
+        let region_ranges = regions.iter().map(|r| r.range).collect::<Vec<_>>();
+        for range in ranges.iter().flat_map(|r| r.subtract(&region_ranges)) {
+            writeln!(
+                w,
+                "{:<name_width$}  {}  {} outside every region",
+                "!",
+                range_text(range),
+                size_text(range.size(), opts.bytes)
+            )?;
+        }
+    }
+
+    writeln!(w)?;
+    let segments = segments(ranges, span);
+    let largest = segments.iter().map(|(r, _)| r.size()).max().unwrap_or(1);
+    for (range, used) in segments {
+        // Which region each segment starts in, when there are regions
+        let region = match regions.iter().find(|r| r.range.contains(range.start)) {
+            _ if regions.is_empty() => String::new(),
+            Some(region) => format!("{:<name_width$}  ", region.name),
+            None => format!("{:<name_width$}  ", "-"),
+        };
+        // At least one character, so that no segment disappears
+        let len = ((range.size() as f64 / largest as f64) * opts.width as f64).ceil() as usize;
+        writeln!(
+            w,
+            "    {}  {:<5}  {}{:>12}  {}",
+            range_text(range),
+            if used { "used" } else { "gap" },
+            region,
+            size_text(range.size(), opts.bytes),
+            std::iter::repeat_n(if used { FULL } else { EMPTY }, len.max(1)).collect::<String>()
+        )?;
+    }
+    writeln!(
+        w,
+        "\n{} full  {} partly used  {} empty, bars {} cells wide",
+        FULL, PARTIAL, EMPTY, opts.width
+    )
+}
+
+fn range_text(range: AddrRange) -> String {
+    format!("0x{:08x}-0x{:08x}", range.start, range.end)
+}
+
+/// Number of bytes of `ranges` inside `window`.
+pub(super) fn used_in(ranges: &[AddrRange], window: AddrRange) -> u64 {
+    ranges
+        .iter()
+        .filter_map(|r| r.intersection(window))
+        .map(|r| r.size())
+        .sum()
+}
+
+/// `window` drawn as `width` cells, or one per byte if it's smaller.
+fn bar(ranges: &[AddrRange], window: AddrRange, width: usize) -> String {
+    let size = window.size();
+    let cells = (width as u64).min(size);
+    let mut first = 0;
+    (0..cells)
+        .map(|i| {
+            let cell = AddrRange {
+                start: (window.start as u64 + i * size / cells) as u32,
+                end: (window.start as u64 + (i + 1) * size / cells - 1) as u32,
+            };
+            // Ranges ending before this cell end before every later one too
+            while ranges.get(first).is_some_and(|r| r.end < cell.start) {
+                first += 1;
+            }
+            let later = &ranges[first..];
+            let used = used_in(
+                &later[..later.partition_point(|r| r.start <= cell.end)],
+                cell,
+            );
+            if used == 0 {
+                EMPTY
+            } else if used == cell.size() {
+                FULL
+            } else {
+                PARTIAL
+            }
+        })
+        .collect()
+}
+
+/// The parts of `span` holding data and the gaps between them, in order.
+pub(super) fn segments(ranges: &[AddrRange], span: AddrRange) -> Vec<(AddrRange, bool)> {
+    let mut segments = Vec::new();
+    let mut next = span.start as u64;
+    for range in ranges.iter().filter_map(|r| r.intersection(span)) {
+        if (range.start as u64) > next {
+            let gap = AddrRange {
+                start: next as u32,
+                end: range.start - 1,
+            };
+            segments.push((gap, false));
+        }
+        segments.push((range, true));
+        next = range.end as u64 + 1;
+    }
+    if next <= span.end as u64 {
+        let gap = AddrRange {
+            start: next as u32,
+            end: span.end,
+        };
+        segments.push((gap, false));
+    }
+    segments
+}
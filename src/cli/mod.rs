@@ -0,0 +1,1441 @@
+//! Command line arguments, parsed into an [`Args`] holding the typed
+//! [`Command`] to run.
+
+use argh::FromArgs;
+use hex_reader::memory_map::Region;
+use hex_reader::{checksum, elf, hex, symbols};
+
+use std::path::Path;
+use std::str::FromStr;
+
+mod map;
+mod output;
+mod repl;
+mod report;
+mod run;
+mod shell;
+mod watch;
+
+pub(crate) use run::run;
+use run::STDIN;
+
+/// Parses the arguments the program was started with, exiting with usage
+/// on bad ones.
+pub(crate) fn parse() -> Args {
+    let strings = std::env::args_os()
+        .map(|s| s.into_string())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|arg| {
+            eprintln!("Invalid utf8: {}", arg.to_string_lossy());
+            std::process::exit(1)
+        });
+    let Some((program, rest)) = strings.split_first() else {
+        eprintln!("No program name, argv is empty");
+        std::process::exit(1)
+    };
+    let cmd = Path::new(program)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(program);
+    // argh takes a lone `-` for an option, so the stdin placeholder goes in
+    // its place
+    let rest = rest
+        .iter()
+        .map(|s| if s == "-" { STDIN } else { s.as_str() })
+        .collect::<Vec<_>>();
+    Args::from_args(&[cmd], &rest).unwrap_or_else(|early_exit| {
+        std::process::exit(match early_exit.status {
+            Ok(()) => {
+                println!("{}", early_exit.output);
+                0
+            }
+            Err(()) => {
+                eprintln!(
+                    "{}\nRun {} --help for more information.",
+                    early_exit.output, cmd
+                );
+                1
+            }
+        })
+    })
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(description = "Parses a .hex file")]
+pub(crate) struct Args {
+    #[argh(positional, description = "file to read, - for stdin")]
+    filename: String,
+
+    #[argh(
+        positional,
+        description = "more files to combine with the first before running the command, \
+                       failing on overlapping bytes"
+    )]
+    more: Vec<String>,
+
+    #[argh(
+        switch,
+        description = "skip malformed lines instead of failing, reporting them at the end"
+    )]
+    lenient: bool,
+
+    #[argh(
+        switch,
+        description = "accept files that end without an EOF record, as truncated captures do"
+    )]
+    allow_missing_eof: bool,
+
+    #[argh(
+        switch,
+        description = "skip anything after the EOF record instead of failing"
+    )]
+    ignore_trailing: bool,
+
+    #[argh(
+        switch,
+        description = "parse content after the EOF record as another image"
+    )]
+    concatenated: bool,
+
+    #[argh(
+        switch,
+        description = "parse back-to-back images and merge them, failing if they overlap"
+    )]
+    multi: bool,
+
+    #[argh(
+        switch,
+        description = "load inputs from a FILE.hexcache next to them while they're unchanged, \
+                       writing one after parsing"
+    )]
+    cache: bool,
+
+    #[argh(
+        switch,
+        description = "store runs of one repeated byte, like erased flash, compactly in memory, \
+                       for huge mostly blank images"
+    )]
+    compress: bool,
+
+    #[argh(
+        switch,
+        description = "index the input in one pass and decode data only where it's read, so \
+                       that info, print and dump hold little of a huge image in memory (ranges, \
+                       map and entry never hold it)"
+    )]
+    lazy: bool,
+
+    #[argh(
+        switch,
+        description = "run the command again whenever an input file changes, clearing the \
+                       screen in between"
+    )]
+    watch: bool,
+
+    #[argh(
+        option,
+        description = "device profile from the config file to take defaults from"
+    )]
+    profile: Option<String>,
+
+    #[argh(option, description = "config file to read profiles from")]
+    config: Option<String>,
+
+    #[argh(
+        option,
+        description = "take the memory map, machine and page size from the built-in database \
+                       entry for this part, such as stm32f407 or nrf52840, over the profile's"
+    )]
+    chip: Option<String>,
+
+    #[argh(
+        option,
+        description = "TOML file of [region.NAME] tables describing the device's memory, used \
+                       instead of the profile's regions by to-elf, usage, map and --region"
+    )]
+    memory_map: Option<String>,
+
+    #[argh(
+        option,
+        description = "CMSIS-SVD file of the device, naming the peripheral registers at the \
+                       addresses print, find and ranges show"
+    )]
+    svd: Option<String>,
+
+    #[argh(
+        switch,
+        description = "check everything and report what would be written, such as ranges moved \
+                       and bytes per file, without writing any file"
+    )]
+    dry_run: bool,
+
+    #[argh(
+        switch,
+        description = "replace existing output files without asking, for scripts"
+    )]
+    yes: bool,
+
+    #[argh(
+        option,
+        description = "address at which EEPROM contents are placed (0x810000 for AVR)",
+        from_str_fn(num_decode)
+    )]
+    eeprom_offset: Option<u32>,
+
+    #[argh(
+        option,
+        description = "labels file naming addresses (defaults to <file>.labels next to the input)"
+    )]
+    labels: Option<String>,
+
+    #[argh(switch, description = "print machine-readable JSON instead of text")]
+    json: bool,
+
+    #[argh(
+        option,
+        description = "show addresses as if the image started at this address, without moving \
+                       any data; addresses given on the command line stay as in the file",
+        from_str_fn(num_decode)
+    )]
+    rebase: Option<u32>,
+
+    #[argh(
+        option,
+        description = "most data bytes per written record (default: keep input records)"
+    )]
+    record_len: Option<u8>,
+
+    #[argh(switch, description = "write hex digits in lowercase")]
+    lowercase: bool,
+
+    #[argh(
+        option,
+        default = "hex::LineEnding::Lf",
+        description = "line ending of written files: lf or crlf (default lf)"
+    )]
+    line_ending: hex::LineEnding,
+
+    #[argh(
+        switch,
+        description = "leave the start address record out of written files"
+    )]
+    no_start_addr: bool,
+
+    #[argh(
+        option,
+        description = "limit print, dump, checksum and diff to this region of the profile's \
+                       memory map, e.g. FLASH"
+    )]
+    region: Option<String>,
+
+    #[argh(
+        option,
+        description = "let fill, align, swap and xform change only this window START..END, \
+                       can be repeated",
+        from_str_fn(range_decode)
+    )]
+    only: Vec<hex::AddrRange>,
+
+    #[argh(
+        option,
+        description = "keep fill, align, swap and xform out of this window START..END, \
+                       can be repeated",
+        from_str_fn(range_decode)
+    )]
+    except: Vec<hex::AddrRange>,
+
+    #[argh(
+        switch,
+        description = "record the tool version and this command after the EOF record of written \
+                       files, keeping what the input recorded"
+    )]
+    provenance: bool,
+
+    #[argh(subcommand)]
+    sub: Command,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand)]
+pub(crate) enum Command {
+    PrettyPrint(PrettyPrintCommand),
+    AddressRanges(AddrRangesCommand),
+    Map(MapCommand),
+    Report(ReportCommand),
+    Usage(UsageCommand),
+    PrintRange(PrintRangeCommand),
+    Dump(DumpCommand),
+    ToElf(ToElfCommand),
+    Entry(EntryCommand),
+    Transpose(TransposeCommand),
+    Diff(DiffCommand),
+    Checksum(ChecksumCommand),
+    ToHex(ToHexCommand),
+    Info(InfoCommand),
+    MakeDelta(MakeDeltaCommand),
+    ApplyDelta(ApplyDeltaCommand),
+    Merge(MergeCommand),
+    Patch(PatchCommand),
+    Fill(FillCommand),
+    Crop(CropCommand),
+    Erase(EraseCommand),
+    Split(SplitCommand),
+    Find(FindCommand),
+    Strings(StringsCommand),
+    SimulateFlash(SimulateFlashCommand),
+    Entropy(EntropyCommand),
+    CrcPatch(CrcPatchCommand),
+    Swap(SwapCommand),
+    Xform(XformCommand),
+    Align(AlignCommand),
+    Normalize(NormalizeCommand),
+    Explain(ExplainCommand),
+    Doctor(DoctorCommand),
+    Estimate(EstimateCommand),
+    Shell(ShellCommand),
+    Repl(ReplCommand),
+    Cache(CacheCommand),
+    Formats(FormatsCommand),
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "entry",
+    description = "Print entry point of hex file"
+)]
+pub(crate) struct EntryCommand {}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "info",
+    description = "Summarize the contents of the hex file",
+    example = "hex-reader firmware.hex info\nhex-reader --json firmware.elf info"
+)]
+pub(crate) struct InfoCommand {
+    #[argh(switch, description = "print sizes as byte counts instead of KiB/MiB")]
+    bytes: bool,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "pretty", description = "Pretty-print hex file")]
+pub(crate) struct PrettyPrintCommand {}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "ranges",
+    description = "Address ranges in hex file",
+    example = "hex-reader firmware.hex ranges --bytes"
+)]
+pub(crate) struct AddrRangesCommand {
+    #[argh(switch, description = "print sizes as byte counts instead of KiB/MiB")]
+    bytes: bool,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "map",
+    description = "Draw how the image fills the address space, per memory-map region of the \
+                   profile if it has any",
+    example = "hex-reader firmware.hex map\n\
+               hex-reader --profile stm32f4 firmware.hex map --width 100"
+)]
+pub(crate) struct MapCommand {
+    #[argh(option, default = "64", description = "cells in each bar (default 64)")]
+    width: usize,
+
+    #[argh(
+        option,
+        description = "draw only this window, as START..END",
+        from_str_fn(range_decode)
+    )]
+    range: Option<hex::AddrRange>,
+
+    #[argh(switch, description = "print sizes as byte counts instead of KiB/MiB")]
+    bytes: bool,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "report",
+    description = "Write a self-contained HTML page with the memory map, ranges, checksums, \
+                   entropy and a searchable hex view of the image",
+    example = "hex-reader firmware.hex report firmware.html\n\
+               hex-reader --profile stm32f4 firmware.hex report --no-data summary.html"
+)]
+pub(crate) struct ReportCommand {
+    #[argh(positional, description = "file to write the page to")]
+    path: String,
+
+    #[argh(
+        option,
+        default = "256",
+        description = "bytes per cell of the entropy heatmap",
+        from_str_fn(num_decode)
+    )]
+    window: u32,
+
+    #[argh(
+        switch,
+        description = "leave out the hex view, and with it the data, for a smaller page"
+    )]
+    no_data: bool,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "usage",
+    description = "Report how much of each memory of the device the image uses and how much \
+                   is left, warning about data outside all of them",
+    example = "hex-reader --profile stm32f4 firmware.hex usage\n\
+               hex-reader firmware.hex usage --memory FLASH=0x08000000+0x80000 \
+               --memory RAM=0x20000000+0x20000"
+)]
+pub(crate) struct UsageCommand {
+    #[argh(
+        option,
+        description = "a memory besides the regions of the profile, as NAME=START..END or \
+                       NAME=START+SIZE",
+        from_str_fn(memory_decode)
+    )]
+    memory: Vec<Region>,
+
+    #[argh(switch, description = "print sizes as byte counts instead of KiB/MiB")]
+    bytes: bool,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "to-elf",
+    description = "Convert hex file to ELF",
+    example = "hex-reader firmware.hex to-elf --vector-table-end 0x100 firmware.elf\n\
+               hex-reader firmware.hex to-elf --machine riscv32 firmware.elf"
+)]
+pub(crate) struct ToElfCommand {
+    #[argh(positional, description = "file to output ELF to")]
+    path: String,
+
+    #[argh(
+        option,
+        description = "target architecture: arm, riscv32, avr, msp430, xtensa or x86 (default: \
+                       the profile's, else arm)"
+    )]
+    machine: Option<elf::Machine>,
+
+    #[argh(
+        option,
+        description = "e_flags for the ELF header instead of the machine's default, such as \
+                       0x05000400 for hard-float ARM",
+        from_str_fn(num_decode)
+    )]
+    elf_flags: Option<u32>,
+
+    #[argh(
+        option,
+        description = "byte order of the ELF file: little (default) or big, for big-endian \
+                       cores; section contents are written as the image holds them",
+        default = "elf::Endian::Little"
+    )]
+    endian: elf::Endian,
+
+    #[argh(
+        option,
+        description = "flash address the SRAM sections are loaded from",
+        from_str_fn(num_decode)
+    )]
+    data_lma: Option<u32>,
+
+    #[argh(
+        option,
+        description = "address where the vector table ends and .text begins (default 0xC0)",
+        from_str_fn(num_decode)
+    )]
+    vector_table_end: Option<u32>,
+
+    #[argh(
+        switch,
+        description = "put all of flash in .text instead of splitting off the vector table"
+    )]
+    no_vector_split: bool,
+
+    #[argh(
+        option,
+        description = "add the symbols listed in this file: nm output, a GNU ld or Keil map file, \
+                       or a CSV of name,address[,size]"
+    )]
+    symbols: Option<String>,
+
+    #[argh(
+        option,
+        description = "format of the --symbols file: nm, gnu-map, keil-map or csv (default: \
+                       detected)"
+    )]
+    symbol_format: Option<symbols::SymbolFormat>,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "print",
+    description = "Print bytes in the hex file",
+    example = "hex-reader firmware.hex print --offset 0x08000000 --len 64\n\
+               hex-reader firmware.hex print --at label:version --view words32\n\
+               hex-reader firmware.hex print --at label:calibration --len 32 --as f32"
+)]
+pub(crate) struct PrintRangeCommand {
+    #[argh(
+        option,
+        description = "offset to start printing from (default 0)",
+        from_str_fn(num_decode)
+    )]
+    offset: Option<u32>,
+
+    #[argh(
+        option,
+        description = "where to start printing instead of --offset, an address or label:NAME"
+    )]
+    at: Option<String>,
+
+    #[argh(
+        option,
+        description = "number of bytes to print",
+        from_str_fn(num_decode)
+    )]
+    len: Option<u32>,
+
+    #[argh(
+        option,
+        description = "last address to print, instead of --len",
+        from_str_fn(num_decode)
+    )]
+    end: Option<u32>,
+
+    #[argh(
+        option,
+        description = "inclusive window START..END to print instead of --offset and --len, \
+                       can be repeated",
+        from_str_fn(range_decode)
+    )]
+    range: Vec<hex::AddrRange>,
+
+    #[argh(
+        option,
+        description = "number of bytes to cluster as one",
+        default = "4"
+    )]
+    cluster: usize,
+
+    #[argh(
+        switch,
+        description = "don't zero-pad a short last cluster, show only the bytes present"
+    )]
+    no_pad: bool,
+
+    #[argh(
+        switch,
+        description = "show the bytes as ASCII next to each line, as xxd does"
+    )]
+    ascii: bool,
+
+    #[argh(
+        option,
+        default = "hex::ClusterOrder::Little",
+        description = "byte order within a cluster: little (default) for little-endian words, \
+                       big for big-endian words or none for bytes in file order"
+    )]
+    endian: hex::ClusterOrder,
+
+    #[argh(
+        option,
+        long = "as",
+        description = "show each value as u8, u16, u32, i32 or f32 instead of hex bytes; \
+                       the cluster size becomes the type's"
+    )]
+    as_type: Option<hex::ValueFormat>,
+
+    #[argh(
+        switch,
+        description = "show every line, instead of a * for lines repeating the one before"
+    )]
+    no_fold: bool,
+
+    #[argh(
+        option,
+        default = "ColorChoice::Auto",
+        description = "color bytes by kind: auto (default, when printing to a terminal and \
+                       NO_COLOR isn't set), always or never"
+    )]
+    color: ColorChoice,
+
+    #[argh(
+        option,
+        description = "print the window as one, across gaps between ranges, showing missing \
+                       bytes as .. or as the given byte (e.g. 0xFF)"
+    )]
+    gap_fill: Option<GapFill>,
+
+    #[argh(
+        option,
+        description = "layout to print in: bytes (default) or words32",
+        default = "View::Bytes"
+    )]
+    view: View,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("Unknown color choice: {}", s)),
+        }
+    }
+}
+
+/// What `print --gap-fill` shows for addresses without data.
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum GapFill {
+    Dots,
+    Byte(u8),
+}
+
+impl FromStr for GapFill {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            ".." => Ok(GapFill::Dots),
+            _ => byte_decode(s).map(GapFill::Byte),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum View {
+    Bytes,
+    Words32,
+}
+
+impl FromStr for View {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "bytes" => Ok(View::Bytes),
+            "words32" => Ok(View::Words32),
+            _ => Err(format!("Unknown view: {}", s)),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Endian {
+    Little,
+    Big,
+}
+
+impl FromStr for Endian {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "little" | "le" => Ok(Endian::Little),
+            "big" | "be" => Ok(Endian::Big),
+            _ => Err(format!("Unknown byte order: {}", s)),
+        }
+    }
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "dump",
+    description = "Dump bytes to a file",
+    example = "hex-reader fw.hex dump --offset 0x1000 --len 256 --stdout | xxd"
+)]
+pub(crate) struct DumpCommand {
+    #[argh(
+        option,
+        description = "offset to start printing from (default 0, or the first byte of --region)",
+        from_str_fn(num_decode)
+    )]
+    offset: Option<u32>,
+
+    #[argh(
+        option,
+        description = "number of bytes to print",
+        from_str_fn(num_decode)
+    )]
+    len: Option<u32>,
+
+    #[argh(
+        switch,
+        description = "continue a partially written dump instead of starting over"
+    )]
+    resume: bool,
+
+    #[argh(
+        option,
+        description = "let the window span gaps and several ranges, filling gaps with this byte",
+        from_str_fn(byte_decode)
+    )]
+    fill: Option<u8>,
+
+    #[argh(switch, description = "write the bytes to stdout instead of a file")]
+    stdout: bool,
+
+    #[argh(positional, description = "file to write the bytes to")]
+    filename: Option<String>,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "transpose",
+    description = "Transpose an address range to another address"
+)]
+pub(crate) struct TransposeCommand {
+    #[argh(
+        positional,
+        description = "start of address range to transpose",
+        from_str_fn(num_decode)
+    )]
+    start: u32,
+
+    #[argh(
+        positional,
+        description = "address to transpose to",
+        from_str_fn(num_decode)
+    )]
+    dest: u32,
+
+    #[argh(positional, description = "filename to write to")]
+    filename: String,
+
+    #[argh(
+        switch,
+        description = "leave the start address alone even if it is in the moved range"
+    )]
+    keep_entry: bool,
+
+    #[argh(
+        option,
+        description = "what to do where the destination overlaps other data: error (default), \
+                       first-wins to keep that data or last-wins to overwrite it",
+        default = "hex::OverlapPolicy::Error"
+    )]
+    merge_policy: hex::OverlapPolicy,
+
+    #[argh(
+        switch,
+        description = "overwrite data at the destination, same as --merge-policy last-wins, \
+                       and move it even into reserved regions of the memory map"
+    )]
+    force: bool,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "diff",
+    description = "Compare bytes, entry point, layout and profile fields against another hex file",
+    example = "hex-reader old.hex diff new.hex --summary"
+)]
+pub(crate) struct DiffCommand {
+    #[argh(positional, description = "hex file to compare against")]
+    other: String,
+
+    #[argh(
+        switch,
+        description = "only print totals instead of every range and byte"
+    )]
+    summary: bool,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "checksum",
+    description = "Compute checksums over the image data"
+)]
+pub(crate) struct ChecksumCommand {
+    #[argh(
+        option,
+        description = "algorithm to compute (crc32, crc16-ccitt, sum8, sum16, sum32, sha256), can be repeated"
+    )]
+    algo: Vec<checksum::Algorithm>,
+
+    #[argh(
+        option,
+        description = "inclusive window START..END to checksum instead of the whole image",
+        from_str_fn(range_decode)
+    )]
+    range: Option<hex::AddrRange>,
+
+    #[argh(
+        option,
+        description = "include gaps in the checksum, filled with this byte",
+        from_str_fn(byte_decode)
+    )]
+    fill: Option<u8>,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "to-hex",
+    description = "Write the image out as Intel HEX"
+)]
+pub(crate) struct ToHexCommand {
+    #[argh(positional, description = "file to write to")]
+    path: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "make-delta",
+    description = "Write a delta turning this image into another"
+)]
+pub(crate) struct MakeDeltaCommand {
+    #[argh(
+        option,
+        description = "delta format (vcdiff)",
+        default = "DeltaFormat::Vcdiff"
+    )]
+    format: DeltaFormat,
+
+    #[argh(positional, description = "hex file the delta should produce")]
+    new: String,
+
+    #[argh(positional, description = "file to write the delta to")]
+    path: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "apply-delta",
+    description = "Apply a delta to this image and write the result as Intel HEX"
+)]
+pub(crate) struct ApplyDeltaCommand {
+    #[argh(
+        option,
+        description = "delta format (vcdiff)",
+        default = "DeltaFormat::Vcdiff"
+    )]
+    format: DeltaFormat,
+
+    #[argh(
+        option,
+        description = "address of the result, if the delta doesn't record it",
+        from_str_fn(num_decode)
+    )]
+    base: Option<u32>,
+
+    #[argh(positional, description = "delta file to apply")]
+    delta: String,
+
+    #[argh(positional, description = "file to write the result to")]
+    path: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "merge",
+    description = "Combine other hex files into this one",
+    example = "hex-reader bootloader.hex merge app.hex --output combined.hex"
+)]
+pub(crate) struct MergeCommand {
+    #[argh(
+        option,
+        description = "what to do with overlapping bytes: error (default), first-wins or last-wins",
+        default = "hex::OverlapPolicy::Error"
+    )]
+    overlap: hex::OverlapPolicy,
+
+    #[argh(option, short = 'o', description = "file to write the merged image to")]
+    output: String,
+
+    #[argh(positional, description = "hex files to merge in, in order")]
+    inputs: Vec<String>,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "patch",
+    description = "Overwrite bytes in the image and write the result"
+)]
+pub(crate) struct PatchCommand {
+    #[argh(option, description = "address to patch at, an address or label:NAME")]
+    addr: String,
+
+    #[argh(option, description = "bytes to write, as hex digits (e.g. DEADBEEF)")]
+    bytes: Option<HexBytes>,
+
+    #[argh(option, description = "binary file whose contents to write")]
+    from: Option<String>,
+
+    #[argh(
+        switch,
+        description = "add bytes that fall outside the existing ranges instead of failing"
+    )]
+    create: bool,
+
+    #[argh(positional, description = "file to write the patched image to")]
+    path: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "fill",
+    description = "Fill gaps between ranges, or a given window, with a byte pattern"
+)]
+pub(crate) struct FillCommand {
+    #[argh(
+        option,
+        description = "byte or repeating pattern to fill with, as hex digits (default the profile's fill, or FF)"
+    )]
+    pattern: Option<HexBytes>,
+
+    #[argh(
+        option,
+        description = "start of the window to fill instead of the gaps",
+        from_str_fn(num_decode)
+    )]
+    start: Option<u32>,
+
+    #[argh(
+        option,
+        description = "length of the window to fill",
+        from_str_fn(num_decode)
+    )]
+    len: Option<u32>,
+
+    #[argh(positional, description = "file to write the filled image to")]
+    path: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "crop",
+    description = "Keep only the data inside the given address windows"
+)]
+pub(crate) struct CropCommand {
+    #[argh(
+        option,
+        description = "inclusive window START..END to keep, can be repeated",
+        from_str_fn(range_decode)
+    )]
+    range: Vec<hex::AddrRange>,
+
+    #[argh(positional, description = "file to write the cropped image to")]
+    path: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "erase",
+    description = "Remove the data inside the given address windows"
+)]
+pub(crate) struct EraseCommand {
+    #[argh(
+        option,
+        description = "inclusive window START..END to remove, can be repeated",
+        from_str_fn(range_decode)
+    )]
+    range: Vec<hex::AddrRange>,
+
+    #[argh(positional, description = "file to write the remaining image to")]
+    path: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "split",
+    description = "Write each region of the image to its own hex file"
+)]
+pub(crate) struct SplitCommand {
+    #[argh(
+        option,
+        description = "address to split at instead of at gaps, can be repeated",
+        from_str_fn(num_decode)
+    )]
+    at: Vec<u32>,
+
+    #[argh(
+        option,
+        default = "String::from(\"out_{addr}.hex\")",
+        description = "output file name, with {{addr}} replaced by the first address of the region, or by \"eeprom\" for the .eep file of EEPROM data"
+    )]
+    template: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "find",
+    description = "Print the addresses where a byte sequence occurs"
+)]
+pub(crate) struct FindCommand {
+    #[argh(
+        positional,
+        description = "hex bytes to search for, with ?? matching any byte"
+    )]
+    pattern: BytePattern,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "strings",
+    description = "Print runs of printable ASCII with their addresses"
+)]
+pub(crate) struct StringsCommand {
+    #[argh(
+        option,
+        short = 'n',
+        default = "4",
+        description = "shortest run to print"
+    )]
+    min_len: usize,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "simulate-flash",
+    description = "Report the flash pages programming the image touches"
+)]
+pub(crate) struct SimulateFlashCommand {
+    #[argh(
+        option,
+        description = "flash page size in bytes (default the profile's page_size, or 2048)",
+        from_str_fn(num_decode)
+    )]
+    page_size: Option<u32>,
+
+    #[argh(
+        option,
+        description = "value of an erased byte (default the profile's erase_value, or 0xFF)",
+        from_str_fn(byte_decode)
+    )]
+    erase: Option<u8>,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "entropy",
+    description = "Print the entropy of each block of the image"
+)]
+pub(crate) struct EntropyCommand {
+    #[argh(
+        option,
+        default = "256",
+        description = "block size in bytes",
+        from_str_fn(num_decode)
+    )]
+    window: u32,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "crc-patch",
+    description = "Compute a checksum over a window and store it in the image",
+    example = "hex-reader app.hex crc-patch --range 0x08004000..0x0801FFFC \
+               --store-at 0x0801FFFC out.hex"
+)]
+pub(crate) struct CrcPatchCommand {
+    #[argh(
+        option,
+        default = "checksum::Algorithm::Crc32",
+        description = "algorithm to compute (default crc32)"
+    )]
+    algo: checksum::Algorithm,
+
+    #[argh(
+        option,
+        description = "inclusive window START..END to checksum",
+        from_str_fn(range_decode)
+    )]
+    range: hex::AddrRange,
+
+    #[argh(
+        option,
+        description = "address to store the checksum at, an address or label:NAME"
+    )]
+    store_at: String,
+
+    #[argh(
+        option,
+        default = "Endian::Little",
+        description = "byte order of the stored checksum (little, big)"
+    )]
+    endian: Endian,
+
+    #[argh(
+        option,
+        default = "0xFF",
+        description = "value of the gaps in the window (default 0xFF)",
+        from_str_fn(byte_decode)
+    )]
+    fill: u8,
+
+    #[argh(positional, description = "file to write the patched image to")]
+    path: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "swap",
+    description = "Reverse the byte order of 16- or 32-bit units"
+)]
+pub(crate) struct SwapCommand {
+    #[argh(option, default = "32", description = "unit width in bits (16, 32)")]
+    width: u32,
+
+    #[argh(
+        option,
+        description = "inclusive window START..END to swap instead of the whole image",
+        from_str_fn(range_decode)
+    )]
+    range: Option<hex::AddrRange>,
+
+    #[argh(positional, description = "file to write the swapped image to")]
+    path: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "xform",
+    description = "Combine the image bytes with a key using XOR, AND or OR"
+)]
+pub(crate) struct XformCommand {
+    #[argh(option, description = "operation to apply (xor, and, or)")]
+    op: hex::BitOp,
+
+    #[argh(
+        option,
+        description = "byte or repeating key, as hex digits (e.g. 5A or DEADBEEF)"
+    )]
+    key: HexBytes,
+
+    #[argh(
+        option,
+        description = "inclusive window START..END to transform instead of the whole image",
+        from_str_fn(range_decode)
+    )]
+    range: Option<hex::AddrRange>,
+
+    #[argh(positional, description = "file to write the transformed image to")]
+    path: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "align",
+    description = "Pad each range to start and end on an alignment boundary"
+)]
+pub(crate) struct AlignCommand {
+    #[argh(
+        option,
+        description = "alignment in bytes (default the profile's page_size)",
+        from_str_fn(num_decode)
+    )]
+    to: Option<u32>,
+
+    #[argh(
+        option,
+        description = "byte to pad with (default the profile's fill, or 0xFF)",
+        from_str_fn(byte_decode)
+    )]
+    fill: Option<u8>,
+
+    #[argh(positional, description = "file to write the aligned image to")]
+    path: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "normalize",
+    description = "Rewrite the hex file in canonical form so it diffs cleanly"
+)]
+pub(crate) struct NormalizeCommand {
+    #[argh(
+        option,
+        description = "data bytes per record (default the profile's record_size, or 16)"
+    )]
+    record_len: Option<u8>,
+
+    #[argh(positional, description = "file to write the normalized image to")]
+    path: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "explain",
+    description = "Walk through the first records, describing each field",
+    example = "hex-reader firmware.hex explain --count 5"
+)]
+pub(crate) struct ExplainCommand {
+    #[argh(
+        option,
+        default = "10",
+        description = "number of records to explain (default 10)"
+    )]
+    count: usize,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "doctor",
+    description = "Look for signs of corruption, as serial capture dumps suffer"
+)]
+pub(crate) struct DoctorCommand {
+    #[argh(
+        option,
+        description = "write a copy with damaged lines repaired where exactly one fix fits"
+    )]
+    repair: Option<String>,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "estimate",
+    description = "Estimate how long a programmer takes to flash the image",
+    example = "hex-reader --profile line3 firmware.hex estimate\n\
+               hex-reader firmware.hex estimate --rate 40000 --erase-ms 25 --record-overhead-us 200"
+)]
+pub(crate) struct EstimateCommand {
+    #[argh(
+        option,
+        description = "flash page size in bytes (default from the profile, else 2048)",
+        from_str_fn(num_decode)
+    )]
+    page_size: Option<u32>,
+
+    #[argh(
+        option,
+        description = "programming speed in bytes per second (default from the profile)",
+        from_str_fn(num_decode)
+    )]
+    rate: Option<u32>,
+
+    #[argh(
+        option,
+        description = "milliseconds to erase one page (default from the profile, else 0)",
+        from_str_fn(num_decode)
+    )]
+    erase_ms: Option<u32>,
+
+    #[argh(
+        option,
+        description = "microseconds of overhead per record (default from the profile, else 0)",
+        from_str_fn(num_decode)
+    )]
+    record_overhead_us: Option<u32>,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "shell",
+    description = "Parse the file once, then run commands read from stdin against it",
+    example = "hex-reader firmware.hex shell < queries.txt"
+)]
+pub(crate) struct ShellCommand {}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "repl",
+    description = "Parse the file once, then patch, query and save it interactively",
+    example = "hex-reader firmware.hex repl"
+)]
+pub(crate) struct ReplCommand {}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "cache",
+    description = "Manage binary caches of the parsed image, which load much faster"
+)]
+pub(crate) struct CacheCommand {
+    #[argh(subcommand)]
+    sub: CacheSubcommands,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "formats",
+    description = "List the formats inputs can be in, marking the one the file is in",
+    example = "hex-reader firmware.axf formats"
+)]
+pub(crate) struct FormatsCommand {}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand)]
+enum CacheSubcommands {
+    Save(CacheSaveCommand),
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "save",
+    description = "Save the parsed image to a cache, which can be given as input instead",
+    example = "hex-reader firmware.hex cache save firmware.hexcache\n\
+               hex-reader firmware.hexcache ranges"
+)]
+struct CacheSaveCommand {
+    #[argh(positional, description = "file to write the cache to")]
+    path: String,
+}
+
+/// A byte string given on the command line as hex digits.
+#[derive(PartialEq, Debug)]
+struct HexBytes(Vec<u8>);
+
+impl FromStr for HexBytes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.strip_prefix("0x").unwrap_or(s).replace([' ', '_'], "");
+        if digits.is_empty() || !digits.len().is_multiple_of(2) {
+            return Err(format!("{} isn't a whole number of hex bytes", s));
+        }
+        (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16))
+            .collect::<Result<_, _>>()
+            .map(HexBytes)
+            .map_err(|e| format!("Invalid hex bytes {}: {}", s, e))
+    }
+}
+
+/// A search pattern of hex bytes where `??` stands for any byte.
+#[derive(PartialEq, Debug)]
+struct BytePattern(Vec<Option<u8>>);
+
+impl FromStr for BytePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.strip_prefix("0x").unwrap_or(s).replace([' ', '_'], "");
+        if digits.is_empty() || !digits.len().is_multiple_of(2) {
+            return Err(format!("{} isn't a whole number of hex bytes", s));
+        }
+        (0..digits.len())
+            .step_by(2)
+            .map(|i| match &digits[i..i + 2] {
+                "??" => Ok(None),
+                byte => u8::from_str_radix(byte, 16).map(Some),
+            })
+            .collect::<Result<_, _>>()
+            .map(BytePattern)
+            .map_err(|e| format!("Invalid pattern {}: {}", s, e))
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum DeltaFormat {
+    Vcdiff,
+}
+
+impl FromStr for DeltaFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "vcdiff" | "xdelta3" => Ok(DeltaFormat::Vcdiff),
+            _ => Err(format!("Unknown delta format: {}", s)),
+        }
+    }
+}
+
+fn num_decode(s: &str) -> Result<u32, String> {
+    let s = s.replace('_', "");
+    let s = s.as_str();
+    let (s, rad) = if let Some(s) = s.strip_prefix("0x") {
+        (s, 16)
+    } else if let Some(s) = s.strip_prefix("0b") {
+        (s, 2)
+    } else if let Some(s) = s.strip_prefix("0o") {
+        (s, 8)
+    } else {
+        (s, 10)
+    };
+    u32::from_str_radix(s, rad).map_err(|e| e.to_string())
+}
+
+/// Parses an inclusive `START..END` address range.
+fn byte_decode(s: &str) -> Result<u8, String> {
+    let value = num_decode(s)?;
+    u8::try_from(value).map_err(|_| format!("{} doesn't fit in a byte", s))
+}
+
+/// Parses a `NAME=START..END` or `NAME=START+SIZE` memory.
+fn memory_decode(s: &str) -> Result<Region, String> {
+    let (name, range) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected NAME=START..END or NAME=START+SIZE, got {}", s))?;
+    let range = match range.split_once('+') {
+        Some((start, size)) => {
+            let start = num_decode(start)?;
+            let end = num_decode(size)?
+                .checked_sub(1)
+                .and_then(|last| start.checked_add(last))
+                .ok_or_else(|| format!("Memory {} is empty or runs past 0xffffffff", name))?;
+            hex::AddrRange { start, end }
+        }
+        None => range_decode(range)?,
+    };
+    Ok(Region::new(name, range))
+}
+
+fn range_decode(s: &str) -> Result<hex::AddrRange, String> {
+    let (start, end) = s
+        .split_once("..")
+        .or_else(|| s.split_once(':'))
+        .ok_or_else(|| format!("Expected START..END, got {}", s))?;
+    let range = hex::AddrRange {
+        start: num_decode(start)?,
+        end: num_decode(end)?,
+    };
+    if range.start > range.end {
+        return Err(format!("Range {} ends before it starts", s));
+    }
+    Ok(range)
+}
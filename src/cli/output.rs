@@ -0,0 +1,116 @@
+//! Files written by commands, all opened through [`Outputs`] so that
+//! `--dry-run` and the confirmation before overwriting apply to each.
+
+use color_eyre::eyre::{self, Context};
+use eyre::eyre;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+
+/// How commands may write their output files.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct Outputs {
+    /// Report what would be written instead of writing it.
+    pub dry_run: bool,
+    /// Replace existing files without asking.
+    pub yes: bool,
+}
+
+/// A file being written, or under `--dry-run` a count of what would be.
+pub(super) enum OutputFile {
+    File(File),
+    DryRun {
+        path: String,
+        len: u64,
+        append: bool,
+    },
+}
+
+impl Outputs {
+    /// Creates `path` for writing, replacing any file there.
+    pub fn create(&self, path: &str) -> eyre::Result<OutputFile> {
+        self.open(path, false)
+    }
+
+    /// Opens `path` for writing, after what's already there if `append`.
+    /// Replacing a file that isn't empty asks first when run from a
+    /// terminal, unless `--yes` was given.
+    pub fn open(&self, path: &str, append: bool) -> eyre::Result<OutputFile> {
+        if self.dry_run {
+            return Ok(OutputFile::DryRun {
+                path: path.to_string(),
+                len: 0,
+                append,
+            });
+        }
+        let replaces = !append && fs::metadata(path).is_ok_and(|m| m.len() > 0);
+        if replaces && !self.yes && io::stdin().is_terminal() && !confirm(path)? {
+            return Err(eyre!("Not overwriting {}", path));
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .with_context(|| format!("Creating file {}", path))?;
+        Ok(OutputFile::File(file))
+    }
+
+    /// Writes `data` to `path` as a whole.
+    pub fn write(&self, path: &str, data: &[u8]) -> eyre::Result<()> {
+        let mut file = self.create(path)?;
+        file.write_all(data)
+            .with_context(|| format!("Writing {}", path))?;
+        file.finish()
+    }
+}
+
+impl OutputFile {
+    /// Flushes the file, or under `--dry-run` says what it would have held.
+    pub fn finish(mut self) -> eyre::Result<()> {
+        match &mut self {
+            OutputFile::File(file) => file.flush()?,
+            OutputFile::DryRun { path, len, append } => {
+                let exists = Path::new(path).exists();
+                if *append && exists {
+                    println!("Would append {} bytes to {}", len, path);
+                } else if exists {
+                    println!("Would write {} bytes to {}, replacing it", len, path);
+                } else {
+                    println!("Would write {} bytes to {}", len, path);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputFile::File(file) => file.write(buf),
+            OutputFile::DryRun { len, .. } => {
+                *len += buf.len() as u64;
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputFile::File(file) => file.flush(),
+            OutputFile::DryRun { .. } => Ok(()),
+        }
+    }
+}
+
+/// Asks on stderr whether to replace `path`, reading the answer from stdin.
+fn confirm(path: &str) -> eyre::Result<bool> {
+    eprint!("{} already exists, overwrite it? [y/N] ", path);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
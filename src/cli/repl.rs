@@ -0,0 +1,153 @@
+//! `repl`: an interactive session on an image parsed once, which can be
+//! patched in place and saved, besides running any other command on it.
+
+use color_eyre::eyre::{self, Context};
+use eyre::eyre;
+use hex_reader::hex;
+
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::str::FromStr;
+
+use super::output::Outputs;
+use super::run::{resolve_location, run_with, write_hex_file};
+use super::shell::{parse_args, split_words};
+use super::HexBytes;
+
+const HELP: &str = "\
+Commands besides those of the command line, written as after the filename:
+  print ADDR [LEN] [OPTIONS]   print LEN bytes from ADDR, short for --at and --len
+  patch ADDR BYTES [--create]  overwrite bytes of the image being edited
+  save PATH                    write the image being edited to PATH
+  help                         show this list and the command line usage
+  quit                         leave, asking again if there are unsaved changes
+";
+
+/// The image being edited, with whether it changed since it was last saved.
+struct Session<'a> {
+    filename: &'a str,
+    labels: Option<&'a str>,
+    write_opts: &'a hex::WriteOptions,
+    hex_file: hex::HexFile,
+    dirty: bool,
+}
+
+/// Reads commands from stdin and runs them against `hex_file`, which `patch`
+/// modifies for every command after it. `labels` and `write_opts` are those
+/// given on the command line. A failing command is reported and the session
+/// carries on.
+pub(super) fn run(
+    filename: &str,
+    labels: Option<&str>,
+    hex_file: hex::HexFile,
+    write_opts: &hex::WriteOptions,
+) -> eyre::Result<()> {
+    let interactive = io::stdin().is_terminal();
+    if interactive {
+        eprintln!(
+            "{} loaded, {} range(s). Type `help` for the commands or `quit` to leave.",
+            filename,
+            hex_file.address_ranges().len()
+        );
+    }
+    let mut session = Session {
+        filename,
+        labels,
+        write_opts,
+        hex_file,
+        dirty: false,
+    };
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        if interactive {
+            eprint!("hex> ");
+            io::stderr().flush()?;
+        }
+        let Some(line) = lines.next().transpose()? else {
+            if session.dirty {
+                eprintln!("Warning: Unsaved changes discarded");
+            }
+            break;
+        };
+        let words = match split_words(&line) {
+            Ok(words) => words,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                continue;
+            }
+        };
+        let words = words.iter().map(String::as_str).collect::<Vec<_>>();
+        if let ["quit" | "exit"] = words[..] {
+            if !session.dirty {
+                break;
+            }
+            eprintln!(
+                "There are unsaved changes; `save PATH` them, or `quit` again to discard them"
+            );
+            session.dirty = false;
+            continue;
+        }
+        if let Err(e) = session.command(&words) {
+            eprintln!("Error: {:#}", e);
+        }
+        // Keeps each answer together when stdout is a pipe
+        io::stdout().flush()?;
+    }
+    Ok(())
+}
+
+impl Session<'_> {
+    fn command(&mut self, words: &[&str]) -> eyre::Result<()> {
+        match words {
+            [] => {}
+            ["help"] => {
+                print!("{}", HELP);
+                parse_args(self.filename, &["--help"]);
+            }
+            ["save", path] => {
+                write_hex_file(
+                    &Outputs::default(),
+                    path,
+                    &self.hex_file,
+                    self.write_opts,
+                    None,
+                )?;
+                self.dirty = false;
+            }
+            ["save", ..] => return Err(eyre!("Usage: save PATH")),
+            ["patch", addr, bytes, flags @ ..] if !addr.starts_with('-') => {
+                let create = match flags {
+                    [] => false,
+                    ["--create"] => true,
+                    _ => return Err(eyre!("Usage: patch ADDR BYTES [--create]")),
+                };
+                let addr = resolve_location(addr, self.filename, self.labels)?;
+                let bytes = HexBytes::from_str(bytes).map_err(|e| eyre!(e))?;
+                self.hex_file
+                    .patch(addr, &bytes.0, create)
+                    .context("Patching")?;
+                self.dirty = true;
+            }
+            ["patch", ..] => return Err(eyre!("Usage: patch ADDR BYTES [--create]")),
+            ["print", addr, rest @ ..] if !addr.starts_with('-') => {
+                let (len, rest) = match rest {
+                    [len, rest @ ..] if !len.starts_with('-') => (Some(*len), rest),
+                    _ => (None, rest),
+                };
+                let mut argv = vec!["print", "--at", addr];
+                argv.extend(len.iter().flat_map(|len| ["--len", len]));
+                argv.extend(rest);
+                self.run(&argv)?;
+            }
+            _ => self.run(words)?,
+        }
+        Ok(())
+    }
+
+    /// Runs a command of the command line against the image being edited.
+    fn run(&self, words: &[&str]) -> eyre::Result<()> {
+        match parse_args(self.filename, words) {
+            Some(args) => run_with(args, Some(&self.hex_file)),
+            None => Ok(()),
+        }
+    }
+}
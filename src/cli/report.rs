@@ -0,0 +1,364 @@
+//! `report`: a single self-contained HTML page describing an image, with its
+//! memory map, ranges, checksums, an entropy heatmap and a searchable hex
+//! view, for readers without the command line.
+
+use hex_reader::checksum::{self, Algorithm};
+use hex_reader::hex::{AddrRange, Data, HexFile};
+use hex_reader::json::Json;
+use hex_reader::memory_map::Region;
+
+use std::io::{self, Write};
+
+use super::map::{segments, used_in};
+use super::run::{hex_string, size_text};
+
+/// Digests of the whole image listed in the report.
+const ALGORITHMS: [Algorithm; 5] = [
+    Algorithm::Crc32,
+    Algorithm::Crc16Ccitt,
+    Algorithm::Sum8,
+    Algorithm::Sum32,
+    Algorithm::Sha256,
+];
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h2 { border-bottom: 1px solid #ccc; margin-top: 1.5em; }
+table { border-collapse: collapse; }
+td, th { padding: 2px 12px 2px 0; text-align: left; }
+.mono, td.mono { font-family: monospace; }
+.bar { position: relative; height: 22px; background: #eee; border: 1px solid #999; }
+.bar span { position: absolute; top: 0; bottom: 0; min-width: 1px; background: #2a6ebb; }
+.gap { color: #999; }
+.heat { display: flex; flex-wrap: wrap; gap: 1px; }
+.heat i { width: 8px; height: 8px; }
+#hex { font-family: monospace; white-space: pre; }
+#hex b { background: #fd4; }
+";
+
+const SCRIPT: &str = r#"
+const blobs = RANGES.map(r => ({
+  start: r.start,
+  bytes: Uint8Array.from(atob(r.data), c => c.charCodeAt(0)),
+}));
+const ROW = 16, PAGE = 32;
+// First row of each range, counting rows across all of them
+const firsts = [];
+let rows = 0;
+for (const b of blobs) { firsts.push(rows); rows += Math.ceil(b.bytes.length / ROW); }
+let top = 0, found = null;
+const $ = id => document.getElementById(id);
+const hex2 = v => v.toString(16).padStart(2, '0');
+function rowAt(n) {
+  let i = firsts.length - 1;
+  while (firsts[i] > n) i--;
+  return [i, (n - firsts[i]) * ROW];
+}
+function render() {
+  const lines = [];
+  for (let n = top; n < Math.min(top + PAGE, rows); n++) {
+    const [i, off] = rowAt(n), b = blobs[i];
+    let hex = '', ascii = '';
+    for (let k = off; k < off + ROW; k++) {
+      if (k >= b.bytes.length) { hex += '   '; continue; }
+      const v = b.bytes[k], c = v >= 0x20 && v < 0x7f ? String.fromCharCode(v) : '.';
+      const esc = c.replace(/[&<>]/g, ch => ({ '&': '&amp;', '<': '&lt;', '>': '&gt;' })[ch]);
+      const hit = found && found[0] === i && k >= found[1] && k < found[1] + found[2];
+      hex += (hit ? '<b>' + hex2(v) + '</b>' : hex2(v)) + ' ';
+      ascii += hit ? '<b>' + esc + '</b>' : esc;
+    }
+    lines.push((b.start + off).toString(16).padStart(8, '0') + '  ' + hex + ' ' + ascii);
+  }
+  $('hex').innerHTML = lines.join('\n') || 'No data';
+  $('where').textContent = rows ? `rows ${top + 1}-${Math.min(top + PAGE, rows)} of ${rows}` : '';
+}
+function show(i, off) {
+  top = Math.max(0, Math.min(firsts[i] + Math.floor(off / ROW), rows - 1));
+  render();
+}
+function goTo() {
+  const addr = parseInt($('addr').value.replace(/^0x/i, ''), 16);
+  const i = blobs.findIndex(b => addr >= b.start && addr < b.start + b.bytes.length);
+  if (i < 0) { $('status').textContent = 'No data at that address'; return; }
+  $('status').textContent = '';
+  show(i, addr - blobs[i].start);
+}
+function pattern() {
+  const text = $('find').value;
+  if (/^\s*([0-9a-f]{2}\s*)+$/i.test(text)) {
+    return text.replace(/\s/g, '').match(/../g).map(h => parseInt(h, 16));
+  }
+  return Array.from(new TextEncoder().encode(text));
+}
+function findNext() {
+  const p = pattern();
+  if (!p.length) return;
+  // Continue after the match shown, wrapping around at the end
+  let [from, fromOff] = found ? [found[0], found[1] + 1] : [0, 0];
+  for (let n = 0; n <= blobs.length; n++) {
+    const i = (from + n) % blobs.length, bytes = blobs[i].bytes;
+    for (let k = n ? 0 : fromOff; k + p.length <= bytes.length; k++) {
+      if (p.every((v, j) => bytes[k + j] === v)) {
+        found = [i, k, p.length];
+        $('status').textContent = 'Found at 0x' + (blobs[i].start + k).toString(16).padStart(8, '0');
+        show(i, k);
+        return;
+      }
+    }
+  }
+  found = null;
+  $('status').textContent = 'Not found';
+  render();
+}
+$('prev').onclick = () => { top = Math.max(0, top - PAGE); render(); };
+$('next').onclick = () => { top = Math.min(Math.max(0, rows - 1), top + PAGE); render(); };
+$('go').onclick = goTo;
+$('search').onclick = () => { found = null; findNext(); };
+$('again').onclick = findNext;
+render();
+"#;
+
+/// What goes into [`render`]'s report.
+pub(super) struct ReportOptions<'a> {
+    /// Names the image in the title.
+    pub title: &'a str,
+    /// Memory-map regions to draw bars for; one bar over the data if empty.
+    pub regions: &'a [Region],
+    /// Bytes per cell of the entropy heatmap.
+    pub window: usize,
+    /// Embed the data for the hex view.
+    pub data: bool,
+}
+
+/// Writes the report on `hex_file` as a complete HTML page.
+pub(super) fn render<W: Write>(
+    mut w: W,
+    hex_file: &HexFile,
+    opts: &ReportOptions,
+) -> io::Result<()> {
+    let ranges = hex_file.address_ranges();
+    let data_bytes = ranges.iter().map(|r| r.size()).sum::<u64>();
+    let title = escape(opts.title);
+    writeln!(
+        w,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+    )?;
+    writeln!(w, "<title>{} - hex-reader report</title>", title)?;
+    writeln!(w, "<style>{}</style>\n</head>\n<body>", STYLE)?;
+    writeln!(w, "<h1>{}</h1>", title)?;
+
+    let addr = |a: Option<u32>| a.map_or("-".to_string(), |a| format!("0x{:08x}", a));
+    writeln!(w, "<table>")?;
+    for (name, value) in [
+        (
+            "Data bytes",
+            format!("0x{:x} ({})", data_bytes, size_text(data_bytes, false)),
+        ),
+        ("Ranges", ranges.len().to_string()),
+        ("Lowest addr", addr(ranges.first().map(|r| r.start))),
+        ("Highest addr", addr(ranges.last().map(|r| r.end))),
+        ("Entry point", addr(hex_file.start_addr())),
+    ] {
+        writeln!(
+            w,
+            "<tr><th>{}</th><td class=\"mono\">{}</td></tr>",
+            name, value
+        )?;
+    }
+    writeln!(w, "</table>")?;
+
+    // The span of the data, if there's any
+    let span = ranges
+        .first()
+        .zip(ranges.last())
+        .map(|(first, last)| AddrRange {
+            start: first.start,
+            end: last.end,
+        });
+
+    writeln!(w, "<h2>Memory map</h2>")?;
+    let bars = if opts.regions.is_empty() {
+        span.map(|span| ("image", span)).into_iter().collect()
+    } else {
+        opts.regions
+            .iter()
+            .map(|r| (r.name.as_str(), r.range))
+            .collect::<Vec<_>>()
+    };
+    for (name, window) in bars {
+        let used = used_in(&ranges, window);
+        writeln!(
+            w,
+            "<p><b>{}</b> <span class=\"mono\">{}</span>: {} of {} used ({:.1}%)</p>",
+            escape(name),
+            window,
+            size_text(used, false),
+            size_text(window.size(), false),
+            used as f64 * 100.0 / window.size() as f64
+        )?;
+        write!(w, "<div class=\"bar\">")?;
+        for range in ranges.iter().filter_map(|r| r.intersection(window)) {
+            let percent =
+                |addr: u64| (addr - window.start as u64) as f64 * 100.0 / window.size() as f64;
+            write!(
+                w,
+                "<span style=\"left:{:.3}%;width:{:.3}%\" title=\"{}\"></span>",
+                percent(range.start as u64),
+                percent(range.end as u64 + 1) - percent(range.start as u64),
+                range
+            )?;
+        }
+        writeln!(w, "</div>")?;
+    }
+    if !opts.regions.is_empty() {
+        let region_ranges = opts.regions.iter().map(|r| r.range).collect::<Vec<_>>();
+        for range in ranges.iter().flat_map(|r| r.subtract(&region_ranges)) {
+            writeln!(
+                w,
+                "<p><b>Outside every region:</b> <span class=\"mono\">{}</span>, {}</p>",
+                range,
+                size_text(range.size(), false)
+            )?;
+        }
+    }
+
+    writeln!(w, "<h2>Ranges</h2>")?;
+    match span {
+        None => writeln!(w, "<p>No data</p>")?,
+        Some(span) => {
+            writeln!(
+                w,
+                "<table>\n<tr><th>Start</th><th>End</th><th></th><th>Size</th><th>CRC32</th></tr>"
+            )?;
+            for (range, used) in segments(&ranges, span) {
+                let crc = if used {
+                    let data = data_in(hex_file, range).iter().map(|d| d.to_bytes());
+                    let digest = checksum::compute(&[Algorithm::Crc32], data);
+                    hex_string(&digest[0])
+                } else {
+                    String::new()
+                };
+                writeln!(
+                    w,
+                    "<tr{}><td class=\"mono\">0x{:08x}</td><td class=\"mono\">0x{:08x}</td>\
+                     <td>{}</td><td>{}</td><td class=\"mono\">{}</td></tr>",
+                    if used { "" } else { " class=\"gap\"" },
+                    range.start,
+                    range.end,
+                    if used { "used" } else { "gap" },
+                    size_text(range.size(), false),
+                    crc
+                )?;
+            }
+            writeln!(w, "</table>")?;
+        }
+    }
+
+    writeln!(w, "<h2>Checksums</h2>\n<table>")?;
+    let digests = checksum::compute(&ALGORITHMS, hex_file.data().iter().map(|d| d.to_bytes()));
+    for (algo, digest) in ALGORITHMS.iter().zip(digests) {
+        writeln!(
+            w,
+            "<tr><th>{}</th><td class=\"mono\">{}</td></tr>",
+            algo.name(),
+            hex_string(&digest)
+        )?;
+    }
+    writeln!(
+        w,
+        "</table>\n<p>Over the data bytes in address order, gaps left out.</p>"
+    )?;
+
+    writeln!(w, "<h2>Entropy</h2>")?;
+    writeln!(
+        w,
+        "<p>One cell per {} bytes, from blue for constant data to red for random or \
+         compressed data at 8 bits per byte.</p>\n<div class=\"heat\">",
+        opts.window
+    )?;
+    for (range, bits) in hex_file.entropy(opts.window) {
+        writeln!(
+            w,
+            "<i style=\"background:hsl({:.0},75%,50%)\" title=\"{} {:.2} bits\"></i>",
+            240.0 - bits * 30.0,
+            range,
+            bits
+        )?;
+    }
+    writeln!(w, "</div>")?;
+
+    if opts.data {
+        writeln!(
+            w,
+            "<h2>Data</h2>\n<p>\
+             <button id=\"prev\">&lt;</button> <button id=\"next\">&gt;</button> \
+             <span id=\"where\"></span> &nbsp; \
+             <input id=\"addr\" placeholder=\"address\" size=\"12\"> <button id=\"go\">Go</button> &nbsp; \
+             <input id=\"find\" placeholder=\"hex bytes or text\"> <button id=\"search\">Find</button> \
+             <button id=\"again\">Next</button> <span id=\"status\"></span></p>\n\
+             <div id=\"hex\"></div>"
+        )?;
+        let blobs = ranges.iter().map(|&range| {
+            let data = data_in(hex_file, range)
+                .iter()
+                .flat_map(|d| d.bytes().copied());
+            Json::object([
+                ("start", range.start.into()),
+                ("data", Json::Str(base64(data))),
+            ])
+        });
+        writeln!(
+            w,
+            "<script>\nconst RANGES = {};",
+            Json::Array(blobs.collect())
+        )?;
+        writeln!(w, "{}</script>", SCRIPT)?;
+    }
+    writeln!(
+        w,
+        "<p class=\"gap\">Generated by hex-reader {}</p>\n</body>\n</html>",
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The records of `hex_file` inside `range`, which holds whole ones.
+fn data_in(hex_file: &HexFile, range: AddrRange) -> &[Data] {
+    let data = hex_file.data();
+    let first = data.partition_point(|d| d.addr() < range.start);
+    let end = data.partition_point(|d| d.addr() <= range.end);
+    &data[first..end]
+}
+
+/// Standard base64 with padding.
+fn base64(bytes: impl IntoIterator<Item = u8>) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    let mut bytes = bytes.into_iter();
+    loop {
+        let group = [bytes.next(), bytes.next(), bytes.next()];
+        let Some(first) = group[0] else {
+            return out;
+        };
+        let n = (first as u32) << 16
+            | (group[1].unwrap_or(0) as u32) << 8
+            | group[2].unwrap_or(0) as u32;
+        let present = group.iter().flatten().count();
+        for i in 0..4 {
+            out.push(if i <= present {
+                ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char
+            } else {
+                '='
+            });
+        }
+        if present < 3 {
+            return out;
+        }
+    }
+}
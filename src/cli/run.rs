@@ -0,0 +1,2432 @@
+use color_eyre::eyre::{self, Context};
+use eyre::eyre;
+use hex_reader::format::FormatProvider;
+use hex_reader::json::Json;
+use hex_reader::memory_map::MemoryMap;
+use hex_reader::{
+    cache, checksum, chips, config, doctor, eeprom, elf, explain, fields, flash, format, hex,
+    labels, lazy, provenance, svd, symbols, trace32, vcdiff, AddrRangeSet,
+};
+use itertools::Itertools;
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use super::output::Outputs;
+use super::{
+    num_decode, AddrRangesCommand, AlignCommand, ApplyDeltaCommand, Args, CacheCommand,
+    CacheSubcommands, ChecksumCommand, ColorChoice, Command, CrcPatchCommand, CropCommand,
+    DeltaFormat, DiffCommand, DoctorCommand, DumpCommand, Endian, EntropyCommand, EraseCommand,
+    EstimateCommand, ExplainCommand, FillCommand, FindCommand, GapFill, InfoCommand,
+    MakeDeltaCommand, MapCommand, MergeCommand, NormalizeCommand, PatchCommand, PrintRangeCommand,
+    ReportCommand, SimulateFlashCommand, SplitCommand, StringsCommand, SwapCommand, ToElfCommand,
+    ToHexCommand, TransposeCommand, UsageCommand, View, XformCommand,
+};
+
+/// Deltas are computed over flattened images, with gaps filled with the
+/// erased-flash value.
+const DELTA_FILL: u8 = 0xFF;
+
+/// Prefix of the VCDIFF application header recording the image's address.
+const DELTA_BASE_HEADER: &str = "hex-reader base=";
+
+/// Bytes of the image `dump --lazy` decodes at once.
+const LAZY_DUMP_STEP: u64 = 0x10_0000;
+
+/// Stands in for stdin where an input file is expected, given as `-` on the
+/// command line.
+pub(super) const STDIN: &str = "<stdin>";
+
+/// Writes `fill` for every address in `gap`, failing without one.
+fn write_gap(w: &mut impl io::Write, gap: hex::AddrRange, fill: Option<u8>) -> eyre::Result<()> {
+    let Some(fill) = fill else {
+        return Err(eyre!("0x{:08x} isn't in the image", gap.start));
+    };
+    let block = [fill; 4096];
+    let mut left = gap.size();
+    while left > 0 {
+        let n = left.min(block.len() as u64) as usize;
+        w.write_all(&block[..n])?;
+        left -= n as u64;
+    }
+    Ok(())
+}
+
+/// Runs the command given by `args`.
+pub(crate) fn run(args: Args) -> eyre::Result<()> {
+    if args.watch {
+        if args.filename == STDIN || args.more.iter().any(|f| f == STDIN) {
+            return Err(eyre!("--watch needs input files, not stdin"));
+        }
+        if matches!(args.sub, Command::Shell(_) | Command::Repl(_)) {
+            return Err(eyre!("--watch doesn't apply to the shell or repl"));
+        }
+        let inputs = std::iter::once(args.filename)
+            .chain(args.more)
+            .map(PathBuf::from)
+            .collect::<Vec<_>>();
+        return super::watch::run(&inputs);
+    }
+    run_with(args, None)
+}
+
+/// Runs the command given by `args`, taking the input image from `cached`
+/// instead of reading it again if given.
+pub(super) fn run_with(args: Args, cached: Option<&hex::HexFile>) -> eyre::Result<()> {
+    let opts = ParseOptions {
+        lenient: args.lenient,
+        allow_missing_eof: args.allow_missing_eof,
+        trailing: match (args.ignore_trailing, args.concatenated, args.multi) {
+            (false, false, _) => hex::TrailingPolicy::Error,
+            (true, false, false) => hex::TrailingPolicy::Ignore,
+            (false, true, false) => hex::TrailingPolicy::Concatenated,
+            _ => {
+                return Err(eyre!(
+                    "Only one of --ignore-trailing, --concatenated and --multi can be used"
+                ))
+            }
+        },
+        multi: args.multi,
+        // A dry run writes no cache either
+        sidecar: args.cache && !args.dry_run,
+        compress: args.compress,
+        lazy: args.lazy,
+    };
+    if opts.lazy {
+        if opts.compress {
+            return Err(eyre!(
+                "--lazy and --compress are alternatives, give only one"
+            ));
+        }
+        match args.sub {
+            Command::Info(_)
+            | Command::PrintRange(_)
+            | Command::Dump(_)
+            | Command::AddressRanges(_)
+            | Command::Map(_)
+            | Command::Entry(_) => {}
+            _ => {
+                return Err(eyre!(
+                    "--lazy only applies to info, print, dump, ranges, map and entry"
+                ))
+            }
+        }
+    }
+    // Checked up front so that no output file gets truncated first
+    if args.record_len == Some(0) {
+        return Err(eyre!("--record-len must be at least 1"));
+    }
+    let write_opts = hex::WriteOptions {
+        record_len: args.record_len,
+        lowercase: args.lowercase,
+        line_ending: args.line_ending,
+        start_addr: !args.no_start_addr,
+    };
+    let svd = match &args.svd {
+        Some(path) => Some(svd::Device::load(Path::new(path))?),
+        None => None,
+    };
+    let outputs = Outputs {
+        dry_run: args.dry_run,
+        yes: args.yes,
+    };
+    let provenance = if args.provenance {
+        // An input that can't be read fails later, with a better error
+        let mut provenance = read_input(&args.filename)
+            .map(|text| provenance::Provenance::parse(&text))
+            .unwrap_or_default();
+        // Named as installed rather than by the path it was run from
+        provenance.ops.push(
+            std::iter::once("hex-reader".to_string())
+                .chain(std::env::args().skip(1))
+                .map(|arg| {
+                    if arg.contains(char::is_whitespace) {
+                        format!("{:?}", arg)
+                    } else {
+                        arg
+                    }
+                })
+                .join(" "),
+        );
+        Some(provenance)
+    } else {
+        None
+    };
+    let mut profile = match &args.profile {
+        Some(name) => config::Config::load(args.config.as_deref())?
+            .profile(name)?
+            .clone(),
+        None => config::Profile::default(),
+    };
+    if let Some(part) = &args.chip {
+        let chip = chips::find(part)?.profile();
+        profile.memory_map = chip.memory_map;
+        profile.machine = chip.machine.or(profile.machine);
+        profile.page_size = chip.page_size.or(profile.page_size);
+    }
+    if let Some(path) = &args.memory_map {
+        profile.memory_map = MemoryMap::load(Path::new(path))?;
+    }
+    let eeprom_offset = args.eeprom_offset.or(profile.eeprom_offset).or_else(|| {
+        (profile.machine == Some(elf::Machine::Avr)).then_some(eeprom::AVR_EEPROM_OFFSET)
+    });
+    let eeprom = eeprom_offset
+        .map(|offset| {
+            eeprom::EepromSpace::new(
+                offset,
+                profile.eeprom_size.unwrap_or(eeprom::DEFAULT_EEPROM_SIZE),
+            )
+        })
+        .transpose()?;
+    let region = match &args.region {
+        Some(name) => {
+            match args.sub {
+                Command::PrintRange(_)
+                | Command::Dump(_)
+                | Command::Checksum(_)
+                | Command::Diff(_) => {}
+                _ => {
+                    return Err(eyre!(
+                        "--region only applies to print, dump, checksum and diff"
+                    ))
+                }
+            }
+            Some(profile.memory_map.region(name)?.range)
+        }
+        None => None,
+    };
+    let selection = if args.only.is_empty() && args.except.is_empty() {
+        None
+    } else {
+        match args.sub {
+            Command::Fill(_) | Command::Align(_) | Command::Swap(_) | Command::Xform(_) => {}
+            _ => {
+                return Err(eyre!(
+                    "--only and --except only apply to fill, align, swap and xform"
+                ))
+            }
+        }
+        let only = if args.only.is_empty() {
+            AddrRangeSet::from(hex::AddrRange {
+                start: 0,
+                end: u32::MAX,
+            })
+        } else {
+            args.only.iter().copied().collect()
+        };
+        Some(only.difference(&args.except.iter().copied().collect()))
+    };
+    if !args.more.is_empty() && matches!(args.sub, Command::Explain(_) | Command::Doctor(_)) {
+        return Err(eyre!("explain and doctor look at a single file"));
+    }
+
+    let mut session = Session {
+        filename: args.filename,
+        more: args.more,
+        json: args.json,
+        rebase: args.rebase,
+        labels: args.labels,
+        region_name: args.region,
+        opts,
+        cached,
+        profile,
+        outputs,
+        write_opts,
+        provenance,
+        svd,
+        eeprom,
+        region,
+        selection,
+        warnings: Vec::new(),
+    };
+    match args.sub {
+        Command::Cache(cmd) => session.cache(cmd)?,
+        Command::Formats(_) => session.list_formats(),
+        Command::Shell(_) => return session.shell(),
+        Command::Repl(_) => return session.repl(),
+        Command::PrettyPrint(_) => session.pretty_print()?,
+        Command::Usage(cmd) => session.usage(cmd)?,
+        Command::Report(cmd) => session.report(cmd)?,
+        Command::Map(cmd) => session.map(cmd)?,
+        Command::AddressRanges(cmd) => session.address_ranges(cmd)?,
+        Command::PrintRange(cmd) => session.print_range(cmd)?,
+        Command::Dump(cmd) => session.dump(cmd)?,
+        Command::ToElf(cmd) => session.convert_to_elf(cmd)?,
+        Command::Entry(_) => session.entry()?,
+        Command::Transpose(cmd) => session.transpose(cmd)?,
+        Command::Diff(cmd) => session.diff(cmd)?,
+        Command::ToHex(cmd) => session.convert_to_hex(cmd)?,
+        Command::MakeDelta(cmd) => session.make_delta(cmd)?,
+        Command::ApplyDelta(cmd) => session.apply_delta(cmd)?,
+        Command::Merge(cmd) => session.merge(cmd)?,
+        Command::Patch(cmd) => session.patch(cmd)?,
+        Command::CrcPatch(cmd) => session.crc_patch(cmd)?,
+        Command::Swap(cmd) => session.swap(cmd)?,
+        Command::Xform(cmd) => session.xform(cmd)?,
+        Command::Align(cmd) => session.align(cmd)?,
+        Command::Normalize(cmd) => session.normalize(cmd)?,
+        Command::Explain(cmd) => session.explain(cmd)?,
+        Command::Doctor(cmd) => session.doctor(cmd)?,
+        Command::Estimate(cmd) => session.estimate(cmd)?,
+        Command::Fill(cmd) => session.fill(cmd)?,
+        Command::Crop(cmd) => session.crop(cmd)?,
+        Command::Erase(cmd) => session.erase(cmd)?,
+        Command::Split(cmd) => session.split(cmd)?,
+        Command::Find(cmd) => session.find(cmd)?,
+        Command::Strings(cmd) => session.strings(cmd)?,
+        Command::SimulateFlash(cmd) => session.simulate_flash(cmd)?,
+        Command::Entropy(cmd) => session.entropy(cmd)?,
+        Command::Info(cmd) => session.info(cmd)?,
+        Command::Checksum(cmd) => session.checksum(cmd)?,
+    }
+
+    print_warnings(&session.warnings);
+    Ok(())
+}
+
+/// The input and options a command runs with, and the warnings collected
+/// while reading the input.
+struct Session<'a> {
+    filename: String,
+    more: Vec<String>,
+    json: bool,
+    rebase: Option<u32>,
+    labels: Option<String>,
+    /// The `--region` given, which [`Session::region`] is the range of.
+    region_name: Option<String>,
+    opts: ParseOptions,
+    cached: Option<&'a hex::HexFile>,
+    profile: config::Profile,
+    outputs: Outputs,
+    write_opts: hex::WriteOptions,
+    provenance: Option<provenance::Provenance>,
+    svd: Option<svd::Device>,
+    eeprom: Option<eeprom::EepromSpace>,
+    region: Option<hex::AddrRange>,
+    /// Where `--only` and `--except` allow transformations.
+    selection: Option<AddrRangeSet>,
+    warnings: Vec<(String, hex::ParseError)>,
+}
+
+impl Session<'_> {
+    /// The input image, as cached or read afresh and combined with any more
+    /// inputs.
+    fn load_input(&mut self) -> eyre::Result<hex::HexFile> {
+        if let Some(hex_file) = self.cached {
+            return Ok(hex_file.clone());
+        }
+        let mut hex_file = load_hex_file(&self.filename, self.opts, &mut self.warnings)?;
+        for input in &self.more {
+            let other = load_hex_file(input, self.opts, &mut self.warnings)?;
+            hex_file
+                .merge(&other, hex::OverlapPolicy::Error)
+                .with_context(|| format!("Combining {} with {}", input, self.filename))?;
+        }
+        Ok(hex_file)
+    }
+
+    /// The input image narrowed down to `--region`.
+    fn load_scoped(&mut self) -> eyre::Result<hex::HexFile> {
+        let hex_file = self.load_input()?;
+        Ok(self.scoped(hex_file))
+    }
+
+    /// Whether the input has to be taken from [`Session::load_input`] rather
+    /// than read record by record.
+    fn in_memory(&self) -> bool {
+        self.cached.is_some() || !self.more.is_empty() || self.opts.sidecar
+    }
+
+    /// Narrows an image down to `--region`, if one was given.
+    fn scoped(&self, mut hex_file: hex::HexFile) -> hex::HexFile {
+        if let Some(region) = self.region {
+            hex_file.crop(&[region]);
+        }
+        hex_file
+    }
+
+    fn scoped_ranges(&self, ranges: Vec<hex::AddrRange>) -> Vec<hex::AddrRange> {
+        match self.region {
+            Some(region) => ranges
+                .iter()
+                .filter_map(|r| r.intersection(region))
+                .collect(),
+            None => ranges,
+        }
+    }
+
+    /// The address ranges of the input, without holding its data if it can
+    /// be read record by record.
+    fn load_ranges(&mut self) -> eyre::Result<Vec<hex::AddrRange>> {
+        if self.in_memory() || !is_hex_input(&self.filename) || self.opts.multi {
+            return Ok(self.load_input()?.address_ranges());
+        }
+        let mut ctx = open_hex_file(&self.filename, self.opts)?;
+        let ranges = ctx.address_ranges()?;
+        collect_warnings(&self.filename, ctx, &mut self.warnings);
+        Ok(ranges)
+    }
+
+    /// The input indexed for `--lazy`, unless it has to be loaded whole
+    /// anyway.
+    fn lazy_input(&self) -> eyre::Result<Option<lazy::LazyHexFile<BufReader<File>>>> {
+        if !self.opts.lazy
+            || self.in_memory()
+            || self.opts.multi
+            || self.filename == STDIN
+            || !is_hex_input(&self.filename)
+        {
+            return Ok(None);
+        }
+        let file =
+            File::open(&self.filename).with_context(|| format!("Opening {}", self.filename))?;
+        lazy::LazyHexFile::index(hex_context(BufReader::new(file), self.opts))
+            .with_context(|| format!("Parsing {}", self.filename))
+            .map(Some)
+    }
+
+    /// Runs a transformation on the whole image, or only inside `--only` and
+    /// outside `--except` when they were given.
+    fn transform(
+        &self,
+        hex_file: &mut hex::HexFile,
+        f: &dyn Fn(&mut hex::HexFile) -> eyre::Result<()>,
+    ) -> eyre::Result<()> {
+        match &self.selection {
+            Some(selection) => hex_file.transform_within(selection, f),
+            None => f(hex_file),
+        }
+    }
+
+    /// The input for the shell or repl, which take their commands from
+    /// stdin.
+    fn interactive_input(&mut self) -> eyre::Result<hex::HexFile> {
+        if self.cached.is_some() {
+            return Err(eyre!("Already in a shell"));
+        }
+        if self.filename == STDIN {
+            return Err(eyre!(
+                "The shell reads commands from stdin, so can't read the image from it"
+            ));
+        }
+        let hex_file = self.load_input()?;
+        print_warnings(&self.warnings);
+        Ok(hex_file)
+    }
+
+    fn shell(mut self) -> eyre::Result<()> {
+        let hex_file = self.interactive_input()?;
+        super::shell::run(&self.filename, &hex_file)
+    }
+
+    fn repl(mut self) -> eyre::Result<()> {
+        let hex_file = self.interactive_input()?;
+        super::repl::run(
+            &self.filename,
+            self.labels.as_deref(),
+            hex_file,
+            &self.write_opts,
+        )
+    }
+
+    fn cache(&mut self, cmd: CacheCommand) -> eyre::Result<()> {
+        match cmd.sub {
+            CacheSubcommands::Save(cmd) => {
+                let hex_file = self.load_input()?;
+                // A cache given as input later is used no matter its source
+                let fingerprint = if self.more.is_empty() && self.filename != STDIN {
+                    source_fingerprint(&self.filename, self.opts)?
+                } else {
+                    Vec::new()
+                };
+                let mut file = self.outputs.create(&cmd.path)?;
+                cache::write(io::BufWriter::new(&mut file), &hex_file, &fingerprint)
+                    .with_context(|| format!("Writing {}", cmd.path))?;
+                file.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn list_formats(&self) {
+        let detected = detect_format(&self.filename).map(|f| f.name());
+        if self.json {
+            let formats = formats().iter().map(|f| {
+                Json::object([
+                    ("name", f.name().into()),
+                    ("description", f.description().into()),
+                    (
+                        "extensions",
+                        Json::Array(f.extensions().iter().map(|&e| e.into()).collect()),
+                    ),
+                    ("write", f.can_write().into()),
+                    ("detected", (detected == Some(f.name())).into()),
+                ])
+            });
+            println!("{}", Json::Array(formats.collect()));
+        } else {
+            for f in formats().iter() {
+                println!(
+                    "{} {:<10}{:<18}{:<12}{}",
+                    if detected == Some(f.name()) { '*' } else { ' ' },
+                    f.name(),
+                    f.extensions().join(", "),
+                    if f.can_write() { "read/write" } else { "read" },
+                    f.description()
+                );
+            }
+        }
+    }
+
+    fn pretty_print(&mut self) -> eyre::Result<()> {
+        if self.in_memory() || !is_hex_input(&self.filename) {
+            self.load_input()?.pretty_print(io::stdout().lock())?;
+        } else {
+            let mut ctx = open_hex_file(&self.filename, self.opts)?;
+            ctx.pretty_print(io::stdout().lock())?;
+            collect_warnings(&self.filename, ctx, &mut self.warnings);
+        }
+        Ok(())
+    }
+
+    fn usage(&mut self, cmd: UsageCommand) -> eyre::Result<()> {
+        let ranges = self.load_ranges()?;
+        let memories = self.profile.memory_map.regions.iter().chain(&cmd.memory);
+        if memories.clone().next().is_none() {
+            return Err(eyre!(
+                "No memories to report on, select a profile defining regions with --profile, \
+                 give a --memory-map or give them with --memory"
+            ));
+        }
+        let memory_ranges = memories.clone().map(|m| m.range).collect::<Vec<_>>();
+        let outside = ranges
+            .iter()
+            .flat_map(|r| r.subtract(&memory_ranges))
+            .collect::<Vec<_>>();
+        // Bytes of the image inside `range`
+        let used_in = |range: hex::AddrRange| -> u64 {
+            ranges
+                .iter()
+                .filter_map(|r| r.intersection(range))
+                .map(|r| r.size())
+                .sum()
+        };
+        if self.json {
+            let memories = memories
+                .map(|m| {
+                    let used = used_in(m.range);
+                    Json::object([
+                        ("name", m.name.as_str().into()),
+                        ("start", m.range.start.into()),
+                        ("end", m.range.end.into()),
+                        ("size", m.range.size().into()),
+                        ("used", used.into()),
+                        ("free", (m.range.size() - used).into()),
+                    ])
+                })
+                .collect();
+            let outside = outside.iter().map(|&r| range_json(r)).collect();
+            println!(
+                "{}",
+                Json::object([
+                    ("memories", Json::Array(memories)),
+                    ("outside", Json::Array(outside)),
+                ])
+            );
+        } else {
+            let name_width = memories
+                .clone()
+                .map(|m| m.name.len())
+                .fold("Memory".len(), usize::max);
+            println!(
+                "{:<name_width$}  {:>12}  {:>12}  {:>6}  {:>12}  Range",
+                "Memory", "Used", "Size", "Used%", "Free"
+            );
+            for memory in memories {
+                let (used, size) = (used_in(memory.range), memory.range.size());
+                println!(
+                    "{:<name_width$}  {:>12}  {:>12}  {:>5.1}%  {:>12}  {}",
+                    memory.name,
+                    size_text(used, cmd.bytes),
+                    size_text(size, cmd.bytes),
+                    used as f64 * 100.0 / size as f64,
+                    size_text(size - used, cmd.bytes),
+                    memory.range
+                );
+            }
+            for range in &outside {
+                eprintln!(
+                    "Warning: {} ({}) lies outside every memory",
+                    range,
+                    size_text(range.size(), cmd.bytes)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn report(&mut self, cmd: ReportCommand) -> eyre::Result<()> {
+        if cmd.window == 0 {
+            return Err(eyre!("Block size must not be 0"));
+        }
+        let hex_file = self.load_input()?;
+        let title = std::iter::once(&self.filename).chain(&self.more).join(", ");
+        let report_opts = super::report::ReportOptions {
+            title: &title,
+            regions: &self.profile.memory_map.regions,
+            window: cmd.window as usize,
+            data: !cmd.no_data,
+        };
+        let mut file = self.outputs.create(&cmd.path)?;
+        let mut writer = io::BufWriter::new(&mut file);
+        super::report::render(&mut writer, &hex_file, &report_opts)?;
+        io::Write::flush(&mut writer)?;
+        drop(writer);
+        file.finish()?;
+        Ok(())
+    }
+
+    fn map(&mut self, cmd: MapCommand) -> eyre::Result<()> {
+        if cmd.width == 0 {
+            return Err(eyre!("--width must be at least 1"));
+        }
+        let ranges = self.load_ranges()?;
+        let regions = &self.profile.memory_map.regions;
+        // Everything there is to see: the data and the regions it should
+        // sit in
+        let span = cmd.range.or_else(|| {
+            let starts = ranges.iter().chain(regions.iter().map(|r| &r.range));
+            let ends = starts.clone();
+            Some(hex::AddrRange {
+                start: starts.map(|r| r.start).min()?,
+                end: ends.map(|r| r.end).max()?,
+            })
+        });
+        let Some(span) = span else {
+            return Err(eyre!("{} holds no data", self.filename));
+        };
+        let map_opts = super::map::MapOptions {
+            width: cmd.width,
+            bytes: cmd.bytes,
+        };
+        super::map::render(io::stdout().lock(), &ranges, regions, span, &map_opts)?;
+        Ok(())
+    }
+
+    fn address_ranges(&mut self, cmd: AddrRangesCommand) -> eyre::Result<()> {
+        let ranges = self.load_ranges()?;
+        let shift = display_offset(self.rebase, &ranges);
+        let (ranges, eeprom_ranges) = match self.eeprom {
+            Some(eeprom) => eeprom.partition(ranges),
+            None => (ranges, Vec::new()),
+        };
+        if self.json {
+            let mut items = Vec::new();
+            for &range in &ranges {
+                let mut item = range_json(rebased(range, shift));
+                if self.eeprom.is_some() {
+                    item.push("eeprom", false.into());
+                }
+                if let Some(svd) = &self.svd {
+                    let names = svd
+                        .peripherals_in(range)
+                        .map(|p| p.name.as_str().into())
+                        .collect();
+                    item.push("peripherals", Json::Array(names));
+                }
+                items.push(item);
+            }
+            if let Some(eeprom) = self.eeprom {
+                for &range in &eeprom_ranges {
+                    let mut item = range_json(rebased(range, shift));
+                    item.push("eeprom", true.into());
+                    item.push("offset", (range.start - eeprom.range.start).into());
+                    items.push(item);
+                }
+            }
+            println!("{}", Json::Array(items));
+        } else {
+            println!("Address Ranges:");
+            for range in ranges {
+                let peripherals = self
+                    .svd
+                    .iter()
+                    .flat_map(|svd| svd.peripherals_in(range))
+                    .map(|p| p.name.as_str())
+                    .join(", ");
+                println!(
+                    "    0x{:08x}-0x{:08x} (Size = 0x{:x}, {}){}",
+                    range.start.wrapping_add(shift),
+                    range.end.wrapping_add(shift),
+                    range.size(),
+                    size_text(range.size(), cmd.bytes),
+                    if peripherals.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" in {}", peripherals)
+                    }
+                );
+            }
+            if let (Some(eeprom), false) = (self.eeprom, eeprom_ranges.is_empty()) {
+                println!("EEPROM Ranges:");
+                for range in eeprom_ranges {
+                    println!(
+                        "    0x{:08x}-0x{:08x} (Offset = 0x{:04x}, Size = 0x{:x}, {})",
+                        range.start.wrapping_add(shift),
+                        range.end.wrapping_add(shift),
+                        range.start - eeprom.range.start,
+                        range.size(),
+                        size_text(range.size(), cmd.bytes)
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn print_range(&mut self, cmd: PrintRangeCommand) -> eyre::Result<()> {
+        // With --lazy, each window is decoded only as it's printed
+        let mut lazy = self.lazy_input()?;
+        let hex_file = match lazy {
+            Some(_) => hex::HexFile::new(),
+            None => self.load_scoped()?,
+        };
+        let offset = match &cmd.at {
+            Some(at) => resolve_location(at, &self.filename, self.labels.as_deref())?,
+            None => cmd.offset.unwrap_or(0),
+        };
+        // Where each window asked for starts and how long it is, if
+        // it doesn't run to the end of the data
+        let requests = if cmd.range.is_empty() {
+            let len = match (cmd.len, cmd.end) {
+                (Some(_), Some(_)) => return Err(eyre!("Give at most one of --len and --end")),
+                (None, Some(end)) if end < offset => {
+                    return Err(eyre!(
+                        "--end 0x{:08x} lies before the start at 0x{:08x}",
+                        end,
+                        offset
+                    ))
+                }
+                (None, Some(end)) => (end - offset).checked_add(1),
+                (len, None) => len,
+            };
+            vec![(offset, len)]
+        } else {
+            if cmd.at.is_some() || cmd.offset.is_some() || cmd.len.is_some() || cmd.end.is_some() {
+                return Err(eyre!(
+                    "--range can't be combined with --offset, --at, --len or --end"
+                ));
+            }
+            cmd.range
+                .iter()
+                .map(|r| (r.start, (r.end - r.start).checked_add(1)))
+                .collect()
+        };
+        let ranges = match &lazy {
+            Some(lazy) => self.scoped_ranges(lazy.address_ranges()),
+            None => hex_file.address_ranges(),
+        };
+        let print_opts = hex::PrintOptions {
+            cluster: cmd.cluster,
+            pad: !cmd.no_pad,
+            order: cmd.endian,
+            ascii: cmd.ascii,
+            addr_offset: display_offset(self.rebase, &ranges),
+            value: cmd.as_type,
+            fold: !cmd.no_fold,
+            color: match cmd.color {
+                ColorChoice::Always => true,
+                ColorChoice::Never => false,
+                ColorChoice::Auto => {
+                    io::stdout().is_terminal()
+                        && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                }
+            },
+            gap_fill: match cmd.gap_fill {
+                Some(GapFill::Byte(b)) => Some(b),
+                _ => None,
+            },
+        };
+        if cmd.as_type.is_some() && cmd.view != View::Bytes {
+            return Err(eyre!("--as only applies to the bytes view"));
+        }
+        let cluster = cmd.as_type.map_or(cmd.cluster, |v| v.size());
+        // The windows to print, each with the range shown above it
+        let mut windows = Vec::new();
+        for (offset, len) in requests {
+            if cmd.gap_fill.is_some() {
+                let (Some(first), Some(last)) = (ranges.first(), ranges.last()) else {
+                    return Err(eyre!("{} holds no data", self.filename));
+                };
+                let start = offset.max(first.start);
+                let end = match len {
+                    Some(len) => len
+                        .checked_sub(1)
+                        .and_then(|len| start.checked_add(len))
+                        .ok_or_else(|| {
+                            eyre!("Invalid window of 0x{:x} bytes at 0x{:08x}", len, start)
+                        })?,
+                    None if start <= last.end => last.end,
+                    None => return Err(eyre!("No data at or after 0x{:08x}", start)),
+                };
+                windows.push((hex::AddrRange { start, end }, start, end));
+            } else {
+                let mut rem_len = len;
+                for &range in &ranges {
+                    if let Some(0) = rem_len {
+                        break;
+                    }
+                    if range.is_before(offset) {
+                        continue;
+                    }
+
+                    let start = offset.max(range.start);
+                    let end = if let Some(rem) = rem_len {
+                        range.end.min(start + rem - 1)
+                    } else {
+                        range.end
+                    };
+                    windows.push((range, start, end));
+                    rem_len = rem_len.map(|l| l - (end + 1 - start));
+                }
+            }
+        }
+        for (range, start, end) in windows {
+            let decoded;
+            let hex_file = match &mut lazy {
+                Some(lazy) => {
+                    decoded = self.scoped(lazy.extract(hex::AddrRange { start, end })?);
+                    &decoded
+                }
+                None => &hex_file,
+            };
+            let shown = rebased(range, print_opts.addr_offset);
+            println!("\n\n[0x{:08x} - 0x{:08x}]", shown.start, shown.end);
+            if let Some(svd) = &self.svd {
+                for (peripheral, register) in svd.registers_in(hex::AddrRange { start, end }) {
+                    // Little-endian, and only if the image sets every byte
+                    let bytes = register.range();
+                    let value = (bytes.start..=bytes.end)
+                        .rev()
+                        .try_fold(0u64, |value, addr| {
+                            let byte = hex_file.data_containing(addr)?.get_byte(addr)?;
+                            Some(value << 8 | byte as u64)
+                        });
+                    println!(
+                        "    ; 0x{:08x}  {}.{}{}",
+                        register.addr.wrapping_add(print_opts.addr_offset),
+                        peripheral.name,
+                        register.name,
+                        value.map_or(String::new(), |v| format!(
+                            " = 0x{:0width$x}",
+                            v,
+                            width = register.size as usize * 2
+                        ))
+                    );
+                }
+            }
+            match cmd.view {
+                View::Bytes => {
+                    let len = (end - start) as u64 + 1;
+                    if !cmd.no_pad && cluster != 0 && !len.is_multiple_of(cluster as u64) {
+                        eprintln!(
+                            "Warning: cluster size {} doesn't divide the {} bytes at 0x{:08x}, \
+                             the last cluster is zero-padded (use --no-pad to show only the bytes present)",
+                            cluster,
+                            len,
+                            start.wrapping_add(print_opts.addr_offset)
+                        );
+                    }
+                    hex_file.print_bytes(io::stdout().lock(), start, end, &print_opts)?;
+                    println!();
+                }
+                View::Words32 => {
+                    hex_file.print_words32(io::stdout().lock(), start, end, &print_opts)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn dump(&mut self, cmd: DumpCommand) -> eyre::Result<()> {
+        use std::io::Write;
+
+        let filename = match (&cmd.filename, cmd.stdout) {
+            (Some(_), true) => {
+                return Err(eyre!("Give either a file to dump to or --stdout, not both"))
+            }
+            (None, false) => return Err(eyre!("Give a file to dump to, or --stdout")),
+            (_, true) if cmd.resume => return Err(eyre!("Can't resume a dump to stdout")),
+            (Some(filename), false) => filename.as_str(),
+            (None, true) => "stdout",
+        };
+
+        // With --lazy, the dump is decoded a step at a time as it's written
+        let mut lazy = self.lazy_input()?;
+        let hex_file = match lazy {
+            Some(_) => hex::HexFile::new(),
+            None => self.load_scoped()?,
+        };
+        let ranges = match &lazy {
+            Some(lazy) => self.scoped_ranges(lazy.address_ranges()),
+            None => hex_file.address_ranges(),
+        };
+        let start = match (cmd.offset, self.region) {
+            (Some(offset), _) => offset,
+            (None, Some(region)) => ranges.first().map_or(region.start, |r| r.start),
+            (None, None) => 0,
+        };
+        let end = if cmd.fill.is_some() {
+            match cmd.len {
+                Some(len) => len
+                    .checked_sub(1)
+                    .and_then(|l| start.checked_add(l))
+                    .ok_or_else(|| eyre!("Can't dump {} bytes from 0x{:08x}", len, start))?,
+                None => match ranges.last() {
+                    Some(last) if last.end >= start => last.end,
+                    _ => return Err(eyre!("No data at or after 0x{:08x}", start)),
+                },
+            }
+        } else {
+            let Some(range) = ranges.iter().find(|r| r.contains(start)) else {
+                return Err(eyre!(
+                    "0x{:08x} doesn't belong to any address range (use --fill to dump across gaps)",
+                    start
+                ));
+            };
+            match cmd.len {
+                Some(len) => {
+                    let end = start + len - 1;
+                    if end > range.end {
+                        return Err(eyre!(
+                            "Length {} is causing end to go out of address range [0x{:08x} - 0x{:08x}] at 0x{:08x} (use --fill to dump across gaps)",
+                            len,
+                            range.start,
+                            range.end,
+                            end
+                        ));
+                    }
+                    end
+                }
+                None => range.end,
+            }
+        };
+
+        let written = if cmd.resume {
+            std::fs::metadata(filename).map_or(0, |m| m.len())
+        } else {
+            0
+        };
+        let total = (end - start) as u64 + 1;
+        if written > total {
+            return Err(eyre!(
+                "{} is larger than the requested dump ({} > {} bytes), refusing to resume",
+                filename,
+                written,
+                total
+            ));
+        }
+        if written == total {
+            println!("{} is already complete", filename);
+        } else {
+            let start = start + written as u32;
+
+            let mut output = match cmd.stdout {
+                true => None,
+                false => Some(self.outputs.open(filename, cmd.resume)?),
+            };
+            let file: Box<dyn Write> = match &mut output {
+                Some(output) => Box::new(output),
+                None => Box::new(io::stdout().lock()),
+            };
+            let mut file = io::BufWriter::new(file);
+            // Next address to write, wider than an address so it can
+            // step past the end of the address space
+            let mut next = start as u64;
+            let mut step_start = start as u64;
+            while step_start <= end as u64 {
+                let step_end = match lazy {
+                    Some(_) => (step_start + LAZY_DUMP_STEP - 1).min(end as u64),
+                    None => end as u64,
+                };
+                let decoded;
+                let hex_file = match &mut lazy {
+                    Some(lazy) => {
+                        let step = hex::AddrRange {
+                            start: step_start as u32,
+                            end: step_end as u32,
+                        };
+                        decoded = self.scoped(lazy.extract(step)?);
+                        &decoded
+                    }
+                    None => &hex_file,
+                };
+                for d in hex_file.data() {
+                    let range = d.addr_range();
+                    if (range.end as u64) < next {
+                        continue;
+                    }
+                    if range.start > end {
+                        break;
+                    }
+                    if (range.start as u64) > next {
+                        let gap = hex::AddrRange {
+                            start: next as u32,
+                            end: range.start - 1,
+                        };
+                        write_gap(&mut file, gap, cmd.fill)?;
+                        next = range.start as u64;
+                    }
+                    let last = range.end.min(end);
+                    file.write_all(
+                        &d.to_bytes()
+                            [(next - range.start as u64) as usize..=(last - range.start) as usize],
+                    )?;
+                    next = last as u64 + 1;
+                }
+                step_start = step_end + 1;
+            }
+            if next <= end as u64 {
+                let gap = hex::AddrRange {
+                    start: next as u32,
+                    end,
+                };
+                write_gap(&mut file, gap, cmd.fill)?;
+            }
+            file.flush()?;
+            drop(file);
+            if let Some(output) = output {
+                output.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn convert_to_elf(&mut self, cmd: ToElfCommand) -> eyre::Result<()> {
+        let hex_file = self.load_input()?;
+        let symbols = match &cmd.symbols {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("Reading symbols {}", path))?;
+                symbols::parse(&text, cmd.symbol_format)
+                    .with_context(|| format!("Parsing symbols {}", path))?
+            }
+            None => Vec::new(),
+        };
+        // An explicit --vector-table-end overrides a profile that turns
+        // splitting off
+        let split = !cmd.no_vector_split
+            && (cmd.vector_table_end.is_some() || self.profile.split_vector_table != Some(false));
+        let elf_opts = elf::ElfOptions {
+            machine: cmd
+                .machine
+                .or(self.profile.machine)
+                .unwrap_or(elf::Machine::Arm),
+            flags: cmd.elf_flags.or(self.profile.elf_flags),
+            endian: cmd.endian,
+            data_lma: cmd.data_lma,
+            vector_table_end: split.then(|| {
+                cmd.vector_table_end
+                    .or(self.profile.vector_table_end)
+                    .unwrap_or(elf::DEFAULT_VECTOR_TABLE_END)
+            }),
+            symbols,
+            regions: self.profile.memory_map.regions.clone(),
+        };
+        let elf_data = elf::to_elf(&hex_file, &elf_opts)?;
+        self.outputs.write(&cmd.path, &elf_data)?;
+        Ok(())
+    }
+
+    fn entry(&mut self) -> eyre::Result<()> {
+        let start = if self.in_memory() || !is_hex_input(&self.filename) || self.opts.multi {
+            self.load_input()?.start_addr()
+        } else {
+            let mut ctx = open_hex_file(&self.filename, self.opts)?;
+            let start = ctx.start_addr()?;
+            collect_warnings(&self.filename, ctx, &mut self.warnings);
+            start
+        };
+        if self.json {
+            println!("{}", Json::object([("entry", start.into())]));
+        } else if let Some(start) = start {
+            println!("0x{:08X}", start);
+        } else {
+            println!("No entry point");
+        }
+        Ok(())
+    }
+
+    fn transpose(&mut self, cmd: TransposeCommand) -> eyre::Result<()> {
+        let mut hex_file = self.load_input()?;
+        let policy = match (cmd.force, cmd.merge_policy) {
+            (false, policy) => policy,
+            (true, hex::OverlapPolicy::Error | hex::OverlapPolicy::LastWins) => {
+                hex::OverlapPolicy::LastWins
+            }
+            (true, _) => {
+                return Err(eyre!("--force conflicts with --merge-policy first-wins"));
+            }
+        };
+        // Catches relocating onto option bytes and the like before the
+        // image reaches a device
+        let dest_range = hex_file
+            .address_ranges()
+            .into_iter()
+            .find(|r| r.start == cmd.start)
+            .and_then(|r| r.transpose(cmd.dest));
+        if let Some(dest_range) = dest_range.filter(|_| !cmd.force) {
+            if let Some(region) = self.profile.memory_map.reserved_overlap(dest_range) {
+                return Err(eyre!(
+                    "Destination range {} reaches into {} ({}), which the memory map \
+                     reserves; use --force to move it there anyway",
+                    dest_range,
+                    region.name,
+                    region.range
+                ));
+            }
+        }
+        hex_file.transpose(cmd.start, cmd.dest, !cmd.keep_entry, policy)?;
+        if let Some(dest_range) = dest_range.filter(|_| self.outputs.dry_run) {
+            let src_range = hex::AddrRange {
+                start: cmd.start,
+                end: cmd.start + (dest_range.end - dest_range.start),
+            };
+            println!("Would move {} to {}", src_range, dest_range);
+        }
+        write_hex_file(
+            &self.outputs,
+            &cmd.filename,
+            &hex_file,
+            &self.write_opts,
+            self.provenance.as_ref(),
+        )?;
+        Ok(())
+    }
+
+    fn diff(&mut self, cmd: DiffCommand) -> eyre::Result<()> {
+        let diff_opts = hex::DiffOptions {
+            window: self.region,
+            keep: self
+                .profile
+                .fields
+                .iter()
+                .filter_map(|f| {
+                    let end = f.addr.checked_add(f.len.checked_sub(1)?)?;
+                    Some(hex::AddrRange { start: f.addr, end })
+                })
+                .collect(),
+            count_only: cmd.summary,
+        };
+        let diff = match self.diff_streamed(&cmd.other, &diff_opts)? {
+            Some(diff) => diff,
+            None => {
+                let hex_file = self.load_input()?;
+                let other = load_hex_file(&cmd.other, self.opts, &mut self.warnings)?;
+                hex_file.diff(&other, &diff_opts)
+            }
+        };
+        let (only_left, only_right) = (&diff.left.only_here, &diff.right.only_here);
+        let total = |ranges: &[hex::AddrRange]| ranges.iter().map(|r| r.size()).sum::<u64>();
+        let metadata = metadata_diff(&diff.left, &diff.right, &self.profile.fields);
+        if self.json {
+            let ranges_json = |ranges: &[hex::AddrRange]| {
+                Json::Array(ranges.iter().map(|&r| range_json(r)).collect())
+            };
+            let mut result = Json::object([
+                ("only_in_left", ranges_json(only_left)),
+                ("only_in_right", ranges_json(only_right)),
+                ("differing_bytes", diff.diff_count.into()),
+            ]);
+            if cmd.summary {
+                result = Json::object([
+                    ("only_in_left_bytes", total(only_left).into()),
+                    ("only_in_right_bytes", total(only_right).into()),
+                    ("differing_bytes", diff.diff_count.into()),
+                ]);
+            } else {
+                let diffs = diff.diffs.iter().map(|d| {
+                    Json::object([
+                        ("addr", d.addr.into()),
+                        ("old", (d.left as u32).into()),
+                        ("new", (d.right as u32).into()),
+                    ])
+                });
+                result.push("differences", Json::Array(diffs.collect()));
+            }
+            let metadata = metadata.iter().map(|m| {
+                Json::object([
+                    ("name", m.name.as_str().into()),
+                    ("old", m.old.as_str().into()),
+                    ("new", m.new.as_str().into()),
+                    ("changed", m.changed.into()),
+                ])
+            });
+            result.push("metadata", Json::Array(metadata.collect()));
+            println!("{}", result);
+        } else {
+            for m in &metadata {
+                if m.changed {
+                    println!("{:<12}{} -> {}", m.name, m.old, m.new);
+                } else {
+                    println!("{:<12}{} (unchanged)", m.name, m.old);
+                }
+            }
+            println!();
+            if cmd.summary {
+                for (name, ranges) in [(&self.filename, only_left), (&cmd.other, only_right)] {
+                    println!(
+                        "{} range(s) (0x{:x} bytes) only in {}",
+                        ranges.len(),
+                        total(ranges),
+                        name
+                    );
+                }
+                println!("{} byte(s) differ", diff.diff_count);
+            } else {
+                for (name, ranges) in [(&self.filename, only_left), (&cmd.other, only_right)] {
+                    if !ranges.is_empty() {
+                        println!("Only in {}:", name);
+                        for range in ranges {
+                            println!(
+                                "    0x{:08x}-0x{:08x} (Size = 0x{:x})",
+                                range.start,
+                                range.end,
+                                range.size()
+                            );
+                        }
+                    }
+                }
+                for byte in &diff.diffs {
+                    println!(
+                        "0x{:08x}: {:02x} -> {:02x}",
+                        byte.addr, byte.left, byte.right
+                    );
+                }
+                println!("{} byte(s) differ", diff.diff_count);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compares the input with `other` record by record as both are read,
+    /// or returns `None` if they have to be loaded whole first: when the
+    /// input isn't a single Intel HEX file, or either's records aren't in
+    /// address order.
+    fn diff_streamed(
+        &mut self,
+        other: &str,
+        diff_opts: &hex::DiffOptions,
+    ) -> eyre::Result<Option<hex::RecordDiff>> {
+        if self.in_memory()
+            || self.opts.multi
+            || !is_hex_input(&self.filename)
+            || !is_hex_input(other)
+        {
+            return Ok(None);
+        }
+        let mut left = open_hex_file(&self.filename, self.opts)?;
+        let mut right = open_hex_file(other, self.opts)?;
+        let parsing = |filename: &str| {
+            let filename = filename.to_string();
+            move |record: eyre::Result<hex::Record>| {
+                record.with_context(|| format!("Parsing {}", filename))
+            }
+        };
+        let diff = hex::diff_records(
+            left.records().map(parsing(&self.filename)),
+            right.records().map(parsing(other)),
+            diff_opts,
+        )?;
+        // Loading the images whole reports the same warnings again
+        if diff.is_some() {
+            collect_warnings(&self.filename, left, &mut self.warnings);
+            collect_warnings(other, right, &mut self.warnings);
+        }
+        Ok(diff)
+    }
+    fn convert_to_hex(&mut self, cmd: ToHexCommand) -> eyre::Result<()> {
+        let hex_file = self.load_input()?;
+        write_hex_file(
+            &self.outputs,
+            &cmd.path,
+            &hex_file,
+            &self.write_opts,
+            self.provenance.as_ref(),
+        )?;
+        Ok(())
+    }
+
+    /// Moves the data in the EEPROM space, if one is configured, out of
+    /// `hex_file` and writes it, rebased to 0, to `path` with an `.eep`
+    /// extension. Returns the path written, if there was any EEPROM data.
+    fn write_eep(&self, hex_file: &mut hex::HexFile, path: &str) -> eyre::Result<Option<String>> {
+        let Some(eeprom) = self.eeprom else {
+            return Ok(None);
+        };
+        let mut eep = hex_file.extract(eeprom.range);
+        if eep.data().is_empty() {
+            return Ok(None);
+        }
+        hex_file.erase(&[eeprom.range]);
+        eep.rebase(eeprom.range.start)?;
+        let path = Path::new(path)
+            .with_extension("eep")
+            .to_string_lossy()
+            .into_owned();
+        write_hex_file(
+            &self.outputs,
+            &path,
+            &eep,
+            &self.write_opts,
+            self.provenance.as_ref(),
+        )?;
+        Ok(Some(path))
+    }
+
+    fn make_delta(&mut self, cmd: MakeDeltaCommand) -> eyre::Result<()> {
+        let DeltaFormat::Vcdiff = cmd.format;
+        let old = self.load_input()?;
+        let new = load_hex_file(&cmd.new, self.opts, &mut self.warnings)?;
+        let (_, old) = old.flatten(DELTA_FILL);
+        let (base, new) = new.flatten(DELTA_FILL);
+        let header = format!("{}0x{:08x}", DELTA_BASE_HEADER, base);
+        let delta = vcdiff::encode(&old, &new, Some(header.as_bytes()));
+        self.outputs.write(&cmd.path, &delta)?;
+        println!(
+            "{} bytes -> {} byte delta ({})",
+            new.len(),
+            delta.len(),
+            cmd.path
+        );
+        Ok(())
+    }
+
+    fn apply_delta(&mut self, cmd: ApplyDeltaCommand) -> eyre::Result<()> {
+        let DeltaFormat::Vcdiff = cmd.format;
+        let old = self.load_input()?;
+        let delta = std::fs::read(&cmd.delta).with_context(|| format!("Reading {}", cmd.delta))?;
+        let (_, old) = old.flatten(DELTA_FILL);
+        let decoded = vcdiff::decode(&old, &delta)
+            .with_context(|| format!("Applying delta {}", cmd.delta))?;
+        let base = match cmd.base {
+            Some(base) => base,
+            None => decoded
+                .app_header
+                .as_deref()
+                .and_then(|h| std::str::from_utf8(h).ok())
+                .and_then(|h| h.strip_prefix(DELTA_BASE_HEADER))
+                .and_then(|h| num_decode(h).ok())
+                .ok_or_else(|| eyre!("{} doesn't record an address, use --base", cmd.delta))?,
+        };
+        let mut new = hex::HexFile::new();
+        if !decoded.target.is_empty() {
+            new.add_data(base, decoded.target)?;
+        }
+        write_hex_file(
+            &self.outputs,
+            &cmd.path,
+            &new,
+            &self.write_opts,
+            self.provenance.as_ref(),
+        )?;
+        Ok(())
+    }
+
+    fn merge(&mut self, cmd: MergeCommand) -> eyre::Result<()> {
+        let mut hex_file = self.load_input()?;
+        for input in &cmd.inputs {
+            let other = load_hex_file(input, self.opts, &mut self.warnings)?;
+            hex_file
+                .merge(&other, cmd.overlap)
+                .with_context(|| format!("Merging {}", input))?;
+        }
+        write_hex_file(
+            &self.outputs,
+            &cmd.output,
+            &hex_file,
+            &self.write_opts,
+            self.provenance.as_ref(),
+        )?;
+        Ok(())
+    }
+
+    fn patch(&mut self, cmd: PatchCommand) -> eyre::Result<()> {
+        let mut hex_file = self.load_input()?;
+        let addr = resolve_location(&cmd.addr, &self.filename, self.labels.as_deref())?;
+        let bytes = match (cmd.bytes, &cmd.from) {
+            (Some(bytes), None) => bytes.0,
+            (None, Some(from)) => {
+                std::fs::read(from).with_context(|| format!("Reading {}", from))?
+            }
+            _ => return Err(eyre!("Give exactly one of --bytes and --from")),
+        };
+        hex_file.patch(addr, &bytes, cmd.create)?;
+        write_hex_file(
+            &self.outputs,
+            &cmd.path,
+            &hex_file,
+            &self.write_opts,
+            self.provenance.as_ref(),
+        )?;
+        Ok(())
+    }
+
+    fn crc_patch(&mut self, cmd: CrcPatchCommand) -> eyre::Result<()> {
+        let mut hex_file = self.load_input()?;
+        let addr = resolve_location(&cmd.store_at, &self.filename, self.labels.as_deref())?;
+        let flat = hex_file.flatten_range(cmd.range, cmd.fill);
+        let mut digest = checksum::compute(&[cmd.algo], [flat.as_slice()]).remove(0);
+        let digest_text = hex_string(&digest);
+        if cmd.endian == Endian::Little {
+            digest.reverse();
+        }
+        let store = hex::AddrRange {
+            start: addr,
+            end: addr
+                .checked_add(digest.len() as u32 - 1)
+                .ok_or_else(|| eyre!("The checksum doesn't fit at 0x{:08X}", addr))?,
+        };
+        if store.start <= cmd.range.end && cmd.range.start <= store.end {
+            return Err(eyre!(
+                "The checksum at {} would overlap the window {} it covers",
+                store,
+                cmd.range
+            ));
+        }
+        hex_file.patch(addr, &digest, true)?;
+        write_hex_file(
+            &self.outputs,
+            &cmd.path,
+            &hex_file,
+            &self.write_opts,
+            self.provenance.as_ref(),
+        )?;
+        println!(
+            "{}  {} stored at 0x{:08X}",
+            cmd.algo.name(),
+            digest_text,
+            addr
+        );
+        Ok(())
+    }
+
+    fn swap(&mut self, cmd: SwapCommand) -> eyre::Result<()> {
+        if cmd.width != 16 && cmd.width != 32 {
+            return Err(eyre!("--width must be 16 or 32, not {}", cmd.width));
+        }
+        let mut hex_file = self.load_input()?;
+        self.transform(&mut hex_file, &|h| h.swap(cmd.width / 8, cmd.range))?;
+        write_hex_file(
+            &self.outputs,
+            &cmd.path,
+            &hex_file,
+            &self.write_opts,
+            self.provenance.as_ref(),
+        )?;
+        Ok(())
+    }
+
+    fn xform(&mut self, cmd: XformCommand) -> eyre::Result<()> {
+        let mut hex_file = self.load_input()?;
+        self.transform(&mut hex_file, &|h| h.xform(cmd.op, &cmd.key.0, cmd.range))?;
+        write_hex_file(
+            &self.outputs,
+            &cmd.path,
+            &hex_file,
+            &self.write_opts,
+            self.provenance.as_ref(),
+        )?;
+        Ok(())
+    }
+
+    fn align(&mut self, cmd: AlignCommand) -> eyre::Result<()> {
+        let align = cmd
+            .to
+            .or(self.profile.page_size)
+            .ok_or_else(|| eyre!("Give --to, or a profile with a page_size"))?;
+        let fill = cmd.fill.or(self.profile.fill).unwrap_or(0xFF);
+        let mut hex_file = self.load_input()?;
+        self.transform(&mut hex_file, &|h| h.align(align, fill))?;
+        write_hex_file(
+            &self.outputs,
+            &cmd.path,
+            &hex_file,
+            &self.write_opts,
+            self.provenance.as_ref(),
+        )?;
+        Ok(())
+    }
+
+    fn normalize(&mut self, cmd: NormalizeCommand) -> eyre::Result<()> {
+        let mut hex_file = self.load_input()?;
+        hex_file.normalize(cmd.record_len.or(self.profile.record_size).unwrap_or(16))?;
+        write_hex_file(
+            &self.outputs,
+            &cmd.path,
+            &hex_file,
+            &self.write_opts,
+            self.provenance.as_ref(),
+        )?;
+        Ok(())
+    }
+
+    fn explain(&mut self, cmd: ExplainCommand) -> eyre::Result<()> {
+        explain::explain(open_input(&self.filename)?, cmd.count, io::stdout().lock())?;
+        Ok(())
+    }
+
+    fn doctor(&mut self, cmd: DoctorCommand) -> eyre::Result<()> {
+        let text = read_input(&self.filename)?;
+        let diagnosis = doctor::diagnose(&text);
+        let repaired = match &cmd.repair {
+            Some(path) => {
+                let repaired = doctor::repair(&text);
+                self.outputs.write(path, &repaired.text)?;
+                Some((path, repaired.repairs))
+            }
+            None => None,
+        };
+        if self.json {
+            let findings = diagnosis.findings.iter().map(|f| {
+                Json::object([
+                    ("finding", f.to_string().into()),
+                    ("cause", f.cause().into()),
+                ])
+            });
+            let causes = diagnosis.causes().into_iter().map(|(cause, count)| {
+                Json::object([("cause", cause.into()), ("findings", count.into())])
+            });
+            let mut report = Json::object([
+                ("lines", diagnosis.lines.into()),
+                ("bad_lines", diagnosis.bad_lines.into()),
+                ("findings", Json::Array(findings.collect())),
+                ("causes", Json::Array(causes.collect())),
+            ]);
+            if let Some((_, repairs)) = &repaired {
+                let repairs = repairs.iter().map(|r| {
+                    let (line, status) = match r {
+                        doctor::Repair::Fixed { line, .. } => (line, "fixed"),
+                        doctor::Repair::Ambiguous { line, .. } => (line, "ambiguous"),
+                        doctor::Repair::Unrepairable { line } => (line, "unrepairable"),
+                    };
+                    Json::object([
+                        ("line", (*line).into()),
+                        ("status", status.into()),
+                        ("detail", r.to_string().into()),
+                    ])
+                });
+                report.push("repairs", Json::Array(repairs.collect()));
+            }
+            println!("{}", report);
+        } else {
+            println!(
+                "Checked {} line(s), {} damaged",
+                diagnosis.lines, diagnosis.bad_lines
+            );
+            if diagnosis.findings.is_empty() {
+                println!("No signs of corruption found");
+            } else {
+                for finding in &diagnosis.findings {
+                    println!("    {} ({})", finding, finding.cause());
+                }
+                println!("Likely causes:");
+                for (cause, count) in diagnosis.causes() {
+                    println!("    {:>4} x {}", count, cause);
+                }
+            }
+        }
+        if let (Some((path, repairs)), false) = (&repaired, self.json) {
+            println!("Repairs written to {}:", path);
+            for repair in repairs {
+                println!("    {}", repair);
+            }
+        }
+        Ok(())
+    }
+
+    fn estimate(&mut self, cmd: EstimateCommand) -> eyre::Result<()> {
+        let hex_file = self.load_input()?;
+        let speed = flash::ProgrammerSpeed {
+            program_bytes_per_sec: cmd.rate.or(self.profile.program_bytes_per_sec).ok_or_else(
+                || {
+                    eyre!(
+                        "No programming speed, give --rate or a profile with program_bytes_per_sec"
+                    )
+                },
+            )?,
+            erase_ms_per_page: cmd.erase_ms.or(self.profile.erase_ms_per_page).unwrap_or(0),
+            record_overhead_us: cmd
+                .record_overhead_us
+                .or(self.profile.record_overhead_us)
+                .unwrap_or(0),
+        };
+        let page_size = cmd.page_size.or(self.profile.page_size).unwrap_or(2048);
+        let report = flash::simulate(
+            &hex_file,
+            page_size,
+            self.profile.erase_value.unwrap_or(0xFF),
+        )?;
+        let estimate = flash::estimate(&hex_file, &report, hex_file.data().len(), &speed)?;
+        let coalesced = flash::estimate(
+            &hex_file,
+            &report,
+            flash::coalesced_records(&hex_file, u8::MAX),
+            &speed,
+        )?;
+        if self.json {
+            let times = |e: &flash::Estimate| {
+                Json::object([
+                    ("records", e.records.into()),
+                    ("erase_s", e.erase.as_secs_f64().into()),
+                    ("program_s", e.program.as_secs_f64().into()),
+                    ("overhead_s", e.overhead.as_secs_f64().into()),
+                    ("total_s", e.total().as_secs_f64().into()),
+                ])
+            };
+            let mut result = times(&estimate);
+            result.push("pages", estimate.pages.into());
+            result.push("data_bytes", estimate.data_bytes.into());
+            result.push("coalesced", times(&coalesced));
+            println!("{}", result);
+        } else {
+            println!(
+                "Erase:            {} page(s) x {} ms = {:.3} s",
+                estimate.pages,
+                speed.erase_ms_per_page,
+                estimate.erase.as_secs_f64()
+            );
+            println!(
+                "Program:          {} bytes at {} B/s = {:.3} s",
+                estimate.data_bytes,
+                speed.program_bytes_per_sec,
+                estimate.program.as_secs_f64()
+            );
+            println!(
+                "Record overhead:  {} record(s) x {} us = {:.3} s",
+                estimate.records,
+                speed.record_overhead_us,
+                estimate.overhead.as_secs_f64()
+            );
+            println!("Total:            {:.3} s", estimate.total().as_secs_f64());
+            println!(
+                "With records coalesced to 255 bytes: {} record(s), {:.3} s",
+                coalesced.records,
+                coalesced.total().as_secs_f64()
+            );
+        }
+        Ok(())
+    }
+
+    fn fill(&mut self, cmd: FillCommand) -> eyre::Result<()> {
+        let mut hex_file = self.load_input()?;
+        let window = match (cmd.start, cmd.len) {
+            (Some(start), Some(len)) => len
+                .checked_sub(1)
+                .and_then(|len| start.checked_add(len))
+                .map(|end| hex::AddrRange { start, end })
+                .ok_or_else(|| eyre!("Invalid window of 0x{:x} bytes at 0x{:08x}", len, start))?,
+            (None, None) => {
+                let ranges = hex_file.address_ranges();
+                let (Some(first), Some(last)) = (ranges.first(), ranges.last()) else {
+                    return Err(eyre!("{} holds no data", self.filename));
+                };
+                hex::AddrRange {
+                    start: first.start,
+                    end: last.end,
+                }
+            }
+            _ => return Err(eyre!("--start and --len must be given together")),
+        };
+        let pattern = match cmd.pattern {
+            Some(pattern) => pattern.0,
+            None => vec![self.profile.fill.unwrap_or(0xFF)],
+        };
+        self.transform(&mut hex_file, &|h| h.fill(window, &pattern))?;
+        write_hex_file(
+            &self.outputs,
+            &cmd.path,
+            &hex_file,
+            &self.write_opts,
+            self.provenance.as_ref(),
+        )?;
+        Ok(())
+    }
+
+    fn crop(&mut self, cmd: CropCommand) -> eyre::Result<()> {
+        if cmd.range.is_empty() {
+            return Err(eyre!("Give at least one --range to keep"));
+        }
+        let mut hex_file = self.load_input()?;
+        hex_file.crop(&cmd.range);
+        write_hex_file(
+            &self.outputs,
+            &cmd.path,
+            &hex_file,
+            &self.write_opts,
+            self.provenance.as_ref(),
+        )?;
+        Ok(())
+    }
+
+    fn erase(&mut self, cmd: EraseCommand) -> eyre::Result<()> {
+        if cmd.range.is_empty() {
+            return Err(eyre!("Give at least one --range to remove"));
+        }
+        let mut hex_file = self.load_input()?;
+        hex_file.erase(&cmd.range);
+        write_hex_file(
+            &self.outputs,
+            &cmd.path,
+            &hex_file,
+            &self.write_opts,
+            self.provenance.as_ref(),
+        )?;
+        Ok(())
+    }
+
+    fn split(&mut self, cmd: SplitCommand) -> eyre::Result<()> {
+        if !cmd.template.contains("{addr}") {
+            return Err(eyre!("--template must contain {{addr}}"));
+        }
+        let mut hex_file = self.load_input()?;
+        let eep_path = cmd.template.replace("{addr}", "eeprom");
+        if let Some(path) = self.write_eep(&mut hex_file, &eep_path)? {
+            println!("{}", path);
+        }
+        for part in hex_file.split(&cmd.at) {
+            let first = part.address_ranges()[0].start;
+            let path = cmd.template.replace("{addr}", &format!("0x{:08x}", first));
+            write_hex_file(
+                &self.outputs,
+                &path,
+                &part,
+                &self.write_opts,
+                self.provenance.as_ref(),
+            )?;
+            println!("{}", path);
+        }
+        Ok(())
+    }
+
+    fn find(&mut self, cmd: FindCommand) -> eyre::Result<()> {
+        let hex_file = self.load_input()?;
+        let shift = display_offset(self.rebase, &hex_file.address_ranges());
+        let found = hex_file.find(&cmd.pattern.0);
+        if self.json {
+            let addrs = found
+                .into_iter()
+                .map(|addr| addr.wrapping_add(shift).into())
+                .collect();
+            println!("{}", Json::object([("matches", Json::Array(addrs))]));
+        } else {
+            for addr in found {
+                match self.svd.as_ref().and_then(|svd| svd.describe(addr)) {
+                    Some(name) => println!("0x{:08X}  {}", addr.wrapping_add(shift), name),
+                    None => println!("0x{:08X}", addr.wrapping_add(shift)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn strings(&mut self, cmd: StringsCommand) -> eyre::Result<()> {
+        let hex_file = self.load_input()?;
+        let shift = display_offset(self.rebase, &hex_file.address_ranges());
+        let found = hex_file
+            .strings(cmd.min_len)
+            .into_iter()
+            .map(|(addr, text)| (addr.wrapping_add(shift), text))
+            .collect_vec();
+        if self.json {
+            let items = found
+                .into_iter()
+                .map(|(addr, text)| Json::object([("addr", addr.into()), ("text", text.into())]))
+                .collect();
+            println!("{}", Json::Array(items));
+        } else {
+            for (addr, text) in found {
+                println!("0x{:08X}  {}", addr, text);
+            }
+        }
+        Ok(())
+    }
+
+    fn simulate_flash(&mut self, cmd: SimulateFlashCommand) -> eyre::Result<()> {
+        let hex_file = self.load_input()?;
+        let report = flash::simulate(
+            &hex_file,
+            cmd.page_size.or(self.profile.page_size).unwrap_or(2048),
+            cmd.erase.or(self.profile.erase_value).unwrap_or(0xFF),
+        )?;
+        if self.json {
+            let pages = report
+                .pages
+                .iter()
+                .map(|page| {
+                    let mut item = range_json(page.range);
+                    item.push("data_bytes", page.data_bytes.into());
+                    item.push("programmed_bytes", page.programmed_bytes.into());
+                    item.push("state", page.state.to_string().into());
+                    item
+                })
+                .collect();
+            println!(
+                "{}",
+                Json::object([
+                    ("pages", Json::Array(pages)),
+                    ("erase_ops", report.erase_ops().into()),
+                    ("program_ops", report.program_ops().into()),
+                ])
+            );
+        } else {
+            println!(
+                "{:<10}  {:>10}  {:>10}  State",
+                "Page", "Data", "Programmed"
+            );
+            for page in &report.pages {
+                println!(
+                    "0x{:08X}  {:>10}  {:>10}  {}",
+                    page.range.start, page.data_bytes, page.programmed_bytes, page.state
+                );
+            }
+            println!();
+            println!(
+                "Pages:        {} ({} programmed, {} partial, {} erased-only)",
+                report.pages.len(),
+                report.count(flash::PageState::Programmed),
+                report.count(flash::PageState::Partial),
+                report.count(flash::PageState::ErasedOnly)
+            );
+            println!("Erase ops:    {}", report.erase_ops());
+            println!("Program ops:  {}", report.program_ops());
+        }
+        Ok(())
+    }
+
+    fn entropy(&mut self, cmd: EntropyCommand) -> eyre::Result<()> {
+        if cmd.window == 0 {
+            return Err(eyre!("Block size must not be 0"));
+        }
+        let hex_file = self.load_input()?;
+        let entropy = hex_file.entropy(cmd.window as usize);
+        if self.json {
+            let blocks = entropy
+                .into_iter()
+                .map(|(range, bits)| {
+                    let mut item = range_json(range);
+                    item.push("entropy", bits.into());
+                    item
+                })
+                .collect();
+            println!("{}", Json::Array(blocks));
+        } else {
+            // A bar of up to 32 characters, 4 per bit of entropy
+            for (range, bits) in entropy {
+                println!(
+                    "0x{:08X}  {:4.2}  {}",
+                    range.start,
+                    bits,
+                    "#".repeat((bits * 4.0).round() as usize)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn info(&mut self, cmd: InfoCommand) -> eyre::Result<()> {
+        // Record-level statistics only exist for Intel HEX input, and
+        // provenance only for ELF input
+        let mut metadata = None;
+        // Bytes held in memory, when compressing
+        let mut stored = None;
+        let (ranges, entry, stats) = if is_elf(&self.filename) && self.more.is_empty() {
+            let (hex_file, meta) = load_elf_file(&self.filename)?;
+            metadata = Some(meta);
+            let hex_file = compressed(hex_file, self.opts);
+            stored = self.opts.compress.then(|| hex_file.stored_len());
+            (hex_file.address_ranges(), hex_file.start_addr(), None)
+        } else if let Some(lazy) = self.lazy_input()? {
+            let ctx = lazy.context();
+            let stats = (ctx.record_counts().clone(), ctx.bad_checksums().to_vec());
+            let (ranges, entry) = (lazy.address_ranges(), lazy.start_addr());
+            collect_warnings(&self.filename, lazy.into_context(), &mut self.warnings);
+            (ranges, entry, Some(stats))
+        } else if self.in_memory() || !is_hex_input(&self.filename) || self.opts.multi {
+            let hex_file = self.load_input()?;
+            stored = self.opts.compress.then(|| hex_file.stored_len());
+            (hex_file.address_ranges(), hex_file.start_addr(), None)
+        } else {
+            let mut ctx = open_hex_file(&self.filename, self.opts)?;
+            let hex_file = ctx
+                .parse()
+                .with_context(|| format!("Parsing {}", self.filename))?;
+            let stats = (ctx.record_counts().clone(), ctx.bad_checksums().to_vec());
+            collect_warnings(&self.filename, ctx, &mut self.warnings);
+            stored = self.opts.compress.then(|| hex_file.stored_len());
+            (
+                hex_file.address_ranges(),
+                hex_file.start_addr(),
+                Some(stats),
+            )
+        };
+        let (ranges, eeprom_ranges) = match self.eeprom {
+            Some(eeprom) => eeprom.partition(ranges),
+            None => (ranges, Vec::new()),
+        };
+        let data_bytes: u64 = ranges.iter().map(|r| r.size()).sum();
+        let eeprom_bytes: u64 = eeprom_ranges.iter().map(|r| r.size()).sum();
+        let lowest = ranges.first().map(|r| r.start);
+        let highest = ranges.last().map(|r| r.end);
+        let largest_gap = ranges
+            .iter()
+            .tuple_windows()
+            .map(|(a, b)| hex::AddrRange {
+                start: a.end + 1,
+                end: b.start - 1,
+            })
+            .max_by_key(|gap| (gap.size(), std::cmp::Reverse(gap.start)));
+        if self.json {
+            let mut info = Json::object([
+                ("file", self.filename.as_str().into()),
+                ("data_bytes", data_bytes.into()),
+                ("lowest_addr", lowest.into()),
+                ("highest_addr", highest.into()),
+                ("entry", entry.into()),
+                (
+                    "ranges",
+                    Json::Array(ranges.iter().map(|&r| range_json(r)).collect()),
+                ),
+                ("largest_gap", largest_gap.map_or(Json::Null, range_json)),
+            ]);
+            if let Some(eeprom) = self.eeprom {
+                let eeprom_ranges = eeprom_ranges.iter().map(|&range| {
+                    let mut item = range_json(range);
+                    item.push("offset", (range.start - eeprom.range.start).into());
+                    item
+                });
+                info.push("eeprom_bytes", eeprom_bytes.into());
+                info.push("eeprom_ranges", Json::Array(eeprom_ranges.collect()));
+            }
+            if let Some(stored) = stored {
+                info.push("stored_bytes", stored.into());
+            }
+            if let Some((counts, bad_checksums)) = stats {
+                let counts = counts
+                    .by_type()
+                    .iter()
+                    .map(|(kind, count)| (format!("{:02x}", kind), (*count).into()))
+                    .collect();
+                info.push("records", Json::Object(counts));
+                info.push("checksums_valid", bad_checksums.is_empty().into());
+                info.push(
+                    "bad_checksum_lines",
+                    Json::Array(bad_checksums.into_iter().map(Into::into).collect()),
+                );
+            }
+            if let Some(metadata) = &metadata {
+                let comments = metadata.comments.iter().map(|c| c.as_str().into());
+                info.push("comments", Json::Array(comments.collect()));
+                info.push(
+                    "build_id",
+                    metadata.build_id.as_deref().map(hex_string).into(),
+                );
+            }
+            println!("{}", info);
+        } else {
+            let addr = |a: Option<u32>| a.map_or("-".to_string(), |a| format!("0x{:08x}", a));
+            println!(
+                "File:          {}",
+                std::iter::once(&self.filename).chain(&self.more).join(", ")
+            );
+            println!("Ranges:        {}", ranges.len());
+            println!(
+                "Data bytes:    0x{:x} ({})",
+                data_bytes,
+                size_text(data_bytes, cmd.bytes)
+            );
+            if self.eeprom.is_some() {
+                println!(
+                    "EEPROM bytes:  0x{:x} ({}) in {} range(s)",
+                    eeprom_bytes,
+                    size_text(eeprom_bytes, cmd.bytes),
+                    eeprom_ranges.len()
+                );
+            }
+            if let Some(stored) = stored {
+                println!(
+                    "Stored bytes:  0x{:x} ({}), the rest as runs",
+                    stored,
+                    size_text(stored, cmd.bytes)
+                );
+            }
+            println!("Lowest addr:   {}", addr(lowest));
+            println!("Highest addr:  {}", addr(highest));
+            println!("Entry point:   {}", addr(entry));
+            match largest_gap {
+                Some(gap) => println!(
+                    "Largest gap:   0x{:x} ({}) at {}",
+                    gap.size(),
+                    size_text(gap.size(), cmd.bytes),
+                    gap
+                ),
+                None => println!("Largest gap:   -"),
+            }
+            if let Some(metadata) = &metadata {
+                for comment in &metadata.comments {
+                    println!("Comment:       {}", comment);
+                }
+                if let Some(build_id) = &metadata.build_id {
+                    println!("Build ID:      {}", hex_string(build_id));
+                }
+            }
+            if let Some((counts, bad_checksums)) = stats {
+                println!("Records:");
+                for (kind, count) in counts.by_type() {
+                    println!(
+                        "    {:02X} {:<22}{}",
+                        kind,
+                        hex::record_type_name(kind),
+                        count
+                    );
+                }
+                if bad_checksums.is_empty() {
+                    println!("Checksums:     valid");
+                } else {
+                    println!(
+                        "Checksums:     {} bad (line(s) {})",
+                        bad_checksums.len(),
+                        bad_checksums.iter().join(", ")
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn checksum(&mut self, cmd: ChecksumCommand) -> eyre::Result<()> {
+        let hex_file = self.load_input()?;
+        let algos = if cmd.algo.is_empty() {
+            vec![checksum::Algorithm::Crc32]
+        } else {
+            cmd.algo
+        };
+        // --range inside --region narrows it further
+        let range = match (cmd.range, self.region) {
+            (Some(range), Some(region)) => Some(range.intersection(region).ok_or_else(|| {
+                eyre!(
+                    "Range 0x{:08x}..0x{:08x} lies outside region {}",
+                    range.start,
+                    range.end,
+                    self.region_name.as_deref().unwrap_or_default()
+                )
+            })?),
+            (range, region) => range.or(region),
+        };
+        let hex_file = match range {
+            Some(range) => hex_file.extract(range),
+            None => hex_file,
+        };
+        let digests = match cmd.fill {
+            Some(fill) => {
+                let ranges = hex_file.address_ranges();
+                let window = match (range, ranges.first(), ranges.last()) {
+                    (Some(range), _, _) => range,
+                    (None, Some(first), Some(last)) => hex::AddrRange {
+                        start: first.start,
+                        end: last.end,
+                    },
+                    _ => return Err(eyre!("{} holds no data", self.filename)),
+                };
+                let flat = hex_file.flatten_range(window, fill);
+                checksum::compute(&algos, [flat.as_slice()])
+            }
+            None => checksum::compute(&algos, hex_file.data().iter().map(|d| d.to_bytes())),
+        };
+        for (algo, digest) in algos.iter().zip(digests) {
+            println!("{:<12}{}", algo.name(), hex_string(&digest));
+        }
+        Ok(())
+    }
+}
+
+/// Lists the malformed lines skipped in lenient mode, if any.
+fn print_warnings(warnings: &[(String, hex::ParseError)]) {
+    if !warnings.is_empty() {
+        eprintln!("\nSkipped {} malformed line(s):", warnings.len());
+        for (filename, warning) in warnings {
+            eprintln!("    {}: {}", filename, warning.headline());
+        }
+    }
+}
+
+/// `size` in binary units such as "128.0 KiB", or as a plain byte count if
+/// `bytes` is set.
+pub(super) fn size_text(size: u64, bytes: bool) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes || size < 1024 {
+        return format!("{} bytes", size);
+    }
+    let mut scaled = size as f64 / 1024.0;
+    let mut unit = 0;
+    // Move up a unit whenever one decimal would round to 1024.0
+    while scaled >= 1023.95 && unit < UNITS.len() - 1 {
+        scaled /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", scaled, UNITS[unit])
+}
+
+pub(super) fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).join("")
+}
+
+/// Writes `hex_file` to `path`, followed by `provenance` if given.
+pub(super) fn write_hex_file(
+    outputs: &Outputs,
+    path: &str,
+    hex_file: &hex::HexFile,
+    write_opts: &hex::WriteOptions,
+    provenance: Option<&provenance::Provenance>,
+) -> eyre::Result<()> {
+    let mut file = outputs.create(path)?;
+    hex_file.write(&mut file, write_opts)?;
+    if let Some(provenance) = provenance {
+        provenance.write(&mut file, write_opts.line_ending)?;
+    }
+    file.finish()?;
+    if outputs.dry_run {
+        for range in hex_file.address_ranges() {
+            println!("    {} ({} bytes)", range, range.size());
+        }
+    }
+    Ok(())
+}
+
+/// A property of two images compared by `diff` besides their bytes.
+struct MetadataDiff {
+    name: String,
+    old: String,
+    new: String,
+    changed: bool,
+}
+
+/// Compares the entry points, range layouts, total sizes and the profile's
+/// metadata `fields` of two images.
+fn metadata_diff(
+    left: &hex::DiffSide,
+    right: &hex::DiffSide,
+    fields: &[fields::Field],
+) -> Vec<MetadataDiff> {
+    let entry = |side: &hex::DiffSide| {
+        side.kept
+            .start_addr()
+            .map_or("none".to_string(), |addr| format!("0x{:08x}", addr))
+    };
+    let (left_ranges, right_ranges) = (&left.ranges, &right.ranges);
+    let size = |ranges: &[hex::AddrRange]| {
+        format!("0x{:x} bytes", ranges.iter().map(|r| r.size()).sum::<u64>())
+    };
+    let mut rows = vec![
+        MetadataDiff {
+            name: "entry".to_string(),
+            old: entry(left),
+            new: entry(right),
+            changed: left.kept.start_addr() != right.kept.start_addr(),
+        },
+        MetadataDiff {
+            name: "ranges".to_string(),
+            old: format!("{} range(s)", left_ranges.len()),
+            new: format!("{} range(s)", right_ranges.len()),
+            changed: left_ranges != right_ranges,
+        },
+        MetadataDiff {
+            name: "size".to_string(),
+            old: size(left_ranges),
+            new: size(right_ranges),
+            changed: size(left_ranges) != size(right_ranges),
+        },
+    ];
+    for field in fields {
+        let (old, new) = (field.read(&left.kept), field.read(&right.kept));
+        rows.push(MetadataDiff {
+            name: field.name.clone(),
+            changed: old != new,
+            old: old.unwrap_or_else(|| "missing".to_string()),
+            new: new.unwrap_or_else(|| "missing".to_string()),
+        });
+    }
+    rows
+}
+
+/// What to add to addresses so that the lowest one shows as `rebase`.
+fn display_offset(rebase: Option<u32>, ranges: &[hex::AddrRange]) -> u32 {
+    match (rebase, ranges.first()) {
+        (Some(rebase), Some(first)) => rebase.wrapping_sub(first.start),
+        _ => 0,
+    }
+}
+
+fn rebased(range: hex::AddrRange, offset: u32) -> hex::AddrRange {
+    hex::AddrRange {
+        start: range.start.wrapping_add(offset),
+        end: range.end.wrapping_add(offset),
+    }
+}
+
+fn range_json(range: hex::AddrRange) -> Json {
+    Json::object([
+        ("start", range.start.into()),
+        ("end", range.end.into()),
+        ("size", range.size().into()),
+    ])
+}
+
+/// Resolves `label:NAME` through the labels file, or parses a plain address.
+pub(super) fn resolve_location(
+    spec: &str,
+    filename: &str,
+    labels: Option<&str>,
+) -> eyre::Result<u32> {
+    let Some(name) = spec.strip_prefix("label:") else {
+        return num_decode(spec).map_err(|e| eyre!("Invalid address {}: {}", spec, e));
+    };
+    let path = match labels {
+        Some(path) => PathBuf::from(path),
+        None => Path::new(filename).with_extension("labels"),
+    };
+    labels::Labels::load(&path)?
+        .get(name)
+        .ok_or_else(|| eyre!("No label named {} in {}", name, path.display()))
+}
+
+/// TRACE32 scripts referencing binary dumps are imported rather than parsed.
+fn is_trace32(filename: &str) -> bool {
+    Path::new(filename)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cmm"))
+}
+
+/// ELF and AXF files are recognized by their magic rather than extension.
+fn is_elf(filename: &str) -> bool {
+    let mut magic = [0; 4];
+    open_input(filename).is_ok_and(|mut file| file.read_exact(&mut magic).is_ok())
+        && elf::is_elf(&magic)
+}
+
+/// Caches written by `cache save` are recognized by their magic as well.
+fn is_cache(filename: &str) -> bool {
+    let mut magic = [0; 8];
+    open_input(filename).is_ok_and(|mut file| file.read_exact(&mut magic).is_ok())
+        && cache::is_cache(&magic)
+}
+
+/// The formats inputs can be given in.
+fn formats() -> &'static format::Registry {
+    static FORMATS: OnceLock<format::Registry> = OnceLock::new();
+    FORMATS.get_or_init(format::Registry::builtin)
+}
+
+/// The format of `filename`, going by its name and first bytes.
+fn detect_format(filename: &str) -> Option<&'static dyn FormatProvider> {
+    let mut head = Vec::new();
+    open_input(filename)
+        .ok()?
+        .take(format::HEAD_LEN as u64)
+        .read_to_end(&mut head)
+        .ok()?;
+    formats().detect(Path::new(filename), &head)
+}
+
+/// Whether the file is Intel HEX, which can be read record by record.
+fn is_hex_input(filename: &str) -> bool {
+    !is_trace32(filename)
+        && detect_format(filename).is_none_or(|f| f.name() == format::IntelHex.name())
+}
+
+/// Identifies the state of `filename` and the options it's parsed with,
+/// to tell whether a cache of it is current.
+fn source_fingerprint(filename: &str, opts: ParseOptions) -> eyre::Result<Vec<u8>> {
+    let meta = std::fs::metadata(filename).with_context(|| format!("Reading {}", filename))?;
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    Ok(format!("{} {} {:?}", meta.len(), modified.as_nanos(), opts).into_bytes())
+}
+
+fn load_elf_file(filename: &str) -> eyre::Result<(hex::HexFile, elf::ElfMetadata)> {
+    let data = read_input(filename)?;
+    elf::from_elf(&data).with_context(|| format!("Loading {}", filename))
+}
+
+/// Parser settings applied to every input file.
+#[derive(Debug, Clone, Copy)]
+struct ParseOptions {
+    lenient: bool,
+    allow_missing_eof: bool,
+    trailing: hex::TrailingPolicy,
+    multi: bool,
+    /// Load from and save to a `.hexcache` sidecar file.
+    sidecar: bool,
+    /// Store runs of one byte compactly, see [`hex::HexFile::compress`].
+    compress: bool,
+    /// Decode data only where it's read, see [`lazy::LazyHexFile`].
+    lazy: bool,
+}
+
+/// Opens `filename`, or stdin for [`STDIN`]. Stdin is read in whole the
+/// first time, so that it can be opened again.
+fn open_input(filename: &str) -> eyre::Result<Box<dyn BufRead>> {
+    static STDIN_DATA: OnceLock<Vec<u8>> = OnceLock::new();
+
+    if filename == STDIN {
+        let data = match STDIN_DATA.get() {
+            Some(data) => data,
+            None => {
+                let mut data = Vec::new();
+                io::stdin()
+                    .lock()
+                    .read_to_end(&mut data)
+                    .context("Reading stdin")?;
+                STDIN_DATA.get_or_init(|| data)
+            }
+        };
+        return Ok(Box::new(data.as_slice()));
+    }
+    let file = File::open(filename).with_context(|| format!("Opening {}", filename))?;
+    Ok(Box::new(BufReader::new(file)))
+}
+
+/// The whole of `filename`, or of stdin for [`STDIN`].
+fn read_input(filename: &str) -> eyre::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    open_input(filename)?
+        .read_to_end(&mut data)
+        .with_context(|| format!("Reading {}", filename))?;
+    Ok(data)
+}
+
+fn open_hex_file(
+    filename: &str,
+    opts: ParseOptions,
+) -> eyre::Result<hex::Context<Box<dyn BufRead>>> {
+    Ok(hex_context(open_input(filename)?, opts))
+}
+
+fn hex_context<R: BufRead>(reader: R, opts: ParseOptions) -> hex::Context<R> {
+    hex::Context::new(reader)
+        .lenient(opts.lenient)
+        .allow_missing_eof(opts.allow_missing_eof)
+        .trailing(opts.trailing)
+        .compress(opts.compress)
+}
+
+fn load_hex_file(
+    filename: &str,
+    opts: ParseOptions,
+    warnings: &mut Vec<(String, hex::ParseError)>,
+) -> eyre::Result<hex::HexFile> {
+    if is_cache(filename) {
+        let (_, hex_file) =
+            cache::read(open_input(filename)?).with_context(|| format!("Loading {}", filename))?;
+        return Ok(compressed(hex_file, opts));
+    }
+    if !opts.sidecar || filename == STDIN {
+        return parse_hex_file(filename, opts, warnings);
+    }
+
+    let sidecar = format!("{}.hexcache", filename);
+    let fingerprint = source_fingerprint(filename, opts)?;
+    let cached = File::open(&sidecar)
+        .map_err(eyre::Report::from)
+        .and_then(|file| cache::read(BufReader::new(file)));
+    if let Ok((cached_fingerprint, hex_file)) = cached {
+        if cached_fingerprint == fingerprint {
+            return Ok(compressed(hex_file, opts));
+        }
+    }
+    let hex_file = parse_hex_file(filename, opts, warnings)?;
+    // The cache only saves time, so failing to write it is no reason to stop
+    if let Err(e) = File::create(&sidecar)
+        .map_err(eyre::Report::from)
+        .and_then(|file| cache::write(io::BufWriter::new(file), &hex_file, &fingerprint))
+    {
+        eprintln!("Warning: couldn't write {}: {:#}", sidecar, e);
+    }
+    Ok(hex_file)
+}
+
+fn parse_hex_file(
+    filename: &str,
+    opts: ParseOptions,
+    warnings: &mut Vec<(String, hex::ParseError)>,
+) -> eyre::Result<hex::HexFile> {
+    if is_trace32(filename) {
+        return Ok(compressed(trace32::import_cmm(Path::new(filename))?, opts));
+    }
+    if let Some(format) = detect_format(filename).filter(|f| f.name() != format::IntelHex.name()) {
+        let hex_file = format
+            .read(&read_input(filename)?)
+            .with_context(|| format!("Loading {} as {}", filename, format.name()))?;
+        return Ok(compressed(hex_file, opts));
+    }
+    let mut ctx = open_hex_file(filename, opts)?;
+    let hex_file = if opts.multi {
+        let mut images = ctx
+            .parse_images()
+            .with_context(|| format!("Parsing {}", filename))?
+            .into_iter();
+        let mut hex_file = images.next().unwrap_or_default();
+        for (idx, image) in images.enumerate() {
+            hex_file
+                .merge(&image, hex::OverlapPolicy::Error)
+                .with_context(|| format!("Merging image {} of {}", idx + 2, filename))?;
+        }
+        hex_file
+    } else {
+        ctx.parse()
+            .with_context(|| format!("Parsing {}", filename))?
+    };
+    collect_warnings(filename, ctx, warnings);
+    Ok(hex_file)
+}
+
+/// `hex_file`, compressed if `opts` ask for it. Intel HEX is compressed
+/// while it's parsed instead.
+fn compressed(mut hex_file: hex::HexFile, opts: ParseOptions) -> hex::HexFile {
+    if opts.compress {
+        hex_file.compress();
+    }
+    hex_file
+}
+
+fn collect_warnings<R: BufRead>(
+    filename: &str,
+    ctx: hex::Context<R>,
+    warnings: &mut Vec<(String, hex::ParseError)>,
+) {
+    if ctx.missing_eof() {
+        eprintln!("Warning: {} ends without an EOF record", filename);
+    }
+    if let Some(trailing) = ctx.ignored_trailing() {
+        eprintln!(
+            "Warning: ignored {} line(s) ({} bytes) after the EOF record on line {} of {}, \
+             starting with line {}: {}",
+            trailing.lines,
+            trailing.bytes,
+            trailing.eof_line,
+            filename,
+            trailing.first_line,
+            trailing.first
+        );
+    }
+    warnings.extend(
+        ctx.into_warnings()
+            .into_iter()
+            .map(|w| (filename.to_string(), w)),
+    );
+}
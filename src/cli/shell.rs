@@ -0,0 +1,106 @@
+//! `shell`: answers any number of queries against an image parsed once,
+//! so large files don't get reparsed for every one of them.
+
+use argh::FromArgs;
+use color_eyre::eyre;
+use hex_reader::hex;
+
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use super::run::run_with;
+use super::Args;
+
+/// Reads commands from stdin, one per line and written as after the
+/// filename on the command line, and runs each against `hex_file`. A failing
+/// command is reported and the shell carries on.
+pub(super) fn run(filename: &str, hex_file: &hex::HexFile) -> eyre::Result<()> {
+    let interactive = io::stdin().is_terminal();
+    if interactive {
+        eprintln!(
+            "{} loaded, {} range(s). Type a command such as `ranges`, `help` for the list \
+             or `quit` to leave.",
+            filename,
+            hex_file.address_ranges().len()
+        );
+    }
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        if interactive {
+            eprint!("> ");
+            io::stderr().flush()?;
+        }
+        let Some(line) = lines.next().transpose()? else {
+            break;
+        };
+        let words = match split_words(&line) {
+            Ok(words) => words,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                continue;
+            }
+        };
+        match words.first().map(String::as_str) {
+            None => continue,
+            Some("quit" | "exit") => break,
+            Some("help") => {
+                // Prints the usage, listing the commands
+                parse_args(filename, &["--help"]);
+                continue;
+            }
+            Some(_) => {}
+        }
+
+        let words = words.iter().map(String::as_str).collect::<Vec<_>>();
+        let args = match parse_args(filename, &words) {
+            Some(args) => args,
+            None => continue,
+        };
+        if let Err(e) = run_with(args, Some(hex_file)) {
+            eprintln!("Error: {:#}", e);
+        }
+        // Keeps each answer together when stdout is a pipe
+        io::stdout().flush()?;
+    }
+    Ok(())
+}
+
+/// Parses a command line, printing usage or the reason it's invalid if it
+/// doesn't describe a command to run.
+pub(super) fn parse_args(filename: &str, words: &[&str]) -> Option<Args> {
+    let argv = std::iter::once(filename)
+        .chain(words.iter().copied())
+        .collect::<Vec<_>>();
+    match Args::from_args(&["hex-reader"], &argv) {
+        Ok(args) => Some(args),
+        Err(exit) => {
+            match exit.status {
+                Ok(()) => println!("{}", exit.output),
+                Err(()) => eprintln!("{}", exit.output),
+            }
+            None
+        }
+    }
+}
+
+/// Splits a line into words at whitespace, keeping text in double quotes
+/// together.
+pub(super) fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut in_str = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                in_str = !in_str;
+                word.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !in_str => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if in_str {
+        return Err("unterminated string".to_string());
+    }
+    words.extend(word);
+    Ok(words)
+}
@@ -0,0 +1,60 @@
+//! `--watch`: runs the command again each time the inputs change, such as
+//! when the build regenerates them.
+
+use color_eyre::eyre;
+
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use super::run::run_with;
+
+/// How often the inputs are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Size and modification time of each input, or `None` for one that can't
+/// be read, such as while the build is replacing it.
+type Stamp = Vec<Option<(u64, SystemTime)>>;
+
+fn stamp(inputs: &[PathBuf]) -> Stamp {
+    inputs
+        .iter()
+        .map(|path| {
+            let meta = std::fs::metadata(path).ok()?;
+            Some((meta.len(), meta.modified().ok()?))
+        })
+        .collect()
+}
+
+/// Runs the command given on the command line, then again whenever any of
+/// `inputs` changes, until interrupted. Failures are shown like output, so
+/// a broken build doesn't end the watch.
+pub(super) fn run(inputs: &[PathBuf]) -> eyre::Result<()> {
+    let mut last = None;
+    loop {
+        let current = stamp(inputs);
+        if last.as_ref() != Some(&current) {
+            // Builds write their output in pieces, so wait for the inputs to
+            // settle before reading them
+            std::thread::sleep(POLL_INTERVAL);
+            if stamp(inputs) != current {
+                continue;
+            }
+            // Clears the screen and moves the cursor to the top left
+            print!("\x1b[2J\x1b[H");
+            println!(
+                "Watching {} (Ctrl-C to stop)\n",
+                inputs
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            // Parsed again for each run, as running consumes the arguments
+            if let Err(e) = run_with(super::parse(), None) {
+                eprintln!("Error: {:#}", e);
+            }
+            last = Some(current);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
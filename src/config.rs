@@ -0,0 +1,380 @@
+use std::{collections::HashMap, env, fs, path::PathBuf};
+
+use eyre::eyre;
+use eyre::Context;
+
+#[cfg(feature = "elf")]
+use crate::elf::Machine;
+use crate::fields::{Field, FieldFormat};
+use crate::memory_map::{MemoryMap, RegionTable};
+
+/// Per-device defaults, selected with `--profile`.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    #[cfg(feature = "elf")]
+    pub machine: Option<Machine>,
+    #[cfg(feature = "elf")]
+    /// `e_flags` for ELF output, see [`crate::elf::ElfOptions::flags`].
+    pub elf_flags: Option<u32>,
+    pub eeprom_offset: Option<u32>,
+    pub eeprom_size: Option<u32>,
+    pub vector_table_end: Option<u32>,
+    /// Whether to split flash into `.flash` and `.text` at the vector table
+    /// end at all.
+    pub split_vector_table: Option<bool>,
+    /// Flash page size in bytes.
+    pub page_size: Option<u32>,
+    /// Byte to pad and fill gaps with.
+    pub fill: Option<u8>,
+    /// Data bytes per record when normalizing.
+    pub record_size: Option<u8>,
+    /// Value of an erased flash byte.
+    pub erase_value: Option<u8>,
+    /// Programmer speeds, see [`crate::flash::ProgrammerSpeed`].
+    pub program_bytes_per_sec: Option<u32>,
+    pub erase_ms_per_page: Option<u32>,
+    pub record_overhead_us: Option<u32>,
+    /// Regions from `[profile.NAME.region.REGION]` tables.
+    pub memory_map: MemoryMap,
+    /// Metadata fields from `[profile.NAME.field.FIELD]` tables.
+    pub fields: Vec<Field>,
+}
+
+/// A `[profile.NAME.field.FIELD]` table as read, before it is checked.
+struct FieldTable {
+    profile: String,
+    name: String,
+    addr: Option<u32>,
+    len: Option<u32>,
+    format: Option<FieldFormat>,
+    /// Line of the table's first key.
+    line: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    profiles: HashMap<String, Profile>,
+}
+
+impl Config {
+    /// Loads the config from `path`, falling back to `$HEX_READER_CONFIG` and
+    /// then `$XDG_CONFIG_HOME/hex-reader/config.toml`.
+    pub fn load(path: Option<&str>) -> eyre::Result<Self> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => default_path().ok_or_else(|| eyre!("Cannot locate config file"))?,
+        };
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Reading config {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Parsing config {}", path.display()))
+    }
+
+    fn parse(text: &str) -> eyre::Result<Self> {
+        let mut config = Config::default();
+        // Checked once every key of the region or field has been read
+        let mut regions = Vec::<(String, RegionTable)>::new();
+        let mut fields = Vec::<FieldTable>::new();
+        for entry in parse_toml(text)? {
+            let (section, name) = match entry.table.as_slice() {
+                [section, name] => (section, name),
+                [section, profile, kind, region] if section == "profile" && kind == "region" => {
+                    let pos = match regions
+                        .iter()
+                        .position(|(p, r)| p == profile && r.name == *region)
+                    {
+                        Some(pos) => pos,
+                        None => {
+                            regions.push((profile.clone(), RegionTable::new(region, entry.line)));
+                            regions.len() - 1
+                        }
+                    };
+                    regions[pos].1.set(&entry.key, &entry.value, entry.line)?;
+                    continue;
+                }
+                [section, profile, kind, field] if section == "profile" && kind == "field" => {
+                    let pos = match fields
+                        .iter()
+                        .position(|f| f.profile == *profile && f.name == *field)
+                    {
+                        Some(pos) => pos,
+                        None => {
+                            fields.push(FieldTable {
+                                profile: profile.clone(),
+                                name: field.clone(),
+                                addr: None,
+                                len: None,
+                                format: None,
+                                line: entry.line,
+                            });
+                            fields.len() - 1
+                        }
+                    };
+                    let table = &mut fields[pos];
+                    match entry.key.as_str() {
+                        "addr" => table.addr = Some(entry.value.as_u32(entry.line)?),
+                        "len" => table.len = Some(entry.value.as_u32(entry.line)?),
+                        "format" => {
+                            table.format = Some(
+                                entry
+                                    .value
+                                    .as_str(entry.line)?
+                                    .parse()
+                                    .map_err(|e| eyre!("Line {}: {}", entry.line, e))?,
+                            )
+                        }
+                        key => return Err(eyre!("Line {}: unknown field key {}", entry.line, key)),
+                    }
+                    continue;
+                }
+                _ => {
+                    return Err(eyre!(
+                        "Line {}: unexpected table [{}]",
+                        entry.line,
+                        entry.table.join(".")
+                    ))
+                }
+            };
+            if section != "profile" {
+                return Err(eyre!("Line {}: unknown table [{}]", entry.line, section));
+            }
+            let profile = config.profiles.entry(name.clone()).or_default();
+            match entry.key.as_str() {
+                #[cfg(feature = "elf")]
+                "machine" => {
+                    profile.machine = Some(
+                        entry
+                            .value
+                            .as_str(entry.line)?
+                            .parse()
+                            .map_err(|e| eyre!("Line {}: {}", entry.line, e))?,
+                    )
+                }
+                #[cfg(feature = "elf")]
+                "elf_flags" => profile.elf_flags = Some(entry.value.as_u32(entry.line)?),
+                "eeprom_offset" => profile.eeprom_offset = Some(entry.value.as_u32(entry.line)?),
+                "eeprom_size" => profile.eeprom_size = Some(entry.value.as_u32(entry.line)?),
+                "vector_table_end" => {
+                    profile.vector_table_end = Some(entry.value.as_u32(entry.line)?)
+                }
+                "split_vector_table" => {
+                    profile.split_vector_table = Some(entry.value.as_bool(entry.line)?)
+                }
+                "page_size" => profile.page_size = Some(entry.value.as_u32(entry.line)?),
+                "fill" => profile.fill = Some(entry.value.as_u8(entry.line)?),
+                "record_size" => profile.record_size = Some(entry.value.as_u8(entry.line)?),
+                "erase_value" => profile.erase_value = Some(entry.value.as_u8(entry.line)?),
+                "program_bytes_per_sec" => {
+                    profile.program_bytes_per_sec = Some(entry.value.as_u32(entry.line)?)
+                }
+                "erase_ms_per_page" => {
+                    profile.erase_ms_per_page = Some(entry.value.as_u32(entry.line)?)
+                }
+                "record_overhead_us" => {
+                    profile.record_overhead_us = Some(entry.value.as_u32(entry.line)?)
+                }
+                key => return Err(eyre!("Line {}: unknown profile key {}", entry.line, key)),
+            }
+        }
+        for (profile, table) in regions {
+            let region = table.build()?;
+            config
+                .profiles
+                .entry(profile)
+                .or_default()
+                .memory_map
+                .regions
+                .push(region);
+        }
+        for FieldTable {
+            profile,
+            name,
+            addr,
+            len,
+            format,
+            line,
+        } in fields
+        {
+            let Some(addr) = addr else {
+                return Err(eyre!("Line {}: field {} needs an addr", line, name));
+            };
+            let format = format.unwrap_or(FieldFormat::Hex);
+            let len = match (format, len) {
+                (FieldFormat::Value(value), None) => value.size() as u32,
+                (FieldFormat::Value(value), Some(len)) if len != value.size() as u32 => {
+                    return Err(eyre!(
+                        "Line {}: field {} is {} byte(s) long, not {}",
+                        line,
+                        name,
+                        value.size(),
+                        len
+                    ))
+                }
+                (_, Some(len)) if len > 0 => len,
+                _ => {
+                    return Err(eyre!(
+                        "Line {}: field {} needs a len of at least 1",
+                        line,
+                        name
+                    ))
+                }
+            };
+            config
+                .profiles
+                .entry(profile)
+                .or_default()
+                .fields
+                .push(Field {
+                    name,
+                    addr,
+                    len,
+                    format,
+                });
+        }
+        Ok(config)
+    }
+
+    pub fn profile(&self, name: &str) -> eyre::Result<&Profile> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| eyre!("No profile named {}", name))
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("HEX_READER_CONFIG") {
+        return Some(path.into());
+    }
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("hex-reader").join("config.toml"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Str(String),
+    Bool(bool),
+}
+
+impl Value {
+    pub fn as_str(&self, line: usize) -> eyre::Result<&str> {
+        match self {
+            Value::Str(s) => Ok(s),
+            _ => Err(eyre!("Line {}: expected a string", line)),
+        }
+    }
+
+    pub fn as_bool(&self, line: usize) -> eyre::Result<bool> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(eyre!("Line {}: expected a boolean", line)),
+        }
+    }
+
+    pub fn as_u32(&self, line: usize) -> eyre::Result<u32> {
+        match self {
+            Value::Int(i) => {
+                u32::try_from(*i).map_err(|_| eyre!("Line {}: {} is out of range", line, i))
+            }
+            _ => Err(eyre!("Line {}: expected an integer", line)),
+        }
+    }
+
+    pub fn as_u8(&self, line: usize) -> eyre::Result<u8> {
+        match self {
+            Value::Int(i) => {
+                u8::try_from(*i).map_err(|_| eyre!("Line {}: {} is out of range", line, i))
+            }
+            _ => Err(eyre!("Line {}: expected an integer", line)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Entry {
+    pub table: Vec<String>,
+    pub key: String,
+    pub value: Value,
+    pub line: usize,
+}
+
+/// Parses the small subset of TOML used by config files: `[dotted.tables]`
+/// and `key = value` pairs holding integers, strings or booleans.
+pub fn parse_toml(text: &str) -> eyre::Result<Vec<Entry>> {
+    let mut table = Vec::new();
+    let mut entries = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header
+                .strip_suffix(']')
+                .ok_or_else(|| eyre!("Line {}: unterminated table header", line_no))?;
+            table = header
+                .split('.')
+                .map(|part| unquote(part.trim()).to_string())
+                .collect();
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| eyre!("Line {}: expected key = value", line_no))?;
+        entries.push(Entry {
+            table: table.clone(),
+            key: unquote(key.trim()).to_string(),
+            value: parse_value(value.trim())
+                .ok_or_else(|| eyre!("Line {}: invalid value {}", line_no, value.trim()))?,
+            line: line_no,
+        });
+    }
+    Ok(entries)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_str = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_str = !in_str,
+            '#' if !in_str => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn unquote(s: &str) -> &str {
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s)
+}
+
+fn parse_value(s: &str) -> Option<Value> {
+    match s {
+        "true" => return Some(Value::Bool(true)),
+        "false" => return Some(Value::Bool(false)),
+        _ => {}
+    }
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        return Some(Value::Str(s[1..s.len() - 1].to_string()));
+    }
+    let (neg, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let digits = s.replace('_', "");
+    let (digits, rad) = if let Some(d) = digits.strip_prefix("0x") {
+        (d, 16)
+    } else if let Some(d) = digits.strip_prefix("0b") {
+        (d, 2)
+    } else if let Some(d) = digits.strip_prefix("0o") {
+        (d, 8)
+    } else {
+        (digits.as_str(), 10)
+    };
+    let value = i64::from_str_radix(digits, rad).ok()?;
+    Some(Value::Int(if neg { -value } else { value }))
+}
@@ -0,0 +1,331 @@
+use std::fmt;
+
+use itertools::Itertools;
+
+use crate::hex::{Context, TrailingPolicy};
+
+/// Bad lines at most this many lines apart count as one cluster.
+const CLUSTER_GAP: usize = 8;
+/// 0xFF bytes needed on each side of a byte for it to sit in an erased run.
+const ERASED_RUN: usize = 4;
+
+/// A pattern that is unlikely in a healthy image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Finding {
+    /// Lines that don't parse or fail their checksum, close together.
+    BadLines {
+        first_line: usize,
+        last_line: usize,
+        count: usize,
+    },
+    /// A line identical to the one before it.
+    DuplicateLine { line: usize },
+    /// A byte one bit away from 0xFF in the middle of a run of 0xFF.
+    BitFlip { addr: u32, value: u8 },
+}
+
+impl Finding {
+    /// The most likely way the damage came about.
+    pub fn cause(&self) -> &'static str {
+        match self {
+            Finding::BadLines { count: 1, .. } => "isolated transmission error",
+            Finding::BadLines { .. } => "burst of noise on the capture link",
+            Finding::DuplicateLine { .. } => "line sent twice by the capture tool",
+            Finding::BitFlip { .. } => "bit rot or a marginal read of erased flash",
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Finding::BadLines {
+                first_line,
+                last_line,
+                ..
+            } if first_line == last_line => write!(f, "line {} is damaged", first_line),
+            Finding::BadLines {
+                first_line,
+                last_line,
+                count,
+            } => write!(
+                f,
+                "{} damaged lines between lines {} and {}",
+                count, first_line, last_line
+            ),
+            Finding::DuplicateLine { line } => {
+                write!(f, "line {} repeats line {}", line, line - 1)
+            }
+            Finding::BitFlip { addr, value } => {
+                write!(f, "0x{:02X} at 0x{:08X} inside a run of 0xFF", value, addr)
+            }
+        }
+    }
+}
+
+/// Everything [`diagnose`] found in a dump.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnosis {
+    /// Non-empty lines checked.
+    pub lines: usize,
+    /// Lines that don't parse or fail their checksum.
+    pub bad_lines: usize,
+    pub findings: Vec<Finding>,
+}
+
+impl Diagnosis {
+    /// Likely causes with the number of findings pointing at each, most
+    /// common first.
+    pub fn causes(&self) -> Vec<(&'static str, usize)> {
+        let mut causes = Vec::<(&'static str, usize)>::new();
+        for finding in &self.findings {
+            match causes.iter_mut().find(|(c, _)| *c == finding.cause()) {
+                Some((_, n)) => *n += 1,
+                None => causes.push((finding.cause(), 1)),
+            }
+        }
+        causes.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+        causes
+    }
+}
+
+/// Looks through an Intel HEX dump for the kinds of damage serial captures
+/// tend to suffer: clusters of bad lines, repeated lines and single-bit
+/// errors in erased flash.
+pub fn diagnose(text: &[u8]) -> Diagnosis {
+    let mut diagnosis = Diagnosis::default();
+    let mut bad = Vec::new();
+    let mut prev: Option<&[u8]> = None;
+    for (idx, line) in text.split(|&b| b == b'\n').enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        // Comments, such as provenance, aren't records
+        if line.iter().all(u8::is_ascii_whitespace) || line.starts_with(b";") {
+            continue;
+        }
+        diagnosis.lines += 1;
+        if !line_ok(line) {
+            bad.push(idx + 1);
+        }
+        if prev == Some(line) {
+            diagnosis
+                .findings
+                .push(Finding::DuplicateLine { line: idx + 1 });
+        }
+        prev = Some(line);
+    }
+    diagnosis.bad_lines = bad.len();
+
+    for cluster in bad.chunk_by(|a, b| b - a <= CLUSTER_GAP) {
+        diagnosis.findings.push(Finding::BadLines {
+            first_line: cluster[0],
+            last_line: cluster[cluster.len() - 1],
+            count: cluster.len(),
+        });
+    }
+
+    let parsed = Context::new(text)
+        .lenient(true)
+        .allow_missing_eof(true)
+        .trailing(TrailingPolicy::Ignore)
+        .parse();
+    if let Ok(hex) = parsed {
+        for range in hex.address_ranges() {
+            let bytes = hex.flatten_range(range, 0xFF);
+            for (i, &b) in bytes.iter().enumerate() {
+                let erased = |run: &[u8]| run.len() == ERASED_RUN && run.iter().all(|&b| b == 0xFF);
+                if (!b).count_ones() == 1
+                    && i >= ERASED_RUN
+                    && erased(&bytes[i - ERASED_RUN..i])
+                    && erased(bytes.get(i + 1..i + 1 + ERASED_RUN).unwrap_or_default())
+                {
+                    diagnosis.findings.push(Finding::BitFlip {
+                        addr: range.start + i as u32,
+                        value: b,
+                    });
+                }
+            }
+        }
+    }
+    diagnosis
+}
+
+/// What `doctor --repair` did about a damaged line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Repair {
+    /// Exactly one single-fault fix makes the line valid.
+    Fixed {
+        line: usize,
+        repaired: String,
+        how: String,
+    },
+    /// Several fixes are possible, so the line is left as it is.
+    Ambiguous { line: usize, candidates: usize },
+    /// No single-fault fix makes the line valid.
+    Unrepairable { line: usize },
+}
+
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Repair::Fixed {
+                line,
+                repaired,
+                how,
+            } => write!(f, "line {}: {}, now {}", line, how, repaired),
+            Repair::Ambiguous { line, candidates } => write!(
+                f,
+                "line {}: AMBIGUOUS, {} possible fixes, left as is",
+                line, candidates
+            ),
+            Repair::Unrepairable { line } => {
+                write!(f, "line {}: no single-fault fix found, left as is", line)
+            }
+        }
+    }
+}
+
+/// A dump with its damaged lines repaired where that could be done safely.
+#[derive(Debug, Clone, Default)]
+pub struct Repaired {
+    pub text: Vec<u8>,
+    pub repairs: Vec<Repair>,
+}
+
+/// Repairs damaged lines conservatively: a record whose checksum is off by
+/// a single flipped bit, or a line one character short. Only a line with
+/// exactly one plausible fix is changed; a data record's address has to
+/// carry on from the data record before it for the fix to be plausible.
+pub fn repair(text: &[u8]) -> Repaired {
+    let mut repaired = Repaired::default();
+    // Address the next data record is expected at
+    let mut next_addr = None;
+    for (idx, line) in text.split(|&b| b == b'\n').enumerate() {
+        let (line, cr) = match line.strip_suffix(b"\r") {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        let mut fixed = None;
+        if !line.iter().all(u8::is_ascii_whitespace) && !line.starts_with(b";") && !line_ok(line) {
+            let candidates = candidates(line)
+                .into_iter()
+                .filter(|(_, bytes)| plausible(bytes, next_addr))
+                .collect::<Vec<_>>();
+            let outcome = match &candidates[..] {
+                [(how, bytes)] => {
+                    let line = format!(":{}", bytes.iter().map(|b| format!("{:02X}", b)).join(""));
+                    fixed = Some(line.clone().into_bytes());
+                    Repair::Fixed {
+                        line: idx + 1,
+                        repaired: line,
+                        how: how.clone(),
+                    }
+                }
+                [] => Repair::Unrepairable { line: idx + 1 },
+                _ => Repair::Ambiguous {
+                    line: idx + 1,
+                    candidates: candidates.len(),
+                },
+            };
+            repaired.repairs.push(outcome);
+        }
+        let line = fixed.as_deref().unwrap_or(line);
+        if let Some(bytes) = record_bytes(line) {
+            next_addr = match bytes[3] {
+                0x00 if bytes_ok(&bytes) => {
+                    Some(u16::from_be_bytes([bytes[1], bytes[2]]).wrapping_add(bytes[0] as u16))
+                }
+                0x00 | 0x02 | 0x04 => None,
+                _ => next_addr,
+            };
+        }
+        if idx > 0 {
+            repaired.text.push(b'\n');
+        }
+        repaired.text.extend_from_slice(line);
+        if cr {
+            repaired.text.push(b'\r');
+        }
+    }
+    repaired
+}
+
+/// Every valid record one fault away from `line`, with how it was fixed.
+fn candidates(line: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut found = Vec::<(String, Vec<u8>)>::new();
+    let mut add = |how: String, bytes: Vec<u8>| {
+        if !found.iter().any(|(_, b)| *b == bytes) {
+            found.push((how, bytes));
+        }
+    };
+    if let Some(bytes) = record_bytes(line) {
+        for i in 0..bytes.len() {
+            for bit in 0..8 {
+                let mut flipped = bytes.clone();
+                flipped[i] ^= 1 << bit;
+                if bytes_ok(&flipped) {
+                    add(format!("flipped bit {} of byte {}", bit, i), flipped);
+                }
+            }
+        }
+    }
+    if line.len().is_multiple_of(2) {
+        // One character short: try a missing ':' and every hex digit in
+        // every position
+        if let Some(bytes) = record_bytes(&[b":", line].concat()).filter(|b| bytes_ok(b)) {
+            add("restored the missing ':'".to_string(), bytes);
+        }
+        for pos in 1..=line.len() {
+            for digit in b"0123456789ABCDEF" {
+                let longer = [&line[..pos], &[*digit], &line[pos..]].concat();
+                if let Some(bytes) = record_bytes(&longer).filter(|b| bytes_ok(b)) {
+                    add(
+                        format!("inserted '{}' at column {}", *digit as char, pos + 1),
+                        bytes,
+                    );
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Whether a repaired record looks like something a tool would write: a
+/// known type with the right length and, for data, the expected address.
+fn plausible(bytes: &[u8], next_addr: Option<u16>) -> bool {
+    let len = bytes[0];
+    match bytes[3] {
+        0x00 => next_addr.is_none_or(|next| u16::from_be_bytes([bytes[1], bytes[2]]) == next),
+        0x01 => len == 0,
+        0x02 | 0x04 => len == 2,
+        0x03 | 0x05 => len == 4,
+        _ => false,
+    }
+}
+
+/// The bytes of a record line, if it is ':' followed by pairs of hex
+/// digits making up at least a minimal record.
+fn record_bytes(line: &[u8]) -> Option<Vec<u8>> {
+    let digits = line.strip_prefix(b":")?;
+    if digits.len() < 10 || digits.len() % 2 != 0 || !digits.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|p| u8::from_str_radix(p, 16).ok())
+        })
+        .collect()
+}
+
+/// Whether the record's length field and checksum both add up.
+fn bytes_ok(bytes: &[u8]) -> bool {
+    bytes.len() == bytes[0] as usize + 5
+        && bytes.iter().fold(0u8, |acc, &b| acc.wrapping_add(b)) == 0
+}
+
+/// Whether `line` is a well-formed record with a matching checksum.
+fn line_ok(line: &[u8]) -> bool {
+    record_bytes(line).is_some_and(|bytes| bytes_ok(&bytes))
+}
@@ -0,0 +1,42 @@
+use eyre::eyre;
+
+use crate::hex::AddrRange;
+
+/// Where avr-gcc places `.eeprom` contents in the program address space.
+pub const AVR_EEPROM_OFFSET: u32 = 0x0081_0000;
+pub const DEFAULT_EEPROM_SIZE: u32 = 0x1_0000;
+
+/// The window of the address space that holds EEPROM contents rather than
+/// program memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EepromSpace {
+    pub range: AddrRange,
+}
+
+impl EepromSpace {
+    pub fn new(offset: u32, size: u32) -> eyre::Result<Self> {
+        let end = size
+            .checked_sub(1)
+            .and_then(|s| offset.checked_add(s))
+            .ok_or_else(|| {
+                eyre!(
+                    "Invalid EEPROM space of size 0x{:x} at 0x{:08x}",
+                    size,
+                    offset
+                )
+            })?;
+        Ok(Self {
+            range: AddrRange { start: offset, end },
+        })
+    }
+
+    /// Whether `range` holds EEPROM data, judged by where it starts.
+    pub fn holds(&self, range: AddrRange) -> bool {
+        self.range.contains(range.start)
+    }
+
+    /// Splits `ranges` into program memory ranges and EEPROM ranges.
+    pub fn partition(&self, ranges: Vec<AddrRange>) -> (Vec<AddrRange>, Vec<AddrRange>) {
+        ranges.into_iter().partition(|&r| !self.holds(r))
+    }
+}
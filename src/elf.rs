@@ -1,19 +1,22 @@
-use core::slice;
-use std::{fs::File, io::Write, mem};
+use std::{mem, str::FromStr};
 
-use color_eyre::eyre::{self, Context};
+use eyre::eyre;
+use eyre::Context;
+use itertools::Itertools;
 use object::elf;
+use object::endian::{U16, U32};
+use object::pod::bytes_of;
+use object::read::elf::{ElfFile, FileHeader, ProgramHeader as _, SectionHeader as _};
+use object::{Endianness, Object, ObjectSection};
 
 use crate::hex::{AddrRange, HexFile};
+use crate::memory_map::Region;
+use crate::range_set::AddrRangeSet;
+use crate::symbols::{self, SymbolKind};
 
-const FLASH_DATA_RANGE: AddrRange = AddrRange {
-    start: 0x0000_0000,
-    end: 0x0000_00BF,
-};
-const CODE_RANGE: AddrRange = AddrRange {
-    start: 0x0000_00C0,
-    end: 0x0003_FFFF,
-};
+/// Flash, starting at address 0. The vector table at its start becomes the
+/// `.flash` section and the rest `.text`.
+const FLASH_END: u32 = 0x0003_FFFF;
 const OPT_RANGE: AddrRange = AddrRange {
     start: 0x0101_0008,
     end: 0x0101_0033,
@@ -23,7 +26,103 @@ const SRAM_RANGE: AddrRange = AddrRange {
     end: 0x400F_FFFF,
 };
 
-const VECTOR_TABLE_END: u32 = 0xC0;
+pub const DEFAULT_VECTOR_TABLE_END: u32 = 0xC0;
+
+/// Target architecture recorded in the ELF header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Machine {
+    Arm,
+    RiscV32,
+    Avr,
+    Msp430,
+    Xtensa,
+    X86,
+}
+
+impl Machine {
+    fn e_machine(&self) -> u16 {
+        match self {
+            Machine::Arm => elf::EM_ARM,
+            Machine::RiscV32 => elf::EM_RISCV,
+            Machine::Avr => elf::EM_AVR,
+            Machine::Msp430 => elf::EM_MSP430,
+            Machine::Xtensa => elf::EM_XTENSA,
+            Machine::X86 => elf::EM_386,
+        }
+    }
+
+    /// `e_flags` for images of this machine when none are given: the EABI
+    /// version for ARM, with BE8 when big-endian, compressed instructions
+    /// with the soft-float ABI for RISC-V, as on most 32-bit cores, and the
+    /// ATmega `avr5` architecture for AVR, which its tools need to
+    /// disassemble anything.
+    pub fn default_flags(&self, endian: Endian) -> u32 {
+        match self {
+            Machine::Arm if endian == Endian::Big => elf::EF_ARM_EABI_VER5 | elf::EF_ARM_BE8,
+            Machine::Arm => elf::EF_ARM_EABI_VER5,
+            Machine::RiscV32 => elf::EF_RISCV_RVC | elf::EF_RISCV_FLOAT_ABI_SOFT,
+            Machine::Avr => elf::EF_AVR_ARCH_AVR5,
+            Machine::Msp430 | Machine::Xtensa | Machine::X86 => 0,
+        }
+    }
+}
+
+impl FromStr for Machine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "arm" => Ok(Machine::Arm),
+            "riscv32" | "riscv" => Ok(Machine::RiscV32),
+            "avr" => Ok(Machine::Avr),
+            "msp430" => Ok(Machine::Msp430),
+            "xtensa" => Ok(Machine::Xtensa),
+            "x86" | "i386" => Ok(Machine::X86),
+            _ => Err(format!(
+                "Unknown machine: {}, expected arm, riscv32, avr, msp430, xtensa or x86",
+                s
+            )),
+        }
+    }
+}
+
+/// Byte order of the ELF file, and so of its headers, symbols and the
+/// code it describes. Section contents are copied from the image as they
+/// are either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+impl Endian {
+    fn ei_data(&self) -> u8 {
+        match self {
+            Endian::Little => elf::ELFDATA2LSB,
+            Endian::Big => elf::ELFDATA2MSB,
+        }
+    }
+
+    fn endianness(&self) -> Endianness {
+        match self {
+            Endian::Little => Endianness::Little,
+            Endian::Big => Endianness::Big,
+        }
+    }
+}
+
+impl FromStr for Endian {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "little" | "le" => Ok(Endian::Little),
+            "big" | "be" => Ok(Endian::Big),
+            _ => Err(format!("Unknown byte order: {}, expected little or big", s)),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 enum SectionKind {
@@ -31,7 +130,6 @@ enum SectionKind {
     Code,
     Opt,
     Sram,
-    StrTab,
 }
 
 #[derive(Debug)]
@@ -41,70 +139,79 @@ struct SectionData {
     name: Vec<u8>,
 }
 
-fn range_to_section(range: AddrRange) -> SectionData {
-    let (kind, name) = if FLASH_DATA_RANGE.contains_range(range) {
+/// A name derived from the section's address, so that it stays the same
+/// when other ranges come and go. Writable sections get a `_data` suffix.
+fn pseudo_symbol(section: &SectionData) -> String {
+    match section.kind {
+        SectionKind::Sram => format!("seg_{:08x}_data", section.range.start),
+        _ => format!("seg_{:08x}", section.range.start),
+    }
+}
+
+fn range_to_section(range: AddrRange, vector_table_end: Option<u32>) -> eyre::Result<SectionData> {
+    let vector_table = vector_table_end
+        .and_then(|end| end.checked_sub(1))
+        .map(|end| AddrRange { start: 0, end });
+    let code = AddrRange {
+        start: vector_table_end.unwrap_or(0),
+        end: FLASH_END,
+    };
+    let (kind, name) = if vector_table.is_some_and(|vt| vt.contains_range(range)) {
         (SectionKind::Flash, b".flash".to_vec())
-    } else if CODE_RANGE.contains_range(range) {
+    } else if code.contains_range(range) {
         (SectionKind::Code, b".text".to_vec())
     } else if OPT_RANGE.contains_range(range) {
         (SectionKind::Opt, b".opt".to_vec())
     } else if SRAM_RANGE.contains_range(range) {
         (SectionKind::Sram, b".data".to_vec())
     } else {
-        unreachable!("Invalid range: {:?}", range);
+        return Err(eyre!("{} doesn't fit in any known memory region", range));
     };
-    SectionData { range, kind, name }
+    Ok(SectionData { range, kind, name })
 }
 
-const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
-
-#[derive(Debug, Default)]
-#[repr(C)]
-struct ElfIdent {
-    magic: [u8; 4],
-    class: u8,
-    endian: u8,
-    version: u8,
-    abi: u8,
-    abi_version: u8,
-    _pad: [u8; 7],
-}
-
-#[derive(Debug, Default)]
-#[repr(C)]
-struct ElfHeader {
-    ident: ElfIdent,
-    r#type: u16,
-    machine: u16,
-    version: u32,
-    entry: u32,
-    ph_off: u32,
-    sh_off: u32,
-    flags: u32,
-    hdr_size: u16,
-    ph_ent_size: u16,
-    ph_num: u16,
-    sh_ent_size: u16,
-    sh_num: u16,
-    sh_str_idx: u16,
+/// Splits `range` where it crosses from one region of a memory map into
+/// the next, giving each piece the section of the first region holding it.
+fn range_to_region_sections(
+    range: AddrRange,
+    regions: &[Region],
+) -> eyre::Result<Vec<SectionData>> {
+    let mut sections = Vec::new();
+    let mut rest = Some(range);
+    while let Some(range) = rest {
+        let region = regions
+            .iter()
+            .find(|r| r.range.contains(range.start))
+            .ok_or_else(|| eyre!("{} doesn't fit in any region of the memory map", range))?;
+        let piece = range.intersection(region.range).unwrap_or(range);
+        rest = region
+            .range
+            .end
+            .checked_add(1)
+            .and_then(|next| range.split(next))
+            .map(|(_, after)| after);
+        let kind = if region.access.exec {
+            SectionKind::Code
+        } else if region.access.write {
+            SectionKind::Sram
+        } else {
+            SectionKind::Flash
+        };
+        sections.push(SectionData {
+            range: piece,
+            kind,
+            name: region.section().into_bytes(),
+        });
+    }
+    Ok(sections)
 }
 
-#[derive(Debug, Default)]
-#[repr(C)]
-struct ProgramHeader {
-    r#type: u32,
-    offset: u32,
-    virt_addr: u32,
-    phy_addr: u32,
-    file_size: u32,
-    mem_size: u32,
-    flags: u32,
-    align: u32,
-}
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
 
+/// Fields of a section header, written out in the file's byte order by
+/// [`SectionEntry::header`].
 #[derive(Debug, Default)]
-#[repr(C)]
-struct SectionHeader {
+struct SectionEntry {
     name: u32,
     r#type: u32,
     flags: u32,
@@ -117,117 +224,480 @@ struct SectionHeader {
     ent_size: u32,
 }
 
-pub fn to_elf_file(hex: &HexFile, path: &str) -> eyre::Result<()> {
+impl SectionEntry {
+    fn header(&self, e: Endianness) -> elf::SectionHeader32<Endianness> {
+        elf::SectionHeader32 {
+            sh_name: U32::new(e, self.name),
+            sh_type: U32::new(e, self.r#type),
+            sh_flags: U32::new(e, self.flags),
+            sh_addr: U32::new(e, self.addr),
+            sh_offset: U32::new(e, self.offset),
+            sh_size: U32::new(e, self.size),
+            sh_link: U32::new(e, self.link),
+            sh_info: U32::new(e, self.info),
+            sh_addralign: U32::new(e, self.align),
+            sh_entsize: U32::new(e, self.ent_size),
+        }
+    }
+}
+
+/// Fields of a symbol table entry, written out in the file's byte order by
+/// [`SymbolEntry::sym`].
+#[derive(Debug, Default, Clone, Copy)]
+struct SymbolEntry {
+    name: u32,
+    value: u32,
+    size: u32,
+    info: u8,
+    shndx: u16,
+}
+
+impl SymbolEntry {
+    fn sym(&self, e: Endianness) -> elf::Sym32<Endianness> {
+        elf::Sym32 {
+            st_name: U32::new(e, self.name),
+            st_value: U32::new(e, self.value),
+            st_size: U32::new(e, self.size),
+            st_info: self.info,
+            st_other: elf::STV_DEFAULT,
+            st_shndx: U16::new(e, self.shndx),
+        }
+    }
+}
+
+/// Knobs for [`to_elf`].
+#[derive(Debug, Clone)]
+pub struct ElfOptions {
+    pub machine: Machine,
+    /// `e_flags` of the header, [`Machine::default_flags`] if `None`.
+    pub flags: Option<u32>,
+    pub endian: Endian,
+    /// Flash address holding the initial contents of the SRAM sections. When
+    /// set, program headers are emitted that load SRAM sections from here,
+    /// like a linker script's `> RAM AT> FLASH`.
+    pub data_lma: Option<u32>,
+    /// Where the vector table ends and code begins. Flash data below it goes
+    /// into `.flash` and the rest into `.text`; `None` puts it all in `.text`.
+    pub vector_table_end: Option<u32>,
+    /// Symbols added after those naming the sections, as globals in the
+    /// section holding their address, or absolute if none does.
+    pub symbols: Vec<symbols::Symbol>,
+    /// Memory map to lay sections out by instead of the built-in one. Data
+    /// goes into the section of the region holding it, executable regions
+    /// become code and writable ones SRAM; the vector table isn't split off.
+    pub regions: Vec<Region>,
+}
+
+impl Default for ElfOptions {
+    fn default() -> Self {
+        Self {
+            machine: Machine::Arm,
+            flags: None,
+            endian: Endian::Little,
+            data_lma: None,
+            vector_table_end: Some(DEFAULT_VECTOR_TABLE_END),
+            symbols: Vec::new(),
+            regions: Vec::new(),
+        }
+    }
+}
+
+/// Converts `hex` to an in-memory ELF executable, with one section per
+/// address range.
+pub fn to_elf(hex: &HexFile, opts: &ElfOptions) -> eyre::Result<Vec<u8>> {
+    if let Some(end) = opts.vector_table_end.filter(|&end| end > FLASH_END) {
+        return Err(eyre!(
+            "Vector table end 0x{:08X} is past the end of flash at 0x{:08X}",
+            end,
+            FLASH_END
+        ));
+    }
     let addr_ranges = hex.address_ranges();
     let mut sections = Vec::new();
     for range in addr_ranges {
-        if range.contains(VECTOR_TABLE_END) {
-            let (before, after) = range.split(VECTOR_TABLE_END);
-            sections.push(range_to_section(before));
-            sections.push(range_to_section(after));
+        if !opts.regions.is_empty() {
+            sections.extend(range_to_region_sections(range, &opts.regions)?);
+            continue;
+        }
+        let split = opts.vector_table_end.and_then(|end| range.split(end));
+        if let Some((before, after)) = split {
+            sections.push(range_to_section(before, opts.vector_table_end)?);
+            sections.push(range_to_section(after, opts.vector_table_end)?);
         } else {
-            sections.push(range_to_section(range));
+            sections.push(range_to_section(range, opts.vector_table_end)?);
         }
     }
 
-    let mut elf_data = Vec::new();
-
+    let e = opts.endian.endianness();
     // Create space for header
-    elf_data.resize(mem::size_of::<ElfHeader>(), 0);
+    let mut elf_data = vec![0; mem::size_of::<elf::FileHeader32<Endianness>>()];
 
     let entry_point = hex.start_addr().unwrap_or(0) & 0xFFFF_FFFE;
-    let mut hdr = ElfHeader::default();
-
-    // Fill out ident
-    hdr.ident.magic = ELF_MAGIC;
-    hdr.ident.class = elf::ELFCLASS32;
-    hdr.ident.endian = elf::ELFDATA2LSB;
-    hdr.ident.version = elf::EV_CURRENT;
-    hdr.ident.abi = elf::ELFOSABI_SYSV;
-    hdr.ident.abi_version = 0;
-
-    // Fill out parts of header we know
-    hdr.r#type = elf::ET_EXEC;
-    hdr.machine = elf::EM_ARM;
-    hdr.version = elf::EV_CURRENT as u32;
-    hdr.entry = entry_point;
-    hdr.hdr_size = mem::size_of::<ElfHeader>() as u16;
+
+    // Records can straddle section boundaries, so slice them rather than
+    // taking only the ones that fit
+    let contents = sections
+        .iter()
+        .map(|section| hex.extract(section.range).flatten(0).1)
+        .collect::<Vec<_>>();
+    check_coverage(hex, &sections, &contents)?;
 
     // Fill out the sections
     let mut section_offsets = Vec::new();
-    for section in &sections {
-        let off = elf_data.len();
-        let data = hex.data_in_range(section.range);
-        elf_data.extend_from_slice(&data);
-        section_offsets.push(off);
+    for data in &contents {
+        section_offsets.push(elf_data.len() as u32);
+        elf_data.extend_from_slice(data);
     }
 
-    // Create name section
-    sections.push(SectionData {
-        range: AddrRange { start: 0, end: 0 },
-        kind: SectionKind::StrTab,
-        name: b".shstrtab".to_vec(),
-    });
+    // Pseudo-symbols naming each section after its address
+    elf_data.resize(elf_data.len().next_multiple_of(4), 0);
+    let symtab_off = elf_data.len() as u32;
+    let mut strtab = vec![0]; // Initial null
+    elf_data.extend_from_slice(bytes_of(&elf::Sym32::<Endianness>::default()));
+    let mut add_symbol = |name: &str, sym: SymbolEntry| {
+        let sym = SymbolEntry {
+            name: strtab.len() as u32,
+            ..sym
+        };
+        strtab.extend_from_slice(name.as_bytes());
+        strtab.push(0); // Null terminator
+        elf_data.extend_from_slice(bytes_of(&sym.sym(e)));
+    };
+    // The section holding `addr`, or absolute if none does
+    let shndx = |addr: u32| {
+        sections
+            .iter()
+            .position(|section| section.range.contains(addr))
+            .map_or(elf::SHN_ABS, |i| i as u16 + 1)
+    };
+    for (i, section) in sections.iter().enumerate() {
+        let sym = SymbolEntry {
+            value: section.range.start,
+            size: section.range.size() as u32,
+            info: (elf::STB_GLOBAL << 4)
+                | match section.kind {
+                    SectionKind::Code => elf::STT_FUNC,
+                    _ => elf::STT_OBJECT,
+                },
+            shndx: i as u16 + 1,
+            ..Default::default()
+        };
+        add_symbol(&pseudo_symbol(section), sym);
+    }
+    // Anchors as a linker script defines them, `__text_start` at the first
+    // byte of the first `.text` section and `__text_end` just past the last
+    for (i, section) in sections.iter().enumerate() {
+        let name = String::from_utf8_lossy(&section.name)
+            .trim_start_matches('.')
+            .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+        let anchor = SymbolEntry {
+            info: (elf::STB_GLOBAL << 4) | elf::STT_NOTYPE,
+            shndx: i as u16 + 1,
+            ..Default::default()
+        };
+        if !sections[..i].iter().any(|s| s.name == section.name) {
+            let value = section.range.start;
+            add_symbol(
+                &format!("__{}_start", name),
+                SymbolEntry { value, ..anchor },
+            );
+        }
+        if !sections[i + 1..].iter().any(|s| s.name == section.name) {
+            let value = section.range.end.wrapping_add(1);
+            add_symbol(&format!("__{}_end", name), SymbolEntry { value, ..anchor });
+        }
+    }
+    if let Some(entry) = hex.start_addr() {
+        let sym = SymbolEntry {
+            // Keeping the Thumb bit, as ARM function symbols do
+            value: entry,
+            info: (elf::STB_GLOBAL << 4) | elf::STT_FUNC,
+            shndx: shndx(entry_point),
+            ..Default::default()
+        };
+        add_symbol("_entry", sym);
+    }
+    for symbol in &opts.symbols {
+        let sym = SymbolEntry {
+            value: symbol.addr,
+            size: symbol.size.unwrap_or(0),
+            info: (elf::STB_GLOBAL << 4)
+                | match symbol.kind {
+                    SymbolKind::Function => elf::STT_FUNC,
+                    SymbolKind::Object => elf::STT_OBJECT,
+                    SymbolKind::Other => elf::STT_NOTYPE,
+                },
+            shndx: shndx(symbol.addr),
+            ..Default::default()
+        };
+        add_symbol(&symbol.name, sym);
+    }
+    let symtab_size = elf_data.len() as u32 - symtab_off;
+    let strtab_off = elf_data.len() as u32;
+    elf_data.extend_from_slice(&strtab);
 
-    let start_off = elf_data.len();
-    section_offsets.push(elf_data.len());
+    // Create name section
+    let shstrtab_off = elf_data.len() as u32;
     elf_data.push(0); // Initial null
-
-    let mut name_section_len = 1;
-    let mut section_names = Vec::new();
-    for section in &sections {
-        section_names.push(elf_data.len() - start_off);
-        elf_data.extend_from_slice(&section.name);
+    let mut section_name = |name: &[u8]| {
+        let off = elf_data.len() as u32 - shstrtab_off;
+        elf_data.extend_from_slice(name);
         elf_data.push(0); // Null terminator
-        name_section_len += section.name.len() + 1;
-    }
-
-    hdr.sh_str_idx = sections.len() as u16 - 1;
-    sections.last_mut().unwrap().range.end = name_section_len as u32 - 1;
+        off
+    };
+    let section_names = sections
+        .iter()
+        .map(|section| section_name(&section.name))
+        .collect::<Vec<_>>();
+    let symtab_name = section_name(b".symtab");
+    let strtab_name = section_name(b".strtab");
+    let shstrtab_name = section_name(b".shstrtab");
+    let shstrtab_size = elf_data.len() as u32 - shstrtab_off;
 
-    // Fill up section headers
-    hdr.sh_ent_size = mem::size_of::<SectionHeader>() as u16;
-    hdr.sh_off = elf_data.len() as u32;
-    hdr.sh_num = sections.len() as u16;
+    // Fill up section headers, starting with the null section
+    let symtab_idx = sections.len() as u32 + 1;
+    let mut sec_hdrs = vec![SectionEntry::default()];
     for (i, section) in sections.iter().enumerate() {
-        let sec_hdr = SectionHeader {
-            name: section_names[i] as u32,
-            r#type: if matches!(section.kind, SectionKind::StrTab) {
-                elf::SHT_STRTAB
-            } else {
-                elf::SHT_PROGBITS
-            },
+        sec_hdrs.push(SectionEntry {
+            name: section_names[i],
+            r#type: elf::SHT_PROGBITS,
             flags: match section.kind {
                 SectionKind::Flash => elf::SHF_ALLOC,
                 SectionKind::Code => elf::SHF_ALLOC | elf::SHF_EXECINSTR,
                 SectionKind::Opt => elf::SHF_ALLOC,
                 SectionKind::Sram => elf::SHF_ALLOC | elf::SHF_WRITE,
-                SectionKind::StrTab => 0,
             },
-            addr: if matches!(section.kind, SectionKind::StrTab) {
-                0
+            addr: section.range.start,
+            offset: section_offsets[i],
+            size: section.range.size() as u32,
+            ..Default::default()
+        });
+    }
+    sec_hdrs.push(SectionEntry {
+        name: symtab_name,
+        r#type: elf::SHT_SYMTAB,
+        offset: symtab_off,
+        size: symtab_size,
+        link: symtab_idx + 1,
+        info: 1, // Every symbol past the null one is global
+        align: 4,
+        ent_size: mem::size_of::<elf::Sym32<Endianness>>() as u32,
+        ..Default::default()
+    });
+    sec_hdrs.push(SectionEntry {
+        name: strtab_name,
+        r#type: elf::SHT_STRTAB,
+        offset: strtab_off,
+        size: strtab.len() as u32,
+        ..Default::default()
+    });
+    sec_hdrs.push(SectionEntry {
+        name: shstrtab_name,
+        r#type: elf::SHT_STRTAB,
+        offset: shstrtab_off,
+        size: shstrtab_size,
+        ..Default::default()
+    });
+
+    let sh_off = elf_data.len() as u32;
+    let sh_num = sec_hdrs.len() as u16;
+    for sec_hdr in &sec_hdrs {
+        elf_data.extend_from_slice(bytes_of(&sec_hdr.header(e)));
+    }
+
+    // Load segments pairing SRAM sections with their flash copy
+    let (mut ph_off, mut ph_num, mut ph_ent_size) = (0, 0, 0);
+    if let Some(data_lma) = opts.data_lma {
+        ph_ent_size = mem::size_of::<elf::ProgramHeader32<Endianness>>() as u16;
+        ph_off = elf_data.len() as u32;
+        ph_num = sections.len() as u16;
+
+        let mut lma = data_lma;
+        for (i, section) in sections.iter().enumerate() {
+            let size = section.range.size() as u32;
+            let phy_addr = if matches!(section.kind, SectionKind::Sram) {
+                let phy_addr = lma;
+                lma = lma.checked_add(size).ok_or_else(|| {
+                    eyre!(
+                        "Data load address 0x{:08X} runs out of address space",
+                        data_lma
+                    )
+                })?;
+                phy_addr
             } else {
                 section.range.start
-            },
-            offset: section_offsets[i] as u32,
-            size: section.range.size(),
-            ..Default::default()
-        };
-        let sec_hdr_slice = ob_to_slice(&sec_hdr);
-        elf_data.extend_from_slice(sec_hdr_slice);
+            };
+            let prog_hdr = elf::ProgramHeader32 {
+                p_type: U32::new(e, elf::PT_LOAD),
+                p_offset: U32::new(e, section_offsets[i]),
+                p_vaddr: U32::new(e, section.range.start),
+                p_paddr: U32::new(e, phy_addr),
+                p_filesz: U32::new(e, size),
+                p_memsz: U32::new(e, size),
+                p_flags: U32::new(
+                    e,
+                    match section.kind {
+                        SectionKind::Code => elf::PF_R | elf::PF_X,
+                        SectionKind::Sram => elf::PF_R | elf::PF_W,
+                        _ => elf::PF_R,
+                    },
+                ),
+                p_align: U32::new(e, 1),
+            };
+            elf_data.extend_from_slice(bytes_of(&prog_hdr));
+        }
     }
 
-    let hdr_slice = ob_to_slice(&hdr);
+    let hdr = elf::FileHeader32 {
+        e_ident: elf::Ident {
+            magic: ELF_MAGIC,
+            class: elf::ELFCLASS32,
+            data: opts.endian.ei_data(),
+            version: elf::EV_CURRENT,
+            os_abi: elf::ELFOSABI_SYSV,
+            abi_version: 0,
+            padding: [0; 7],
+        },
+        e_type: U16::new(e, elf::ET_EXEC),
+        e_machine: U16::new(e, opts.machine.e_machine()),
+        e_version: U32::new(e, elf::EV_CURRENT as u32),
+        e_entry: U32::new(e, entry_point),
+        e_phoff: U32::new(e, ph_off),
+        e_shoff: U32::new(e, sh_off),
+        e_flags: U32::new(
+            e,
+            opts.flags
+                .unwrap_or_else(|| opts.machine.default_flags(opts.endian)),
+        ),
+        e_ehsize: U16::new(e, mem::size_of::<elf::FileHeader32<Endianness>>() as u16),
+        e_phentsize: U16::new(e, ph_ent_size),
+        e_phnum: U16::new(e, ph_num),
+        e_shentsize: U16::new(e, mem::size_of::<elf::SectionHeader32<Endianness>>() as u16),
+        e_shnum: U16::new(e, sh_num),
+        e_shstrndx: U16::new(e, sh_num - 1),
+    };
+    let hdr_slice = bytes_of(&hdr);
     elf_data[..hdr_slice.len()].copy_from_slice(hdr_slice);
 
-    let mut file = File::create(path).with_context(|| format!("Opening {}", path))?;
-    file.write_all(&elf_data)?;
+    Ok(elf_data)
+}
 
-    Ok(())
+/// Provenance recorded in an ELF file, kept when it is converted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ElfMetadata {
+    /// Strings from the `.comment` section, usually compiler versions.
+    pub comments: Vec<String>,
+    /// The GNU build ID note.
+    pub build_id: Option<Vec<u8>>,
+}
+
+/// Whether `data` looks like an ELF file.
+pub fn is_elf(data: &[u8]) -> bool {
+    data.starts_with(&ELF_MAGIC)
 }
 
-fn ob_to_slice<T: Sized>(t: &T) -> &[u8] {
-    let len = mem::size_of::<T>();
-    let ptr: *const T = t;
-    unsafe { slice::from_raw_parts(ptr as *const u8, len) }
+/// Loads an ELF file's `PT_LOAD` segments at their physical addresses, as
+/// `objcopy -O ihex` does, along with its entry point and metadata.
+pub fn from_elf(data: &[u8]) -> eyre::Result<(HexFile, ElfMetadata)> {
+    match data.get(4).copied() {
+        Some(elf::ELFCLASS32) => load_elf::<elf::FileHeader32<Endianness>>(data),
+        Some(elf::ELFCLASS64) => load_elf::<elf::FileHeader64<Endianness>>(data),
+        _ => Err(eyre!("Not an ELF file")),
+    }
+}
+
+fn load_elf<Elf: FileHeader<Endian = Endianness>>(
+    data: &[u8],
+) -> eyre::Result<(HexFile, ElfMetadata)> {
+    let file = ElfFile::<Elf>::parse(data)?;
+    let endian = file.endian();
+    let mut hex = HexFile::new();
+    for (idx, segment) in file.raw_segments().iter().enumerate() {
+        if segment.p_type(endian) != elf::PT_LOAD || segment.p_filesz(endian).into() == 0 {
+            continue;
+        }
+        let addr = segment.p_paddr(endian).into();
+        let addr = u32::try_from(addr)
+            .map_err(|_| eyre!("Segment {} at 0x{:X} is past 4GiB", idx, addr))?;
+        let bytes = segment
+            .data(endian, data)
+            .map_err(|_| eyre!("Segment {} lies outside the file", idx))?;
+        hex.add_data(addr, bytes.to_vec())
+            .with_context(|| format!("Placing segment {}", idx))?;
+    }
+    // Without program headers, fall back to the allocated sections
+    if hex.data().is_empty() {
+        let sections = file.raw_header().sections(endian, data)?;
+        for header in sections.iter() {
+            if header.sh_type(endian) != elf::SHT_PROGBITS
+                || header.sh_flags(endian).into() & elf::SHF_ALLOC as u64 == 0
+            {
+                continue;
+            }
+            let name = String::from_utf8_lossy(sections.section_name(endian, header)?);
+            let addr = header.sh_addr(endian).into();
+            let addr = u32::try_from(addr)
+                .map_err(|_| eyre!("Section {} at 0x{:X} is past 4GiB", name, addr))?;
+            let bytes = header
+                .data(endian, data)
+                .map_err(|_| eyre!("Section {} lies outside the file", name))?;
+            hex.add_data(addr, bytes.to_vec())
+                .with_context(|| format!("Placing section {}", name))?;
+        }
+    }
+    let entry = u32::try_from(file.entry())
+        .map_err(|_| eyre!("Entry point 0x{:X} is past 4GiB", file.entry()))?;
+    hex.set_start_addr((entry != 0).then_some(entry));
+
+    let comments = match file.section_by_name(".comment") {
+        Some(section) => section
+            .data()?
+            .split(|&b| b == 0)
+            .filter(|s| !s.is_empty())
+            .map(|s| String::from_utf8_lossy(s).into_owned())
+            .collect(),
+        None => Vec::new(),
+    };
+    let metadata = ElfMetadata {
+        comments,
+        build_id: file.build_id()?.map(<[u8]>::to_vec),
+    };
+    Ok((hex, metadata))
+}
+
+/// Makes sure every byte of `hex` ends up in exactly one section, so that
+/// the conversion loses nothing.
+fn check_coverage(
+    hex: &HexFile,
+    sections: &[SectionData],
+    contents: &[Vec<u8>],
+) -> eyre::Result<()> {
+    let mut covered = sections.iter().map(|s| s.range).collect::<Vec<_>>();
+    covered.sort_by_key(|r| r.start);
+    if let Some((a, b)) = covered
+        .iter()
+        .tuple_windows()
+        .find(|(a, b)| b.start <= a.end)
+    {
+        return Err(eyre!("Sections at {} and {} overlap", a, b));
+    }
+    for (section, data) in sections.iter().zip(contents) {
+        if data.len() as u64 != section.range.size() {
+            return Err(eyre!(
+                "Section at {} holds 0x{:X} bytes instead of 0x{:X}",
+                section.range,
+                data.len(),
+                section.range.size()
+            ));
+        }
+    }
+    let uncovered = hex
+        .address_ranges()
+        .into_iter()
+        .collect::<AddrRangeSet>()
+        .difference(&covered.into_iter().collect());
+    if !uncovered.is_empty() {
+        return Err(eyre!("No section holds {}", uncovered));
+    }
+    Ok(())
 }
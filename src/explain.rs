@@ -0,0 +1,170 @@
+use std::io::{self, BufRead};
+
+use crate::hex::record_type_name;
+
+/// Walks through the first `count` records of `reader`, breaking every line
+/// into its fields and describing what it does. Malformed lines are
+/// described rather than rejected, so this also works on broken files.
+pub fn explain<R: BufRead, W: io::Write>(reader: R, count: usize, mut w: W) -> eyre::Result<()> {
+    // Upper address bits set by the last extended address record
+    let mut base = 0u32;
+    let mut explained = 0;
+    for (idx, line) in reader.split(b'\n').enumerate() {
+        if explained == count {
+            break;
+        }
+        let line = line?;
+        let line = String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(&line)).into_owned();
+        if line.trim().is_empty() {
+            continue;
+        }
+        explained += 1;
+        writeln!(w, "Line {}: {}", idx + 1, line)?;
+        for note in explain_line(&line, &mut base) {
+            writeln!(w, "  {}", note)?;
+        }
+        writeln!(w)?;
+    }
+    if explained == 0 {
+        writeln!(w, "The file holds no records.")?;
+    }
+    Ok(())
+}
+
+/// The field breakdown and narration for one line, updating `base` when the
+/// line changes the extended address.
+fn explain_line(line: &str, base: &mut u32) -> Vec<String> {
+    let Some(digits) = line.strip_prefix(':') else {
+        return vec![
+            "=> Not a record: every record starts with ':', which tools use to tell records \
+             from comments and noise."
+                .to_string(),
+        ];
+    };
+    if digits.len() % 2 != 0 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return vec![
+            "=> Malformed: after the ':' a record is pairs of hex digits, one pair per byte."
+                .to_string(),
+        ];
+    }
+    let bytes = (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+        .collect::<Vec<_>>();
+    if bytes.len() < 5 {
+        return vec![format!(
+            "=> Malformed: a record has at least 5 bytes (count, address, type, checksum) \
+             but this one has {}.",
+            bytes.len()
+        )];
+    }
+
+    let len = bytes[0] as usize;
+    let addr = u16::from_be_bytes([bytes[1], bytes[2]]);
+    let kind = bytes[3];
+    let payload = &bytes[4..bytes.len() - 1];
+    let cksum = bytes[bytes.len() - 1];
+    let expected = bytes[..bytes.len() - 1]
+        .iter()
+        .fold(0u8, |acc, &b| acc.wrapping_add(b))
+        .wrapping_neg();
+
+    let mut notes = vec![
+        format!("{:<12}start code", ":"),
+        format!("{:<12}byte count: {} data byte(s)", &digits[0..2], len),
+        format!("{:<12}address field: 0x{:04X}", &digits[2..6], addr),
+        format!(
+            "{:<12}record type 0x{:02X}, {}",
+            &digits[6..8],
+            kind,
+            record_type_name(kind)
+        ),
+    ];
+    if !payload.is_empty() {
+        let shown = &digits[8..digits.len() - 2];
+        let shown = if shown.len() > 10 {
+            format!("{}..", &shown[..8])
+        } else {
+            shown.to_string()
+        };
+        notes.push(format!("{:<12}data, {} byte(s)", shown, payload.len()));
+    }
+    if cksum == expected {
+        notes.push(format!(
+            "{:<12}checksum: OK, all bytes of the record add up to 0x00",
+            &digits[digits.len() - 2..]
+        ));
+    } else {
+        notes.push(format!(
+            "{:<12}checksum: WRONG, should be 0x{:02X} for the bytes to add up to 0x00",
+            &digits[digits.len() - 2..],
+            expected
+        ));
+    }
+    if payload.len() != len {
+        notes.push(format!(
+            "=> Malformed: the byte count says {} data byte(s) but the record holds {}.",
+            len,
+            payload.len()
+        ));
+        return notes;
+    }
+
+    let word = |i: usize| u16::from_be_bytes([payload[i], payload[i + 1]]);
+    let narration = match (kind, len) {
+        (0x00, 0) => "=> An empty data record, which holds nothing and is skipped.".to_string(),
+        (0x00, _) => {
+            let start = base.wrapping_add(addr as u32);
+            format!(
+                "=> Data record: puts {} byte(s) at 0x{:08X}-0x{:08X} (0x{:08X} from the \
+                 extended address plus the 0x{:04X} address field).",
+                len,
+                start,
+                start.wrapping_add(len as u32 - 1),
+                *base,
+                addr
+            )
+        }
+        (0x01, _) => "=> End of file: nothing after this record is part of the image.".to_string(),
+        (0x02, 2) => {
+            *base = (word(0) as u32) << 4;
+            format!(
+                "=> Extended segment address: segment 0x{:04X}, so following data records \
+                 are placed from 0x{:08X} (the segment times 16).",
+                word(0),
+                *base
+            )
+        }
+        (0x03, 4) => format!(
+            "=> Start segment address: execution starts at CS:IP = 0x{:04X}:0x{:04X}.",
+            word(0),
+            word(2)
+        ),
+        (0x04, 2) => {
+            *base = (word(0) as u32) << 16;
+            format!(
+                "=> Extended linear address: switches the upper 16 address bits to 0x{:04X}, \
+                 so following data records are at 0x{:04X}xxxx.",
+                word(0),
+                word(0)
+            )
+        }
+        (0x05, 4) => format!(
+            "=> Start linear address: execution starts at 0x{:08X}.",
+            u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]])
+        ),
+        (0x02 | 0x04, _) => format!(
+            "=> Malformed: {} records hold 2 data bytes, not {}.",
+            record_type_name(kind),
+            len
+        ),
+        (0x03 | 0x05, _) => format!(
+            "=> Malformed: {} records hold 4 data bytes, not {}.",
+            record_type_name(kind),
+            len
+        ),
+        _ => "=> Unknown record type: Intel HEX only defines types 0x00 to 0x05.".to_string(),
+    };
+    notes.push(narration);
+    notes
+}
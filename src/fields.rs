@@ -0,0 +1,63 @@
+use std::str::FromStr;
+
+use crate::hex::{HexFile, ValueFormat};
+
+/// How the bytes of a [`Field`] are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldFormat {
+    /// Hex digits in address order.
+    Hex,
+    /// Text, up to the first NUL.
+    Ascii,
+    /// A little-endian value of the given type.
+    Value(ValueFormat),
+}
+
+impl FromStr for FieldFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hex" => Ok(FieldFormat::Hex),
+            "ascii" => Ok(FieldFormat::Ascii),
+            _ => s
+                .parse()
+                .map(FieldFormat::Value)
+                .map_err(|_| format!("Unknown field format: {}", s)),
+        }
+    }
+}
+
+/// A named piece of metadata stored in the image, such as a version string
+/// or a build stamp.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub addr: u32,
+    pub len: u32,
+    pub format: FieldFormat,
+}
+
+impl Field {
+    /// The field's value in `hex`, or `None` if any of its bytes are missing.
+    pub fn read(&self, hex: &HexFile) -> Option<String> {
+        let bytes = (0..self.len)
+            .map(|i| {
+                let addr = self.addr.checked_add(i)?;
+                hex.data_containing(addr)?.get_byte(addr)
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(match self.format {
+            FieldFormat::Hex => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+            FieldFormat::Ascii => {
+                let text = bytes.split(|&b| b == 0).next().unwrap_or_default();
+                format!("{:?}", String::from_utf8_lossy(text))
+            }
+            FieldFormat::Value(value) => {
+                let mut bytes = bytes;
+                bytes.reverse();
+                value.decode(&bytes)
+            }
+        })
+    }
+}
@@ -0,0 +1,177 @@
+use std::{collections::BTreeMap, fmt, time::Duration};
+
+use eyre::eyre;
+
+use crate::hex::{AddrRange, HexFile};
+
+/// What programming an image does to a flash page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageState {
+    /// Every byte of the page is in the image and some aren't erased.
+    Programmed,
+    /// Part of the page is in the image and some of it isn't erased.
+    Partial,
+    /// The image only holds erased bytes for the page, so it needs an erase
+    /// but no programming.
+    ErasedOnly,
+}
+
+impl fmt::Display for PageState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PageState::Programmed => "programmed",
+            PageState::Partial => "partial",
+            PageState::ErasedOnly => "erased-only",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A flash page touched by the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    pub range: AddrRange,
+    /// Bytes of the page present in the image.
+    pub data_bytes: u64,
+    /// Bytes of the page that differ from the erased value.
+    pub programmed_bytes: u64,
+    pub state: PageState,
+}
+
+/// Pages touched by an image, and the operations needed to flash it.
+#[derive(Debug, Clone, Default)]
+pub struct FlashReport {
+    pub pages: Vec<Page>,
+}
+
+impl FlashReport {
+    /// Every touched page is erased once.
+    pub fn erase_ops(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Pages holding anything but erased bytes are programmed once.
+    pub fn program_ops(&self) -> usize {
+        self.pages
+            .iter()
+            .filter(|p| p.state != PageState::ErasedOnly)
+            .count()
+    }
+
+    pub fn count(&self, state: PageState) -> usize {
+        self.pages.iter().filter(|p| p.state == state).count()
+    }
+}
+
+/// Works out which pages of `page_size` bytes, aligned to address 0,
+/// flashing `hex` touches, with `erased` being the value of an erased byte.
+pub fn simulate(hex: &HexFile, page_size: u32, erased: u8) -> eyre::Result<FlashReport> {
+    if page_size == 0 {
+        return Err(eyre!("Page size must not be 0"));
+    }
+    let page_size = page_size as u64;
+    // Page index to (data bytes, programmed bytes)
+    let mut pages = BTreeMap::<u64, (u64, u64)>::new();
+    for range in hex.address_ranges() {
+        let bytes = hex.data_in_range(range);
+        for (i, &b) in bytes.iter().enumerate() {
+            let page = pages
+                .entry((range.start as u64 + i as u64) / page_size)
+                .or_default();
+            page.0 += 1;
+            page.1 += (b != erased) as u64;
+        }
+    }
+    let pages = pages
+        .into_iter()
+        .map(|(idx, (data_bytes, programmed_bytes))| {
+            let start = idx * page_size;
+            let end = (start + page_size - 1).min(u32::MAX as u64);
+            let range = AddrRange {
+                start: start as u32,
+                end: end as u32,
+            };
+            let state = if programmed_bytes == 0 {
+                PageState::ErasedOnly
+            } else if data_bytes == range.size() {
+                PageState::Programmed
+            } else {
+                PageState::Partial
+            };
+            Page {
+                range,
+                data_bytes,
+                programmed_bytes,
+                state,
+            }
+        })
+        .collect();
+    Ok(FlashReport { pages })
+}
+
+/// How fast a programmer gets an image into flash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgrammerSpeed {
+    pub program_bytes_per_sec: u32,
+    pub erase_ms_per_page: u32,
+    /// Fixed cost of transferring and handling one record.
+    pub record_overhead_us: u32,
+}
+
+/// Expected time to program an image, by phase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Estimate {
+    pub pages: usize,
+    pub data_bytes: u64,
+    pub records: usize,
+    pub erase: Duration,
+    pub program: Duration,
+    pub overhead: Duration,
+}
+
+impl Estimate {
+    pub fn total(&self) -> Duration {
+        self.erase + self.program + self.overhead
+    }
+}
+
+/// Estimates programming `hex` as `records` data records: every page it
+/// touches is erased, every byte it holds is programmed and every record
+/// costs the per-record overhead.
+pub fn estimate(
+    hex: &HexFile,
+    report: &FlashReport,
+    records: usize,
+    speed: &ProgrammerSpeed,
+) -> eyre::Result<Estimate> {
+    if speed.program_bytes_per_sec == 0 {
+        return Err(eyre!("Programming speed must not be 0"));
+    }
+    let data_bytes = hex.address_ranges().iter().map(|r| r.size()).sum::<u64>();
+    Ok(Estimate {
+        pages: report.erase_ops(),
+        data_bytes,
+        records,
+        erase: Duration::from_millis(speed.erase_ms_per_page as u64 * report.erase_ops() as u64),
+        program: Duration::from_secs_f64(data_bytes as f64 / speed.program_bytes_per_sec as f64),
+        overhead: Duration::from_micros(speed.record_overhead_us as u64 * records as u64),
+    })
+}
+
+/// Number of data records needed to hold `hex` if every record is filled
+/// up to `record_len` bytes, records only breaking at gaps and at 64 KiB
+/// segments.
+pub fn coalesced_records(hex: &HexFile, record_len: u8) -> usize {
+    let record_len = record_len.max(1) as u64;
+    let mut records = 0;
+    for range in hex.address_ranges() {
+        let mut addr = range.start as u64;
+        while addr <= range.end as u64 {
+            let segment_end = (addr | 0xFFFF).min(range.end as u64);
+            let len = segment_end - addr + 1;
+            records += len.div_ceil(record_len) as usize;
+            addr = segment_end + 1;
+        }
+    }
+    records
+}
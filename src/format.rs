@@ -0,0 +1,206 @@
+//! File formats images can be read from and written to, behind one
+//! interface so that other crates can add their own.
+//!
+//! A [`Registry`] holds the formats a program knows about. Start from
+//! [`Registry::builtin`] and [`Registry::register`] any more, such as a
+//! vendor's proprietary container, then let [`Registry::detect`] pick the
+//! format of an input.
+
+use std::io;
+use std::path::Path;
+
+use eyre::eyre;
+
+use crate::cache;
+use crate::hex::{Context, HexFile, WriteOptions};
+
+/// A file format images can be read from and, optionally, written to.
+pub trait FormatProvider: Send + Sync {
+    /// Short name to select the format by, such as `ihex`.
+    fn name(&self) -> &str;
+
+    /// One-line description for listings.
+    fn description(&self) -> &str;
+
+    /// File extensions the format usually goes by, without the dot.
+    fn extensions(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Whether a file named `path` starting with `head` is in this format.
+    /// `head` holds up to the first 64 bytes.
+    fn detect(&self, path: &Path, head: &[u8]) -> bool;
+
+    /// Reads an image from the whole contents of a file.
+    fn read(&self, data: &[u8]) -> eyre::Result<HexFile>;
+
+    /// Whether [`FormatProvider::write`] is supported.
+    fn can_write(&self) -> bool {
+        false
+    }
+
+    /// Writes `hex` in this format.
+    fn write(&self, hex: &HexFile, w: &mut dyn io::Write) -> eyre::Result<()> {
+        let _ = (hex, w);
+        Err(eyre!("Writing {} isn't supported", self.name()))
+    }
+}
+
+/// Number of leading bytes handed to [`FormatProvider::detect`].
+pub const HEAD_LEN: usize = 64;
+
+/// The formats known to a program, in the order they're tried.
+#[derive(Default)]
+pub struct Registry {
+    formats: Vec<Box<dyn FormatProvider>>,
+}
+
+impl Registry {
+    /// A registry without any formats.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry with the formats this crate implements.
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(IntelHex);
+        #[cfg(feature = "elf")]
+        registry.register(Elf);
+        registry.register(Cache);
+        registry
+    }
+
+    /// Adds `format`, tried before those registered earlier so that it can
+    /// take over files they would claim.
+    pub fn register(&mut self, format: impl FormatProvider + 'static) -> &mut Self {
+        self.formats.insert(0, Box::new(format));
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn FormatProvider> {
+        self.formats.iter().map(|f| f.as_ref())
+    }
+
+    /// Looks up a format by name, ignoring case.
+    pub fn get(&self, name: &str) -> Option<&dyn FormatProvider> {
+        self.iter().find(|f| f.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The first format claiming a file named `path` starting with `head`.
+    pub fn detect(&self, path: &Path, head: &[u8]) -> Option<&dyn FormatProvider> {
+        self.iter().find(|f| f.detect(path, head))
+    }
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+/// Intel HEX text.
+pub struct IntelHex;
+
+impl FormatProvider for IntelHex {
+    fn name(&self) -> &str {
+        "ihex"
+    }
+
+    fn description(&self) -> &str {
+        "Intel HEX records"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["hex", "ihex", "ihx"]
+    }
+
+    fn detect(&self, path: &Path, head: &[u8]) -> bool {
+        head.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b':')
+            || has_extension(path, self.extensions())
+    }
+
+    fn read(&self, data: &[u8]) -> eyre::Result<HexFile> {
+        Context::new(data).parse()
+    }
+
+    fn can_write(&self) -> bool {
+        true
+    }
+
+    fn write(&self, hex: &HexFile, w: &mut dyn io::Write) -> eyre::Result<()> {
+        hex.write(w, &WriteOptions::default())
+    }
+}
+
+/// ELF executables, loading their segments.
+#[cfg(feature = "elf")]
+pub struct Elf;
+
+#[cfg(feature = "elf")]
+impl FormatProvider for Elf {
+    fn name(&self) -> &str {
+        "elf"
+    }
+
+    fn description(&self) -> &str {
+        "ELF executable, by its loadable segments"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["elf", "axf", "out"]
+    }
+
+    fn detect(&self, _path: &Path, head: &[u8]) -> bool {
+        crate::elf::is_elf(head)
+    }
+
+    fn read(&self, data: &[u8]) -> eyre::Result<HexFile> {
+        Ok(crate::elf::from_elf(data)?.0)
+    }
+
+    fn can_write(&self) -> bool {
+        true
+    }
+
+    fn write(&self, hex: &HexFile, w: &mut dyn io::Write) -> eyre::Result<()> {
+        w.write_all(&crate::elf::to_elf(
+            hex,
+            &crate::elf::ElfOptions::default(),
+        )?)?;
+        Ok(())
+    }
+}
+
+/// The binary cache of [`crate::cache`].
+pub struct Cache;
+
+impl FormatProvider for Cache {
+    fn name(&self) -> &str {
+        "hexcache"
+    }
+
+    fn description(&self) -> &str {
+        "hex-reader binary cache of a parsed image"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["hexcache"]
+    }
+
+    fn detect(&self, _path: &Path, head: &[u8]) -> bool {
+        cache::is_cache(head)
+    }
+
+    fn read(&self, data: &[u8]) -> eyre::Result<HexFile> {
+        Ok(cache::read(data)?.1)
+    }
+
+    fn can_write(&self) -> bool {
+        true
+    }
+
+    fn write(&self, hex: &HexFile, w: &mut dyn io::Write) -> eyre::Result<()> {
+        cache::write(w, hex, &[])
+    }
+}
@@ -1,21 +1,41 @@
-use color_eyre::eyre;
 use eyre::eyre;
-use itertools::Itertools;
-use std::{fmt, io, ops::Not, str::from_utf8};
+use itertools::{Either, Itertools};
+use std::{
+    borrow::Cow,
+    fmt,
+    hash::{Hash, Hasher},
+    io::{self, BufRead},
+    mem,
+    ops::{Not, RangeInclusive},
+    str::FromStr,
+};
 
-#[derive(Debug)]
+use crate::range_set::AddrRangeSet;
+
+/// Bytes per data record when records have to be split up.
+const DEFAULT_RECORD_LEN: usize = 16;
+
+/// Shortest run of one byte [`HexFile::compress`] splits out of a record
+/// holding other bytes too.
+pub const MIN_RUN: usize = 64;
+
+/// An in-memory Intel HEX image: data records sorted by address, plus the
+/// optional start address.
+#[derive(Debug, Clone)]
 pub struct HexFile {
-    start: Option<StartSegmentAddr>,
+    start: Option<StartAddr>,
     data: Vec<Data>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// An inclusive range of addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct AddrRange {
     pub start: u32,
     pub end: u32,
 }
 
 impl AddrRange {
+    /// Whether the whole range lies below `addr`.
     pub fn is_before(&self, addr: u32) -> bool {
         self.start < addr && self.end < addr
     }
@@ -28,16 +48,45 @@ impl AddrRange {
         self.start <= range.start && self.end >= range.end
     }
 
-    pub fn overlaps_range(&self, range: AddrRange) -> bool {
-        self.contains(range.start) || self.contains(range.end)
+    /// Whether the two ranges share at least one address.
+    pub fn overlaps(&self, range: AddrRange) -> bool {
+        self.start <= range.end && range.start <= self.end
+    }
+
+    /// The addresses in both ranges, or `None` if they don't overlap.
+    pub fn intersection(&self, range: AddrRange) -> Option<AddrRange> {
+        self.overlaps(range).then(|| AddrRange {
+            start: self.start.max(range.start),
+            end: self.end.min(range.end),
+        })
+    }
+
+    /// The single range covering both, or `None` if there's a gap between
+    /// them.
+    pub fn union(&self, range: AddrRange) -> Option<AddrRange> {
+        let (first, second) = if self.start <= range.start {
+            (self, &range)
+        } else {
+            (&range, self)
+        };
+        if first
+            .end
+            .checked_add(1)
+            .is_some_and(|next| second.start > next)
+        {
+            return None;
+        }
+        Some(AddrRange {
+            start: first.start,
+            end: first.end.max(second.end),
+        })
     }
 
-    pub fn split(&self, at: u32) -> (AddrRange, AddrRange) {
-        if at <= self.start || at >= self.end {
-            panic!(
-                "Cannot split at {} on range {}-{}",
-                at, self.start, self.end
-            );
+    /// Splits the range into `start..at` and `at..=end`, or returns `None`
+    /// if `at` isn't strictly inside the range.
+    pub fn split(&self, at: u32) -> Option<(AddrRange, AddrRange)> {
+        if at <= self.start || at > self.end {
+            return None;
         }
         let before = AddrRange {
             start: self.start,
@@ -47,27 +96,49 @@ impl AddrRange {
             start: at,
             end: self.end,
         };
-        (before, after)
+        Some((before, after))
     }
 
-    pub fn size(&self) -> u32 {
-        self.end - self.start + 1
+    /// Number of addresses in the range.
+    pub fn size(&self) -> u64 {
+        (self.end - self.start) as u64 + 1
     }
 
-    pub fn transpose(&self, dest: u32) -> Self {
-        if dest >= self.start {
-            let diff = dest - self.start;
-            Self {
-                start: dest,
-                end: self.end + diff,
+    /// The parts of the range not covered by `others`, which must be sorted.
+    pub fn subtract(&self, others: &[AddrRange]) -> Vec<AddrRange> {
+        let mut result = Vec::new();
+        let mut next = Some(self.start);
+        for o in others
+            .iter()
+            .filter(|o| o.end >= self.start && o.start <= self.end)
+        {
+            let Some(start) = next else { break };
+            if o.start > start {
+                result.push(AddrRange {
+                    start,
+                    end: o.start - 1,
+                });
             }
-        } else {
-            let diff = self.start - dest;
-            Self {
-                start: dest,
-                end: self.end - diff,
+            if o.end >= start {
+                next = o.end.checked_add(1);
             }
         }
+        if let Some(start) = next.filter(|&start| start <= self.end) {
+            result.push(AddrRange {
+                start,
+                end: self.end,
+            });
+        }
+        result
+    }
+
+    /// The same-sized range starting at `dest`, or `None` if it would run
+    /// past the end of the address space.
+    pub fn transpose(&self, dest: u32) -> Option<Self> {
+        Some(Self {
+            start: dest,
+            end: dest.checked_add(self.end - self.start)?,
+        })
     }
 }
 
@@ -77,377 +148,2748 @@ impl fmt::Display for AddrRange {
     }
 }
 
+/// Images are equal when they hold the same bytes at the same addresses and
+/// have the same start address, however the data is split into records and
+/// whether the start address is given as a segment or a linear one.
+impl PartialEq for HexFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.start.map(|s| s.entry()) == other.start.map(|s| s.entry())
+            && self.address_ranges() == other.address_ranges()
+            && self
+                .data
+                .iter()
+                .flat_map(|d| d.bytes().copied())
+                .eq(other.data.iter().flat_map(|d| d.bytes().copied()))
+    }
+}
+
+impl Eq for HexFile {}
+
+impl Hash for HexFile {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.start.map(|s| s.entry()).hash(state);
+        self.address_ranges().hash(state);
+        // Hash in fixed-size blocks so record boundaries don't matter
+        let mut block = Vec::with_capacity(DEFAULT_RECORD_LEN);
+        for b in self.data.iter().flat_map(|d| d.bytes().copied()) {
+            block.push(b);
+            if block.len() == DEFAULT_RECORD_LEN {
+                state.write(&block);
+                block.clear();
+            }
+        }
+        state.write(&block);
+    }
+}
+
+impl Default for HexFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HexFile {
-    pub fn print_bytes(&self, start: u32, end: u32, cluster: usize) {
-        use std::fmt::Write;
+    /// An empty image.
+    pub fn new() -> Self {
+        Self {
+            start: None,
+            data: Vec::new(),
+        }
+    }
+
+    /// Collects records into an image.
+    pub(crate) fn from_records(records: impl IntoIterator<Item = Record>) -> Self {
+        Self::collect(records, false)
+    }
+
+    /// Collects records into an image, compressing them as they come in if
+    /// `compress` is set, so that they're never all held expanded.
+    pub(crate) fn collect(records: impl IntoIterator<Item = Record>, compress: bool) -> Self {
+        let mut start = None;
+        let mut data = Vec::new();
+        for record in records {
+            match record {
+                Record::Eof => {}
+                Record::Data(d) if compress => push_compressed(&mut data, d),
+                Record::Data(d) => data.push(d),
+                Record::StartSegmentAddr(s) => start = Some(StartAddr::Segment(s)),
+                Record::StartLinearAddr(addr) => start = Some(StartAddr::Linear(addr)),
+                Record::ExtSegmentAddr(_) | Record::ExtLinearAddr(_) => {}
+            }
+        }
+        data.sort_by_key(|d| d.addr);
+        HexFile { start, data }
+    }
+
+    /// Adds `bytes` at `addr`, failing if any of them are already present.
+    pub fn add_data(&mut self, addr: u32, bytes: Vec<u8>) -> eyre::Result<()> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        u32::try_from(bytes.len() - 1)
+            .ok()
+            .and_then(|len| addr.checked_add(len))
+            .ok_or_else(|| {
+                eyre!(
+                    "Data at 0x{:08X} runs past the end of the address space",
+                    addr
+                )
+            })?;
+        self.insert(Data::new(addr, bytes))
+    }
 
-        let mut data = self
+    /// Adds the record `data`, failing if any of its bytes are already
+    /// present.
+    fn insert(&mut self, data: Data) -> eyre::Result<()> {
+        let range = data.addr_range();
+        if let Some(d) = self
             .data
             .iter()
-            .find(|d| d.addr_range().contains(start))
-            .unwrap();
+            .find(|d| d.addr_range().contains(range.start) || range.contains(d.addr))
+        {
+            return Err(eyre!(
+                "{} overlaps with existing data at {}",
+                range,
+                d.addr_range()
+            ));
+        }
+        let was_valid = self.debug_valid();
+        let pos = self.data.partition_point(|d| d.addr < range.start);
+        self.data.insert(pos, data);
+        self.debug_check(was_valid);
+        Ok(())
+    }
+
+    /// Writes the start address and every data record to `w`.
+    pub fn pretty_print<W: io::Write>(&self, mut w: W) -> eyre::Result<()> {
+        if let Some(start) = &self.start {
+            start.pretty_print(&mut w)?;
+        }
+        for d in &self.data {
+            d.pretty_print(&mut w)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the bytes in `start..=end` to `w` as a hexdump, ordering the
+    /// bytes within each cluster as `opts.order` says. A short last cluster
+    /// is filled up with zeros if `pad` is set, or with blanks otherwise.
+    /// With `opts.value` set every cluster is one value of that type, shown
+    /// in decimal. With `opts.fold` a run of lines repeating the line before
+    /// them shows as a single `*`, as in xxd; the last line always shows.
+    /// With `opts.color` bytes are colored with ANSI escapes by kind.
+    /// Addresses without data show as `opts.gap_fill`, or as `..` without
+    /// one.
+    pub fn print_bytes<W: io::Write>(
+        &self,
+        mut w: W,
+        start: u32,
+        end: u32,
+        opts: &PrintOptions,
+    ) -> eyre::Result<()> {
+        let cluster = opts.value.map_or(opts.cluster, |v| v.size());
+        if cluster == 0 {
+            return Err(eyre!("Cluster size must be at least 1"));
+        }
+        if opts.value.is_some_and(|v| v.size() > 1) && opts.order == ClusterOrder::None {
+            return Err(eyre!("Typed values need a little or big byte order"));
+        }
+        let ranges = self.address_ranges();
+        // The range at or after the current address, and the record last
+        // read from
+        let mut range_idx = 0;
+        let mut data = None::<&Data>;
 
         const CLUSTER_PER_LINE: usize = 4;
-        let mut cluster_cnt = 0;
+        let width = match opts.value {
+            Some(value) => value.width(),
+            None => 2 * cluster + opts.order.separator().len() * (cluster - 1),
+        };
+        let lines = (start..=end).chunks(cluster * CLUSTER_PER_LINE);
+        let mut lines = lines.into_iter().peekable();
+        // The bytes of the line before, and whether repeats of it are being
+        // skipped
+        let mut prev: Option<Vec<Option<u8>>> = None;
+        let mut folding = false;
+        let mut full = false;
+        let paint =
+            |addr: u32, b: u8, text: String| match byte_color(b, addr == start || addr == end) {
+                Some(color) if opts.color => format!("\x1b[{}m{}\x1b[0m", color, text),
+                _ => text,
+            };
 
-        for addrs in &(start..=end).chunks(cluster) {
+        while let Some(addrs) = lines.next() {
             let addrs = addrs.collect_vec();
-            let mut cluster = "".repeat((cluster - addrs.len()) * 2);
-            for &addr in addrs.iter().rev() {
-                if !data.addr_range().contains(addr) {
-                    data = self
-                        .data
-                        .iter()
-                        .find(|d| d.addr_range().contains(addr))
-                        .unwrap();
+            let mut bytes = Vec::with_capacity(addrs.len());
+            for &addr in &addrs {
+                while ranges.get(range_idx).is_some_and(|r| r.end < addr) {
+                    range_idx += 1;
+                }
+                if !ranges.get(range_idx).is_some_and(|r| r.contains(addr)) {
+                    bytes.push(opts.gap_fill);
+                    continue;
+                }
+                let d = match data {
+                    Some(d) if d.addr_range().contains(addr) => d,
+                    _ => self
+                        .data_containing(addr)
+                        .ok_or_else(|| eyre!("0x{:08x} isn't in the image", addr))?,
+                };
+                data = Some(d);
+                bytes.push(d.get_byte(addr));
+            }
+            let last = lines.peek().is_none();
+            if opts.fold && !last && prev.as_ref() == Some(&bytes) {
+                if !folding {
+                    write!(w, "\n*")?;
+                    folding = true;
                 }
-                write!(&mut cluster, "{:02x}", data.get_byte(addr)).ok();
+                continue;
             }
+            folding = false;
 
-            if cluster_cnt % CLUSTER_PER_LINE == 0 {
-                print!("\n{:08x}  ", addrs[0]);
+            write!(w, "\n{:08x}  ", addrs[0].wrapping_add(opts.addr_offset))?;
+            for (chunk, chunk_addrs) in bytes.chunks(cluster).zip(addrs.chunks(cluster)) {
+                let text = match opts.value {
+                    Some(value) if chunk.contains(&None) => format!("{:>1$}", "..", value.width()),
+                    Some(value) if opts.pad || chunk.len() == cluster => {
+                        let mut padded = chunk.iter().flatten().copied().collect_vec();
+                        padded.resize(cluster, 0);
+                        if opts.order == ClusterOrder::Little {
+                            padded.reverse();
+                        }
+                        format!("{:>1$}", value.decode(&padded), value.width())
+                    }
+                    Some(value) => " ".repeat(value.width()),
+                    None => {
+                        let filler = if opts.pad { "00" } else { "  " };
+                        let mut cells = chunk
+                            .iter()
+                            .zip(chunk_addrs)
+                            .map(|(&b, &addr)| match b {
+                                Some(b) => paint(addr, b, format!("{:02x}", b)),
+                                None => "..".to_string(),
+                            })
+                            .collect_vec();
+                        cells.resize(cluster, filler.to_string());
+                        if opts.order == ClusterOrder::Little {
+                            cells.reverse();
+                        }
+                        cells.join(opts.order.separator())
+                    }
+                };
+                write!(w, "{} ", text)?;
             }
-            cluster_cnt += 1;
-
-            print!("{} ", cluster);
+            let clusters = bytes.len().div_ceil(cluster);
+            if opts.ascii {
+                // Line the column up with the ones above on a short last line
+                let missing = CLUSTER_PER_LINE - clusters;
+                let text = bytes
+                    .iter()
+                    .zip(&addrs)
+                    .map(|(&b, &addr)| match b {
+                        Some(b) if b.is_ascii_graphic() || b == b' ' => {
+                            paint(addr, b, (b as char).to_string())
+                        }
+                        Some(b) => paint(addr, b, ".".to_string()),
+                        None => " ".to_string(),
+                    })
+                    .collect::<String>();
+                write!(w, "{} {}", " ".repeat(missing * (width + 1)), text)?;
+            }
+            full = clusters == CLUSTER_PER_LINE;
+            prev = Some(bytes);
         }
-        if cluster_cnt % CLUSTER_PER_LINE == 0 {
-            println!();
+        if full {
+            writeln!(w)?;
         }
+        Ok(())
     }
 
-    pub fn pretty_print(&self) {
-        if let Some(start) = &self.start {
-            println!(
-                "Start Addr: CS = 0x{:04x}, IP = 0x{:04x}\n",
-                start.cs, start.ip
-            );
-        }
-        for d in &self.data {
-            d.pretty_print();
+    /// Writes the aligned 32-bit words overlapping
+    /// `start..=end` to `w`, one per line with its address and its offset
+    /// from the first word, their bytes ordered as `opts.order` says. Bytes
+    /// outside the window or the image show up as `..`.
+    pub fn print_words32<W: io::Write>(
+        &self,
+        mut w: W,
+        start: u32,
+        end: u32,
+        opts: &PrintOptions,
+    ) -> eyre::Result<()> {
+        let first = start & !3;
+        for addr in (first as u64..=end as u64).step_by(4) {
+            let mut cells = (addr..addr + 4)
+                .map(|a| match u32::try_from(a) {
+                    Ok(a) if (start..=end).contains(&a) => self
+                        .data_containing(a)
+                        .and_then(|d| d.get_byte(a))
+                        .map_or("..".to_string(), |b| format!("{:02x}", b)),
+                    _ => "..".to_string(),
+                })
+                .collect_vec();
+            if opts.order == ClusterOrder::Little {
+                cells.reverse();
+            }
+            let word = cells.join(opts.order.separator());
+            writeln!(
+                w,
+                "{:08x}  +0x{:04x}  {}",
+                (addr as u32).wrapping_add(opts.addr_offset),
+                addr - first as u64,
+                word
+            )?;
         }
+        Ok(())
     }
 
+    /// Contiguous address ranges covered by the data records.
     pub fn address_ranges(&self) -> Vec<AddrRange> {
-        let (first, rest) = self.data.split_first().unwrap();
+        let Some((first, rest)) = self.data.split_first() else {
+            return Vec::new();
+        };
         let mut ranges = Vec::new();
-        let mut start = first.addr;
-        let mut end = first.addr + first.data.len() as u32 - 1;
+        let mut curr = first.addr_range();
         for d in rest {
-            if d.addr != end + 1 {
-                ranges.push(AddrRange { start, end });
-                start = d.addr;
+            let range = d.addr_range();
+            // Records can overlap when images were concatenated
+            if curr
+                .end
+                .checked_add(1)
+                .is_some_and(|next| range.start > next)
+            {
+                ranges.push(curr);
+                curr = range;
+            } else {
+                curr.end = curr.end.max(range.end);
             }
-            end = d.addr + d.data.len() as u32 - 1;
         }
-        ranges.push(AddrRange { start, end });
+        ranges.push(curr);
         ranges
     }
 
+    /// Checks the invariants every operation on an image keeps: each record
+    /// holds at least one byte and fits in the address space, records are
+    /// sorted by address without overlapping, and
+    /// [`HexFile::address_ranges`] covers exactly their bytes.
+    ///
+    /// Parsing doesn't enforce this, as concatenated images can repeat
+    /// addresses; such an image fails here until its overlaps are resolved.
+    pub fn validate(&self) -> eyre::Result<()> {
+        let mut prev = None::<AddrRange>;
+        let mut size = 0;
+        for (idx, d) in self.data.iter().enumerate() {
+            if d.is_empty() {
+                return Err(eyre!("Record {} at 0x{:08X} is empty", idx, d.addr));
+            }
+            if u32::try_from(d.len() - 1)
+                .ok()
+                .and_then(|len| d.addr.checked_add(len))
+                .is_none()
+            {
+                return Err(eyre!(
+                    "Record {} at 0x{:08X} runs past the end of the address space",
+                    idx,
+                    d.addr
+                ));
+            }
+            let range = d.addr_range();
+            if let Some(prev) = prev {
+                if prev.end >= range.start {
+                    return Err(eyre!(
+                        "Record {} at {} isn't after the one at {}",
+                        idx,
+                        range,
+                        prev
+                    ));
+                }
+            }
+            prev = Some(range);
+            size += range.size();
+        }
+
+        let ranges = self.address_ranges();
+        let disjoint = ranges
+            .iter()
+            .tuple_windows()
+            .all(|(a, b)| a.end.checked_add(1).is_some_and(|next| next < b.start));
+        let covered = ranges.iter().map(AddrRange::size).sum::<u64>();
+        if !disjoint || covered != size {
+            return Err(eyre!(
+                "Address ranges {} don't match the {} bytes in the records",
+                ranges.iter().join(", "),
+                size
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the image is valid, checked only in debug builds. Pairs with
+    /// [`HexFile::debug_check`].
+    fn debug_valid(&self) -> bool {
+        cfg!(debug_assertions) && self.validate().is_ok()
+    }
+
+    /// Panics if an operation left an image that was valid before it,
+    /// according to `was_valid`, invalid.
+    fn debug_check(&self, was_valid: bool) {
+        if was_valid {
+            if let Err(e) = self.validate() {
+                panic!("Image corrupted: {}", e);
+            }
+        }
+    }
+
+    /// Data records, sorted by address.
     pub fn data(&self) -> &[Data] {
         &self.data
     }
 
-    pub fn data_at(&self, idx: usize) -> &Data {
-        &self.data[idx]
+    pub fn data_at(&self, idx: usize) -> Option<&Data> {
+        self.data.get(idx)
     }
 
+    /// The data record holding `addr`, if any.
+    pub fn data_containing(&self, addr: u32) -> Option<&Data> {
+        self.data.iter().find(|d| d.addr_range().contains(addr))
+    }
+
+    /// Concatenated bytes of all data records lying entirely in `range`.
     pub fn data_in_range(&self, range: AddrRange) -> Vec<u8> {
         let mut data = Vec::new();
         for d in &self.data {
             let curr_range = d.addr_range();
             if range.contains_range(curr_range) {
-                data.extend_from_slice(&d.data);
+                data.extend(d.bytes());
             }
         }
         data
     }
 
-    pub fn start_addr(&self) -> Option<u32> {
-        self.start.map(|ss| ((ss.cs as u32) << 16) | (ss.ip as u32))
+    /// Addresses where `pattern` occurs, with `None` matching any byte.
+    /// Matches may span record boundaries but not gaps.
+    pub fn find(&self, pattern: &[Option<u8>]) -> Vec<u32> {
+        let mut found = Vec::new();
+        if pattern.is_empty() {
+            return found;
+        }
+        for range in self.address_ranges() {
+            let bytes = self.data_in_range(range);
+            for (offset, window) in bytes.windows(pattern.len()).enumerate() {
+                let matches = window
+                    .iter()
+                    .zip(pattern)
+                    .all(|(b, p)| p.is_none_or(|p| p == *b));
+                if matches {
+                    found.push(range.start + offset as u32);
+                }
+            }
+        }
+        found
     }
 
-    pub fn transpose(&mut self, start: u32, dest: u32) -> eyre::Result<()> {
-        let ranges = self.address_ranges();
-        let src_range = ranges
-            .iter()
-            .find(|x| x.start == start)
-            .ok_or(eyre!("0x{:08X} doesn't start any range", start))?;
-        let dest_range = src_range.transpose(dest);
-        if let Some(overlap_range) = ranges
-            .iter()
-            .find(|x| x != &src_range && x.overlaps_range(dest_range))
-        {
-            return Err(eyre!(
-                "Destination range {} overlaps with existing range {}",
-                dest_range,
-                overlap_range
-            ));
-        }
-        for data in &mut self.data {
-            if src_range.contains(data.addr) {
-                if dest >= start {
-                    let diff = dest - start;
-                    data.addr += diff;
-                } else {
-                    let diff = start - dest;
-                    data.addr -= diff;
+    /// Runs of at least `min_len` printable ASCII characters, with their
+    /// start addresses. Runs may span record boundaries but not gaps.
+    pub fn strings(&self, min_len: usize) -> Vec<(u32, String)> {
+        let mut found = Vec::new();
+        for range in self.address_ranges() {
+            let bytes = self.data_in_range(range);
+            let mut offset = 0;
+            for run in bytes.split(|&b| !(b.is_ascii_graphic() || b == b' ' || b == b'\t')) {
+                if run.len() >= min_len.max(1) {
+                    found.push((
+                        range.start + offset as u32,
+                        String::from_utf8_lossy(run).into_owned(),
+                    ));
                 }
+                offset += run.len() + 1;
             }
         }
-        Ok(())
+        found
     }
 
-    pub fn write<W: io::Write>(&self, mut w: W) -> eyre::Result<()> {
-        let mut hi_addr = 0u16;
-        for data in &self.data {
-            let curr_hi_addr = ((0xFFFF0000 & data.addr) >> 16) as u16;
-            if curr_hi_addr != hi_addr {
-                hi_addr = curr_hi_addr;
-                let bytes = hi_addr.to_be_bytes();
-                let cksum = bytes[0]
-                    .wrapping_add(bytes[1])
-                    .wrapping_add(0x06)
-                    .not()
-                    .wrapping_add(0x01);
-                writeln!(w, ":02000004{:04X}{:02X}", hi_addr, cksum)?;
-            }
-
-            let curr_lo_addr = (0xFFFF & data.addr) as u16;
-            let len = data.data.len() as u8;
-            write!(w, ":{:02X}{:04X}00", len, curr_lo_addr)?;
-
-            let addr_bytes = curr_lo_addr.to_be_bytes();
-            let mut cksum = len.wrapping_add(addr_bytes[0]).wrapping_add(addr_bytes[1]);
-            for &b in &data.data {
-                write!(w, "{:02X}", b)?;
-                cksum = cksum.wrapping_add(b);
-            }
-            cksum = cksum.not().wrapping_add(0x01);
-            writeln!(w, "{:02X}", cksum)?;
-        }
-
-        if let Some(start) = self.start {
-            let cs_bytes = start.cs.to_be_bytes();
-            let ip_bytes = start.ip.to_be_bytes();
-            let cksum = cs_bytes[0]
-                .wrapping_add(cs_bytes[1])
-                .wrapping_add(ip_bytes[0])
-                .wrapping_add(ip_bytes[1])
-                .wrapping_add(0x07)
-                .not()
-                .wrapping_add(0x01);
-            writeln!(w, ":04000003{:04X}{:04X}{:02X}", start.cs, start.ip, cksum)?;
-        }
-
-        writeln!(w, ":00000001FF")?;
-        Ok(())
+    /// Shannon entropy in bits per byte over consecutive windows of
+    /// `window` bytes. Windows don't span gaps, so the last one of each
+    /// address range may be shorter.
+    pub fn entropy(&self, window: usize) -> Vec<(AddrRange, f64)> {
+        let mut profile = Vec::new();
+        for range in self.address_ranges() {
+            let bytes = self.data_in_range(range);
+            let mut addr = range.start;
+            for chunk in bytes.chunks(window.max(1)) {
+                let mut counts = [0usize; 256];
+                for &b in chunk {
+                    counts[b as usize] += 1;
+                }
+                let len = chunk.len() as f64;
+                let bits = counts
+                    .iter()
+                    .filter(|&&n| n > 0)
+                    .map(|&n| {
+                        let p = n as f64 / len;
+                        p * (1.0 / p).log2()
+                    })
+                    .sum::<f64>();
+                let end = addr + (chunk.len() - 1) as u32;
+                profile.push((AddrRange { start: addr, end }, bits));
+                addr = end.wrapping_add(1);
+            }
+        }
+        profile
     }
-}
 
-pub struct Context<'a> {
-    buf: &'a [u8],
-    addr_hi: Option<u16>,
-    eof: bool,
-    line_idx: usize,
-}
+    /// A copy of the bytes falling inside `range`, without the start address.
+    pub fn extract(&self, range: AddrRange) -> HexFile {
+        let mut data = Vec::new();
+        for d in &self.data {
+            let curr = d.addr_range();
+            let start = curr.start.max(range.start);
+            let end = curr.end.min(range.end);
+            if start <= end {
+                data.push(d.sub(AddrRange { start, end }));
+            }
+        }
+        HexFile { start: None, data }
+    }
 
-impl<'a> Context<'a> {
-    pub fn new(buf: &'a [u8]) -> Self {
-        Self {
-            buf,
-            addr_hi: None,
-            eof: false,
-            line_idx: 0,
+    /// The whole image as one buffer starting at its lowest address, which
+    /// is returned alongside. Gaps between records are filled with `fill`.
+    pub fn flatten(&self, fill: u8) -> (u32, Vec<u8>) {
+        let (Some(first), Some(last)) = (self.data.first(), self.data.last()) else {
+            return (0, Vec::new());
+        };
+        let base = first.addr;
+        let mut flat = vec![fill; (last.addr_range().end - base) as usize + 1];
+        for d in &self.data {
+            let off = (d.addr - base) as usize;
+            d.copy_to(&mut flat[off..off + d.len()]);
         }
+        (base, flat)
     }
-}
 
-impl Context<'_> {
-    pub fn into_hex_file(mut self) -> eyre::Result<HexFile> {
-        let mut start = None;
-        let mut data = Vec::new();
+    /// The bytes of `range`, with addresses holding no data filled with
+    /// `fill`.
+    pub fn flatten_range(&self, range: AddrRange, fill: u8) -> Vec<u8> {
+        let mut flat = vec![fill; range.size() as usize];
+        for d in &self.extract(range).data {
+            let off = (d.addr - range.start) as usize;
+            d.copy_to(&mut flat[off..off + d.len()]);
+        }
+        flat
+    }
 
-        loop {
-            match self.next_record()? {
-                Some(Record::Eof) => break,
-                Some(Record::Data(d)) => data.push(d),
-                Some(Record::StartSegmentAddr(s)) => {
-                    start = Some(s);
-                }
-                _ => {}
-            }
+    /// Moves every record down by `base`, so data at `base` ends up at 0.
+    pub fn rebase(&mut self, base: u32) -> eyre::Result<()> {
+        let was_valid = self.debug_valid();
+        for d in &mut self.data {
+            d.addr = d
+                .addr
+                .checked_sub(base)
+                .ok_or_else(|| eyre!("0x{:08X} is below the new base 0x{:08X}", d.addr, base))?;
         }
+        self.debug_check(was_valid);
+        Ok(())
+    }
 
-        data.sort_by(|l, r| l.addr.cmp(&r.addr));
-        Ok(HexFile { start, data })
+    /// Address ranges holding data in `self` but not in `other`.
+    pub fn ranges_not_in(&self, other: &HexFile) -> Vec<AddrRange> {
+        let others = other.address_ranges();
+        self.address_ranges()
+            .into_iter()
+            .flat_map(|range| range.subtract(&others))
+            .collect()
     }
 
-    fn next_record(&mut self) -> eyre::Result<Option<Record>> {
-        if self.eof {
-            if self.has_next_line() {
-                return Err(eyre!("Unexpected line after EOF record"));
-            } else {
-                return Ok(None);
+    /// Combines `other` into `self`, resolving overlapping bytes and
+    /// conflicting start addresses according to `policy`.
+    pub fn merge(&mut self, other: &HexFile, policy: OverlapPolicy) -> eyre::Result<()> {
+        let was_valid = self.debug_valid();
+        self.start = match (self.start, other.start) {
+            (Some(a), Some(b)) if a != b => match policy {
+                OverlapPolicy::Error => {
+                    return Err(eyre!(
+                        "Conflicting start addresses 0x{:08X} and 0x{:08X}",
+                        a.addr(),
+                        b.addr()
+                    ))
+                }
+                OverlapPolicy::FirstWins => Some(a),
+                OverlapPolicy::LastWins => Some(b),
+            },
+            (a, b) => a.or(b),
+        };
+        // Records of `other` don't overlap each other, so the gaps filled
+        // from one never cover bytes of the next
+        let ours = match policy {
+            OverlapPolicy::FirstWins => self.address_ranges(),
+            _ => Vec::new(),
+        };
+        for d in &other.data {
+            match policy {
+                OverlapPolicy::Error => self.insert(d.clone())?,
+                OverlapPolicy::FirstWins => {
+                    let range = d.addr_range();
+                    let first = ours.partition_point(|o| o.end < range.start);
+                    let last = ours.partition_point(|o| o.start <= range.end);
+                    for gap in range.subtract(&ours[first..last.max(first)]) {
+                        self.insert(d.sub(gap))?;
+                    }
+                }
+                OverlapPolicy::LastWins => {
+                    self.remove(&d.addr_range().into());
+                    self.insert(d.clone())?;
+                }
             }
         }
-        let addr_hi = self.addr_hi;
-        let Some((idx, line)) = self.next_line() else { return Err(eyre!("Unexpected EOF")); };
+        self.debug_check(was_valid);
+        Ok(())
+    }
 
-        if line.is_empty() {
-            return Err(eyre!("Line {}: empty line", idx));
+    /// Overwrites the bytes at `addr` with `bytes`. Unless `create` is set,
+    /// every byte must already be present in the image; otherwise missing
+    /// ones are added as new data.
+    pub fn patch(&mut self, addr: u32, bytes: &[u8], create: bool) -> eyre::Result<()> {
+        let Some(len) = bytes.len().checked_sub(1) else {
+            return Ok(());
+        };
+        let range = u32::try_from(len)
+            .ok()
+            .and_then(|len| addr.checked_add(len))
+            .map(|end| AddrRange { start: addr, end })
+            .ok_or_else(|| {
+                eyre!(
+                    "Patch at 0x{:08X} runs past the end of the address space",
+                    addr
+                )
+            })?;
+        let missing = range.subtract(&self.address_ranges());
+        if !create && !missing.is_empty() {
+            return Err(eyre!(
+                "Patch {} isn't inside the image, missing {}",
+                range,
+                missing.iter().join(", ")
+            ));
         }
-        if line[0] != b':' {
-            return Err(eyre!("Line {}: doesn't start with ':'", idx));
+        let was_valid = self.debug_valid();
+        self.split_runs_at(range);
+        for d in &mut self.data {
+            let curr = d.addr_range();
+            let start = curr.start.max(range.start);
+            let end = curr.end.min(range.end);
+            if start <= end {
+                let src = &bytes[(start - addr) as usize..=(end - addr) as usize];
+                let offset = d.addr;
+                d.bytes_mut()[(start - offset) as usize..=(end - offset) as usize]
+                    .copy_from_slice(src);
+            }
         }
+        for gap in missing {
+            self.add_data(
+                gap.start,
+                bytes[(gap.start - addr) as usize..=(gap.end - addr) as usize].to_vec(),
+            )?;
+        }
+        self.debug_check(was_valid);
+        Ok(())
+    }
 
-        let kind = u8::from_str_radix(
-            from_utf8(
-                line.get(7..=8)
-                    .ok_or_else(|| eyre!("Line {}: no kind field", idx))?,
-            )?,
-            16,
-        )?;
-
-        match kind {
-            0x00 => {
-                let len = u8::from_str_radix(
-                    from_utf8(
-                        line.get(1..=2)
-                            .ok_or_else(|| eyre!("Line {}: no len field", idx))?,
-                    )?,
-                    16,
-                )?;
+    /// Discards all data outside `windows`, splitting records that straddle
+    /// their boundaries. The start address is kept.
+    pub fn crop(&mut self, windows: &[AddrRange]) {
+        let was_valid = self.debug_valid();
+        let windows = windows.iter().copied().collect::<AddrRangeSet>();
+        let mut data = Vec::new();
+        for d in self.data.drain(..) {
+            let curr = d.addr_range();
+            if windows.contains_range(curr) {
+                data.push(d);
+                continue;
+            }
+            let kept = windows.intersection(&curr.into());
+            data.extend(kept.ranges().iter().map(|&keep| d.sub(keep)));
+        }
+        self.data = data;
+        self.debug_check(was_valid);
+    }
 
-                let addr = u16::from_str_radix(
-                    from_utf8(
-                        line.get(3..=6)
-                            .ok_or_else(|| eyre!("Line {}: no addr field", idx))?,
-                    )?,
-                    16,
-                )?;
+    /// Runs `transform` on the data inside `selection` only. It sees the
+    /// image cropped to `selection`, and anything it puts outside is
+    /// dropped, so data elsewhere comes through untouched.
+    pub fn transform_within<F>(
+        &mut self,
+        selection: &AddrRangeSet,
+        transform: F,
+    ) -> eyre::Result<()>
+    where
+        F: FnOnce(&mut HexFile) -> eyre::Result<()>,
+    {
+        let mut selected = HexFile {
+            start: self.start,
+            data: self.data.clone(),
+        };
+        selected.crop(selection.ranges());
+        self.remove(selection);
+        transform(&mut selected)?;
+        selected.crop(selection.ranges());
+        self.merge(&selected, OverlapPolicy::Error)
+    }
 
-                let mut data = Vec::new();
-                data.reserve(len as usize);
+    /// Re-splits the data into records of `record_len` bytes, each starting
+    /// on a multiple of `record_len` and none crossing a 64 KiB segment, so
+    /// that the same bytes always produce the same records. Runs stored by
+    /// [`HexFile::compress`] are expanded.
+    pub fn normalize(&mut self, record_len: u8) -> eyre::Result<()> {
+        if record_len == 0 {
+            return Err(eyre!("Record length must be at least 1"));
+        }
+        let was_valid = self.debug_valid();
+        let record_len = record_len as u64;
+        let mut data = Vec::new();
+        for range in self.address_ranges() {
+            let bytes = self.data_in_range(range);
+            let mut addr = range.start as u64;
+            let mut rest = &bytes[..];
+            while !rest.is_empty() {
+                let to_boundary = 0x1_0000 - (addr & 0xFFFF);
+                let len = (record_len - addr % record_len)
+                    .min(to_boundary)
+                    .min(rest.len() as u64) as usize;
+                let (chunk, tail) = rest.split_at(len);
+                data.push(Data::new(addr as u32, chunk.to_vec()));
+                addr += len as u64;
+                rest = tail;
+            }
+        }
+        self.data = data;
+        self.debug_check(was_valid);
+        Ok(())
+    }
 
-                for byte in line[9..].chunks(2).take(len as usize) {
-                    let byte = u8::from_str_radix(from_utf8(byte)?, 16)?;
-                    data.push(byte);
-                }
-                if data.len() < len as usize {
-                    return Err(eyre!(
-                        "Line {}: too few data bytes, expected {} but got {}",
-                        idx,
-                        len,
-                        data.len()
-                    ));
+    /// Splits the image into one part per contiguous address range or, when
+    /// `boundaries` are given, per stretch between consecutive boundaries.
+    /// Empty parts are left out and every part keeps the start address.
+    pub fn split(&self, boundaries: &[u32]) -> Vec<HexFile> {
+        let windows = if boundaries.is_empty() {
+            self.address_ranges()
+        } else {
+            let mut bounds = boundaries.to_vec();
+            bounds.sort_unstable();
+            bounds.dedup();
+            let mut windows = Vec::with_capacity(bounds.len() + 1);
+            let mut start = 0;
+            for bound in bounds {
+                if bound > start {
+                    windows.push(AddrRange {
+                        start,
+                        end: bound - 1,
+                    });
                 }
-
-                let addr = if let Some(addr_hi) = addr_hi {
-                    ((addr_hi as u32) << 16) | addr as u32
-                } else {
-                    addr as u32
-                };
-
-                Ok(Some(Record::Data(Data { data, addr })))
+                start = bound;
             }
-            0x01 => {
-                self.eof = true;
-                Ok(Some(Record::Eof))
+            windows.push(AddrRange {
+                start,
+                end: u32::MAX,
+            });
+            windows
+        };
+        windows
+            .into_iter()
+            .filter_map(|w| {
+                let first = self.data.partition_point(|d| d.addr_range().end < w.start);
+                let data = self.data[first..]
+                    .iter()
+                    .take_while(|d| d.addr <= w.end)
+                    .map(|d| {
+                        let curr = d.addr_range();
+                        let keep = AddrRange {
+                            start: curr.start.max(w.start),
+                            end: curr.end.min(w.end),
+                        };
+                        d.sub(keep)
+                    })
+                    .collect::<Vec<_>>();
+                (!data.is_empty()).then_some(HexFile {
+                    start: self.start,
+                    data,
+                })
+            })
+            .collect()
+    }
+
+    /// Reverses the byte order of every `width`-byte unit, optionally only
+    /// inside `window`. Units are aligned to multiples of `width`, and every
+    /// range being swapped must consist of whole units.
+    pub fn swap(&mut self, width: u32, window: Option<AddrRange>) -> eyre::Result<()> {
+        if width == 0 {
+            return Err(eyre!("Swap width must be at least 1"));
+        }
+        let window = window.unwrap_or(AddrRange {
+            start: 0,
+            end: u32::MAX,
+        });
+        for range in self.address_ranges() {
+            let range = AddrRange {
+                start: range.start.max(window.start),
+                end: range.end.min(window.end),
+            };
+            if range.start > range.end {
+                continue;
             }
-            0x03 => {
-                let cs = u16::from_str_radix(
-                    from_utf8(
-                        line.get(9..=12)
-                            .ok_or_else(|| eyre!("Line {}: no CS field", idx))?,
-                    )?,
-                    16,
-                )?;
-                let ip = u16::from_str_radix(
-                    from_utf8(
-                        line.get(13..=16)
-                            .ok_or_else(|| eyre!("Line {}: no IP field", idx))?,
-                    )?,
-                    16,
-                )?;
-                Ok(Some(Record::StartSegmentAddr(StartSegmentAddr { cs, ip })))
+            if !range.start.is_multiple_of(width) || !range.size().is_multiple_of(width as u64) {
+                return Err(eyre!(
+                    "{} doesn't consist of whole {}-byte units",
+                    range,
+                    width
+                ));
             }
-            0x04 => {
-                let addr_hi = u16::from_str_radix(
-                    from_utf8(
-                        line.get(9..=12)
-                            .ok_or_else(|| eyre!("Line {}: no addr_hi field", idx))?,
-                    )?,
-                    16,
-                )?;
-                self.addr_hi = Some(addr_hi);
-                Ok(None)
+            let mut bytes = self.flatten_range(range, 0);
+            for unit in bytes.chunks_mut(width as usize) {
+                unit.reverse();
             }
-            _ => Err(eyre!("Line {}: Unknown kind {:02X}", idx, kind)),
+            self.patch(range.start, &bytes, false)?;
         }
+        Ok(())
     }
 
-    fn next_line(&mut self) -> Option<(usize, &[u8])> {
-        if self.buf.is_empty() {
-            None
-        } else {
-            self.line_idx += 1;
-            if let Some(idx) = self.buf.iter().position(|&x| x == b'\n') {
-                let first = &self.buf[..idx];
-                self.buf = &self.buf[idx + 1..];
-                Some((self.line_idx, first))
-            } else {
-                Some((self.line_idx, self.buf))
+    /// Combines every byte, or only those inside `window`, with `key` using
+    /// `op`. The key repeats from the start of the window, or from the
+    /// lowest address of the image.
+    pub fn xform(&mut self, op: BitOp, key: &[u8], window: Option<AddrRange>) -> eyre::Result<()> {
+        if key.is_empty() {
+            return Err(eyre!("Key is empty"));
+        }
+        let Some(first) = self.data.first().map(|d| d.addr) else {
+            return Ok(());
+        };
+        let window = window.unwrap_or(AddrRange {
+            start: first,
+            end: u32::MAX,
+        });
+        self.split_runs_at(window);
+        for d in &mut self.data {
+            if !window.overlaps(d.addr_range()) {
+                continue;
+            }
+            // A run stays one when every byte gets the same key byte
+            if let (Bytes::Run { byte, .. }, &[k]) = (&mut d.data, key) {
+                *byte = op.apply(*byte, k);
+                continue;
+            }
+            let start = d.addr;
+            for (i, byte) in d.bytes_mut().iter_mut().enumerate() {
+                let addr = start + i as u32;
+                if window.contains(addr) {
+                    let phase = (addr - window.start) as usize % key.len();
+                    *byte = op.apply(*byte, key[phase]);
+                }
             }
         }
+        Ok(())
     }
 
-    fn has_next_line(&self) -> bool {
-        !self.buf.is_empty()
+    /// Pads every address range with `fill` so that it starts and ends on
+    /// a multiple of `alignment`. Ranges may grow into each other.
+    pub fn align(&mut self, alignment: u32, fill: u8) -> eyre::Result<()> {
+        if alignment == 0 {
+            return Err(eyre!("Alignment must be at least 1"));
+        }
+        let alignment = alignment as u64;
+        for range in self.address_ranges() {
+            let start = range.start as u64 / alignment * alignment;
+            let end = (range.end as u64 + 1).next_multiple_of(alignment) - 1;
+            let padded = AddrRange {
+                start: start as u32,
+                end: end.min(u32::MAX as u64) as u32,
+            };
+            self.fill(padded, &[fill])?;
+        }
+        Ok(())
     }
-}
-
-#[derive(Debug)]
-enum Record {
-    Data(Data),
-    Eof,
-    StartSegmentAddr(StartSegmentAddr),
-}
 
-#[derive(Debug)]
-pub struct Data {
-    data: Vec<u8>,
-    addr: u32,
-}
+    /// Discards all data inside `ranges`, splitting records that straddle
+    /// their boundaries. The complement of [`HexFile::crop`].
+    pub fn erase(&mut self, ranges: &[AddrRange]) {
+        let was_valid = self.debug_valid();
+        self.remove(&ranges.iter().copied().collect());
+        self.debug_check(was_valid);
+    }
 
-impl Data {
-    fn pretty_print(&self) {
-        print!("Addr: 0x{:08x}, ", self.addr);
-        print!("Data: [");
-        for (i, byte) in self.data.iter().enumerate() {
-            print!("{:02x}", byte);
-            if i == self.data.len() - 1 {
-                println!("]");
-            } else {
-                print!(", ");
-            }
+    /// Fills the addresses in `range` that hold no data with `pattern`,
+    /// repeated from the start of `range`. Existing bytes are left alone.
+    pub fn fill(&mut self, range: AddrRange, pattern: &[u8]) -> eyre::Result<()> {
+        if pattern.is_empty() {
+            return Err(eyre!("Fill pattern is empty"));
         }
+        for gap in range.subtract(&self.address_ranges()) {
+            let bytes = (gap.start as u64..=gap.end as u64)
+                .map(|addr| pattern[((addr - range.start as u64) % pattern.len() as u64) as usize])
+                .collect();
+            self.add_data(gap.start, bytes)?;
+        }
+        Ok(())
     }
 
-    pub fn addr_range(&self) -> AddrRange {
-        AddrRange {
-            start: self.addr,
-            end: self.addr + self.data.len() as u32 - 1,
+    /// Stores runs of one repeated byte, such as erased flash, as that byte
+    /// and a count rather than byte by byte. Records holding nothing else
+    /// are merged with a run they continue, and runs of at least
+    /// [`MIN_RUN`] bytes are split out of records with other bytes, so a
+    /// mostly blank image takes a fraction of the memory. Reading the bytes
+    /// is unaffected, but where records begin inside runs isn't kept.
+    pub fn compress(&mut self) {
+        let was_valid = self.debug_valid();
+        let mut data = Vec::with_capacity(self.data.len());
+        for d in self.data.drain(..) {
+            push_compressed(&mut data, d);
         }
+        self.data = data;
+        self.debug_check(was_valid);
     }
 
-    pub fn get_byte(&self, addr: u32) -> u8 {
-        self.data[(addr - self.addr) as usize]
+    /// Number of bytes held in memory for the data, counting runs as none.
+    pub fn stored_len(&self) -> u64 {
+        self.data
+            .iter()
+            .filter(|d| d.run_byte().is_none())
+            .map(|d| d.len() as u64)
+            .sum()
+    }
+
+    /// Splits records stored as runs that straddle the edges of `range`, so
+    /// that changing the bytes inside it expands only the part inside.
+    fn split_runs_at(&mut self, range: AddrRange) {
+        let straddles = |d: &Data| {
+            let curr = d.addr_range();
+            d.run_byte().is_some() && curr.overlaps(range) && !range.contains_range(curr)
+        };
+        if !self.data.iter().any(straddles) {
+            return;
+        }
+        let mut data = Vec::with_capacity(self.data.len() + 2);
+        for d in self.data.drain(..) {
+            if !straddles(&d) {
+                data.push(d);
+                continue;
+            }
+            let curr = d.addr_range();
+            let inside = AddrRange {
+                start: curr.start.max(range.start),
+                end: curr.end.min(range.end),
+            };
+            if curr.start < inside.start {
+                data.push(d.sub(AddrRange {
+                    start: curr.start,
+                    end: inside.start - 1,
+                }));
+            }
+            data.push(d.sub(inside));
+            if inside.end < curr.end {
+                data.push(d.sub(AddrRange {
+                    start: inside.end + 1,
+                    end: curr.end,
+                }));
+            }
+        }
+        self.data = data;
     }
-}
 
-#[derive(Debug, Clone, Copy)]
-struct StartSegmentAddr {
-    cs: u16,
-    ip: u16,
+    /// Drops the bytes in `erased`, splitting records that straddle its
+    /// boundaries.
+    fn remove(&mut self, erased: &AddrRangeSet) {
+        let mut data = Vec::with_capacity(self.data.len());
+        for d in self.data.drain(..) {
+            let curr = d.addr_range();
+            if !erased.overlaps(curr) {
+                data.push(d);
+                continue;
+            }
+            for &keep in AddrRangeSet::from(curr).difference(erased).ranges() {
+                data.push(d.sub(keep));
+            }
+        }
+        self.data = data;
+    }
+
+    /// Whether `self` and `other` hold the same bytes at the same addresses,
+    /// in the same order, and the same start address, however the bytes are
+    /// split into records. Writing an image and parsing it back gives an
+    /// image this holds for.
+    pub fn same_content(&self, other: &HexFile) -> bool {
+        let bytes = |hex: &HexFile| {
+            hex.data
+                .iter()
+                .flat_map(|d| (0..).zip(d.bytes()).map(|(i, &b)| (d.addr + i, b)))
+                .collect_vec()
+        };
+        self.start == other.start && bytes(self) == bytes(other)
+    }
+
+    /// Bytes that differ between `self` and `other` at addresses present in
+    /// both.
+    pub fn byte_diffs<'a>(&'a self, other: &'a HexFile) -> ByteDiffs<'a> {
+        ByteDiffs {
+            left: &self.data,
+            right: &other.data,
+            next: 0,
+        }
+    }
+
+    /// Compares `self` with `other` as [`diff_records`] does record streams.
+    pub fn diff(&self, other: &HexFile, opts: &DiffOptions) -> RecordDiff {
+        diff_records(self.records().map(Ok), other.records().map(Ok), opts)
+            .ok()
+            .flatten()
+            .expect("an image's records are in address order")
+    }
+
+    /// The start address and data of the image as records, in address order.
+    fn records(&self) -> impl Iterator<Item = Record> + '_ {
+        let start = self.start.map(|start| match start {
+            StartAddr::Segment(s) => Record::StartSegmentAddr(s),
+            StartAddr::Linear(addr) => Record::StartLinearAddr(addr),
+        });
+        start
+            .into_iter()
+            .chain(self.data.iter().cloned().map(Record::Data))
+    }
+
+    /// Sets the start address, written as a start linear address record.
+    pub fn set_start_addr(&mut self, addr: Option<u32>) {
+        self.start = addr.map(StartAddr::Linear);
+    }
+
+    /// The start address as its record gave it.
+    pub fn start(&self) -> Option<StartAddr> {
+        self.start
+    }
+
+    /// The start address, with CS:IP pairs given as `CS << 16 | IP`.
+    pub fn start_addr(&self) -> Option<u32> {
+        self.start.map(|ss| ss.addr())
+    }
+
+    /// Moves the range starting at `start` to `dest`. With `move_entry`, a
+    /// start address inside the range moves along with it. `policy` decides
+    /// between the moved bytes and ones already at the destination, the
+    /// moved bytes being the ones added last.
+    pub fn transpose(
+        &mut self,
+        start: u32,
+        dest: u32,
+        move_entry: bool,
+        policy: OverlapPolicy,
+    ) -> eyre::Result<()> {
+        let ranges = self.address_ranges();
+        let src_range = *ranges
+            .iter()
+            .find(|x| x.start == start)
+            .ok_or(eyre!("0x{:08X} doesn't start any range", start))?;
+        let dest_range = src_range.transpose(dest).ok_or_else(|| {
+            eyre!(
+                "Moving {} to 0x{:08X} runs past the end of the address space",
+                src_range,
+                dest
+            )
+        })?;
+        if let Some(overlap_range) = ranges
+            .iter()
+            .find(|x| **x != src_range && x.overlaps(dest_range))
+            .filter(|_| policy == OverlapPolicy::Error)
+        {
+            return Err(eyre!(
+                "Destination range {} overlaps with existing range {}",
+                dest_range,
+                overlap_range
+            ));
+        }
+        let moved = |addr: u32| addr.wrapping_sub(start).wrapping_add(dest);
+        let new_start = match self.start {
+            Some(StartAddr::Linear(addr)) if move_entry && src_range.contains(addr) => {
+                Some(StartAddr::Linear(moved(addr)))
+            }
+            Some(StartAddr::Segment(s)) if move_entry && src_range.contains(s.physical()) => {
+                let seg = StartSegmentAddr::from_physical(moved(s.physical()), s.cs).ok_or_else(
+                    || {
+                        eyre!(
+                            "The start address would move to 0x{:08X}, which CS:IP can't reach",
+                            moved(s.physical())
+                        )
+                    },
+                )?;
+                Some(StartAddr::Segment(seg))
+            }
+            other => other,
+        };
+        let mut moving = self.extract(src_range);
+        for data in &mut moving.data {
+            data.addr = moved(data.addr);
+        }
+        let was_valid = self.debug_valid();
+        self.remove(&src_range.into());
+        self.merge(&moving, policy)?;
+        self.start = new_start;
+        self.debug_check(was_valid);
+        Ok(())
+    }
+
+    /// Writes the image as Intel HEX, formatted as `opts` asks.
+    pub fn write<W: io::Write>(&self, mut w: W, opts: &WriteOptions) -> eyre::Result<()> {
+        if opts.record_len == Some(0) {
+            return Err(eyre!("Record length must be at least 1"));
+        }
+        let mut hi_addr = 0u16;
+        for (d, chunk) in self
+            .data
+            .iter()
+            .flat_map(|d| d.records(opts.record_len).map(move |chunk| (d, chunk)))
+        {
+            let curr_hi_addr = (chunk.start >> 16) as u16;
+            if curr_hi_addr != hi_addr {
+                hi_addr = curr_hi_addr;
+                write_record(&mut w, opts, 0x04, 0, &hi_addr.to_be_bytes())?;
+            }
+            write_record(&mut w, opts, 0x00, chunk.start as u16, &d.slice(chunk))?;
+        }
+
+        if opts.start_addr {
+            match self.start {
+                Some(StartAddr::Segment(start)) => {
+                    let mut payload = start.cs.to_be_bytes().to_vec();
+                    payload.extend(start.ip.to_be_bytes());
+                    write_record(&mut w, opts, 0x03, 0, &payload)?;
+                }
+                Some(StartAddr::Linear(addr)) => {
+                    write_record(&mut w, opts, 0x05, 0, &addr.to_be_bytes())?;
+                }
+                None => {}
+            }
+        }
+
+        write_record(&mut w, opts, 0x01, 0, &[])?;
+        Ok(())
+    }
+}
+
+/// Appends `d` to `data` with its runs of one byte split out, merging a
+/// run that continues the one `data` ends with into it.
+fn push_compressed(data: &mut Vec<Data>, d: Data) {
+    for piece in d.split_runs(MIN_RUN) {
+        if let Some(last) = data.last_mut() {
+            let continues = last.addr_range().end.checked_add(1) == Some(piece.addr);
+            if let (
+                true,
+                Some(byte),
+                Bytes::Run {
+                    byte: last_byte,
+                    len,
+                    ..
+                },
+            ) = (continues, piece.run_byte(), &mut last.data)
+            {
+                if *last_byte == byte {
+                    *len += piece.len();
+                    continue;
+                }
+            }
+        }
+        data.push(piece);
+    }
+}
+
+/// Writes one record line, computing its checksum.
+fn write_record<W: io::Write>(
+    mut w: W,
+    opts: &WriteOptions,
+    kind: u8,
+    addr: u16,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut line = vec![payload.len() as u8];
+    line.extend(addr.to_be_bytes());
+    line.push(kind);
+    line.extend(payload);
+    let cksum = line
+        .iter()
+        .fold(0u8, |acc, &b| acc.wrapping_add(b))
+        .not()
+        .wrapping_add(0x01);
+    line.push(cksum);
+    write!(w, ":")?;
+    for b in line {
+        if opts.lowercase {
+            write!(w, "{:02x}", b)?;
+        } else {
+            write!(w, "{:02X}", b)?;
+        }
+    }
+    write!(w, "{}", opts.line_ending.as_str())
+}
+
+/// How [`HexFile::print_bytes`] and [`HexFile::print_words32`] lay out
+/// their output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrintOptions {
+    /// Bytes shown as one cluster, ignored by `print_words32`.
+    pub cluster: usize,
+    /// Zero-pad a short last cluster instead of leaving it blank.
+    pub pad: bool,
+    /// Order of the bytes within a cluster.
+    pub order: ClusterOrder,
+    /// Follow every line with its bytes as ASCII, non-printable ones shown
+    /// as `.`. Ignored by `print_words32`.
+    pub ascii: bool,
+    /// Added to every address shown, leaving the data where it is.
+    pub addr_offset: u32,
+    /// Show every cluster as a value of this type instead of as hex bytes,
+    /// which sets the cluster size to the type's. Ignored by
+    /// `print_words32`.
+    pub value: Option<ValueFormat>,
+    /// Collapse runs of repeated lines into a `*`. Ignored by
+    /// `print_words32`.
+    pub fold: bool,
+    /// Color bytes by kind with ANSI escapes: 0x00 and 0xFF filler, printable
+    /// ASCII and the first and last byte asked for. Ignored by
+    /// `print_words32`.
+    pub color: bool,
+    /// Byte to show for addresses without data, which show as `..` without
+    /// one. Ignored by `print_words32`.
+    pub gap_fill: Option<u8>,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            cluster: 4,
+            pad: true,
+            order: ClusterOrder::Little,
+            ascii: false,
+            addr_offset: 0,
+            value: None,
+            fold: true,
+            color: false,
+            gap_fill: None,
+        }
+    }
+}
+
+/// The ANSI color of a printed byte, if it gets one. `boundary` marks the
+/// first and last byte asked for.
+fn byte_color(b: u8, boundary: bool) -> Option<&'static str> {
+    if boundary {
+        Some("1;33")
+    } else if b == 0x00 || b == 0xFF {
+        Some("90")
+    } else if b.is_ascii_graphic() || b == b' ' {
+        Some("32")
+    } else {
+        None
+    }
+}
+
+/// How bytes are ordered within a printed cluster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClusterOrder {
+    /// Reversed, reading as a little-endian word.
+    Little,
+    /// In address order, reading as a big-endian word.
+    Big,
+    /// In address order and spaced out, as individual bytes.
+    None,
+}
+
+impl ClusterOrder {
+    fn separator(&self) -> &'static str {
+        match self {
+            ClusterOrder::Little | ClusterOrder::Big => "",
+            ClusterOrder::None => " ",
+        }
+    }
+}
+
+impl FromStr for ClusterOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "little" | "le" => Ok(ClusterOrder::Little),
+            "big" | "be" => Ok(ClusterOrder::Big),
+            "none" => Ok(ClusterOrder::None),
+            _ => Err(format!("Unknown byte order: {}", s)),
+        }
+    }
+}
+
+/// Type to decode printed clusters as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueFormat {
+    U8,
+    U16,
+    U32,
+    I32,
+    F32,
+}
+
+impl ValueFormat {
+    /// Bytes in one value.
+    pub fn size(&self) -> usize {
+        match self {
+            ValueFormat::U8 => 1,
+            ValueFormat::U16 => 2,
+            ValueFormat::U32 | ValueFormat::I32 | ValueFormat::F32 => 4,
+        }
+    }
+
+    /// Columns a value is right-aligned in, enough for any value of the
+    /// integer types.
+    fn width(&self) -> usize {
+        match self {
+            ValueFormat::U8 => 3,
+            ValueFormat::U16 => 5,
+            ValueFormat::U32 => 10,
+            ValueFormat::I32 => 11,
+            ValueFormat::F32 => 14,
+        }
+    }
+
+    /// Formats `bytes`, most significant first, as a value of this type.
+    pub(crate) fn decode(&self, bytes: &[u8]) -> String {
+        let word = bytes.iter().fold(0u32, |acc, &b| (acc << 8) | b as u32);
+        match self {
+            ValueFormat::U8 | ValueFormat::U16 | ValueFormat::U32 => word.to_string(),
+            ValueFormat::I32 => (word as i32).to_string(),
+            ValueFormat::F32 => {
+                let value = f32::from_bits(word);
+                // Plain notation would run to dozens of digits at the extremes
+                if value == 0.0 || !value.is_finite() || (1e-4..1e9).contains(&value.abs()) {
+                    value.to_string()
+                } else {
+                    format!("{:e}", value)
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for ValueFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "u8" => Ok(ValueFormat::U8),
+            "u16" => Ok(ValueFormat::U16),
+            "u32" => Ok(ValueFormat::U32),
+            "i32" => Ok(ValueFormat::I32),
+            "f32" => Ok(ValueFormat::F32),
+            _ => Err(format!("Unknown value type: {}", s)),
+        }
+    }
+}
+
+/// Line terminator for written Intel HEX.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::CrLf),
+            _ => Err(format!("Unknown line ending: {}", s)),
+        }
+    }
+}
+
+/// How [`HexFile::write`] formats its output, to match what downstream
+/// tools expect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Most data bytes per record. Without a limit, data is kept in the
+    /// records it came in and only split where it has to be.
+    pub record_len: Option<u8>,
+    /// Write hex digits in lowercase.
+    pub lowercase: bool,
+    pub line_ending: LineEnding,
+    /// Write the start address record, if the image has a start address.
+    pub start_addr: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            record_len: None,
+            lowercase: false,
+            line_ending: LineEnding::Lf,
+            start_addr: true,
+        }
+    }
+}
+
+/// A byte that differs between two files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteDiff {
+    pub addr: u32,
+    pub left: u8,
+    pub right: u8,
+}
+
+/// Walks the sorted records of two files side by side, yielding the bytes
+/// that differ at addresses present in both.
+pub struct ByteDiffs<'a> {
+    left: &'a [Data],
+    right: &'a [Data],
+    next: u64,
+}
+
+impl Iterator for ByteDiffs<'_> {
+    type Item = ByteDiff;
+
+    fn next(&mut self) -> Option<ByteDiff> {
+        loop {
+            let (l, r) = (self.left.first()?, self.right.first()?);
+            let (l_range, r_range) = (l.addr_range(), r.addr_range());
+
+            let start = (l_range.start.max(r_range.start) as u64).max(self.next);
+            let end = l_range.end.min(r_range.end) as u64;
+            if start <= end {
+                let both = AddrRange {
+                    start: start as u32,
+                    end: end as u32,
+                };
+                if let Some((pos, (left, right))) = l
+                    .iter_range(both)
+                    .zip(r.iter_range(both))
+                    .find_position(|(a, b)| a != b)
+                {
+                    let addr = start + pos as u64;
+                    self.next = addr + 1;
+                    return Some(ByteDiff {
+                        addr: addr as u32,
+                        left,
+                        right,
+                    });
+                }
+            }
+
+            if l_range.end <= r_range.end {
+                self.left = &self.left[1..];
+            } else {
+                self.right = &self.right[1..];
+            }
+        }
+    }
+}
+
+/// What [`diff_records`] compares and what it records.
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Only compare the images inside this range.
+    pub window: Option<AddrRange>,
+    /// Ranges whose bytes to keep in [`DiffSide::kept`], such as those of
+    /// metadata fields.
+    pub keep: Vec<AddrRange>,
+    /// Count the differing bytes without listing them.
+    pub count_only: bool,
+}
+
+/// What [`diff_records`] found out about one of the images.
+#[derive(Debug, Default)]
+pub struct DiffSide {
+    /// Address ranges holding data.
+    pub ranges: Vec<AddrRange>,
+    /// Address ranges holding data in this image but not the other.
+    pub only_here: Vec<AddrRange>,
+    /// The start address, and the bytes inside [`DiffOptions::keep`].
+    pub kept: HexFile,
+}
+
+/// The differences between two images, see [`diff_records`].
+#[derive(Debug, Default)]
+pub struct RecordDiff {
+    pub left: DiffSide,
+    pub right: DiffSide,
+    /// The bytes that differ at addresses present in both, unless
+    /// [`DiffOptions::count_only`] is set.
+    pub diffs: Vec<ByteDiff>,
+    pub diff_count: u64,
+}
+
+/// Compares the images two record streams describe, walking both side by
+/// side so that only one data record of each is held at a time, however
+/// large the images are.
+///
+/// That needs the records of each stream in address order, as nearly every
+/// tool writes them. If either stream's aren't, `Ok(None)` is returned and
+/// the images have to be loaded whole to be compared with
+/// [`HexFile::diff`].
+pub fn diff_records(
+    left: impl IntoIterator<Item = eyre::Result<Record>>,
+    right: impl IntoIterator<Item = eyre::Result<Record>>,
+    opts: &DiffOptions,
+) -> eyre::Result<Option<RecordDiff>> {
+    let keep = coalesce(opts.keep.clone());
+    let mut l = DiffCursor::new(left.into_iter(), opts.window, &keep);
+    let mut r = DiffCursor::new(right.into_iter(), opts.window, &keep);
+    let mut diff = RecordDiff::default();
+    if !l.advance()? || !r.advance()? {
+        return Ok(None);
+    }
+    loop {
+        let in_order = match (&l.current, &r.current) {
+            (None, None) => break,
+            (Some(l_range), None) => {
+                push_range(&mut l.side.only_here, *l_range);
+                l.advance()?
+            }
+            (None, Some(r_range)) => {
+                push_range(&mut r.side.only_here, *r_range);
+                r.advance()?
+            }
+            (Some(l_range), Some(r_range)) if l_range.start < r_range.start => {
+                let end = l_range.end.min(r_range.start - 1);
+                push_range(&mut l.side.only_here, AddrRange { end, ..*l_range });
+                l.consume(end)?
+            }
+            (Some(l_range), Some(r_range)) if r_range.start < l_range.start => {
+                let end = r_range.end.min(l_range.start - 1);
+                push_range(&mut r.side.only_here, AddrRange { end, ..*r_range });
+                r.consume(end)?
+            }
+            (Some(l_range), Some(r_range)) => {
+                let both = AddrRange {
+                    start: l_range.start,
+                    end: l_range.end.min(r_range.end),
+                };
+                let (l_data, r_data) = (l.data.as_ref().unwrap(), r.data.as_ref().unwrap());
+                for (addr, (left, right)) in (both.start..=both.end)
+                    .zip(l_data.iter_range(both).zip(r_data.iter_range(both)))
+                    .filter(|(_, (left, right))| left != right)
+                {
+                    diff.diff_count += 1;
+                    if !opts.count_only {
+                        diff.diffs.push(ByteDiff { addr, left, right });
+                    }
+                }
+                l.consume(both.end)? && r.consume(both.end)?
+            }
+        };
+        if !in_order {
+            return Ok(None);
+        }
+    }
+    diff.left = l.side;
+    diff.right = r.side;
+    Ok(Some(diff))
+}
+
+/// Appends `range` to `ranges`, joining it to the last one if adjacent.
+fn push_range(ranges: &mut Vec<AddrRange>, range: AddrRange) {
+    match ranges.last_mut() {
+        Some(last) if last.end.checked_add(1) == Some(range.start) => last.end = range.end,
+        _ => ranges.push(range),
+    }
+}
+
+/// One of the record streams [`diff_records`] walks.
+struct DiffCursor<'a, I> {
+    records: I,
+    window: Option<AddrRange>,
+    keep: &'a [AddrRange],
+    /// The data record being compared.
+    data: Option<Data>,
+    /// The part of it not compared yet.
+    current: Option<AddrRange>,
+    /// Where the next data record may start at the earliest.
+    next_addr: u64,
+    side: DiffSide,
+}
+
+impl<'a, I: Iterator<Item = eyre::Result<Record>>> DiffCursor<'a, I> {
+    fn new(records: I, window: Option<AddrRange>, keep: &'a [AddrRange]) -> Self {
+        Self {
+            records,
+            window,
+            keep,
+            data: None,
+            current: None,
+            next_addr: 0,
+            side: DiffSide::default(),
+        }
+    }
+
+    /// Moves on to the next data record inside the window, returning false
+    /// if it starts before the end of the one before it.
+    fn advance(&mut self) -> eyre::Result<bool> {
+        self.data = None;
+        self.current = None;
+        while let Some(record) = self.records.next().transpose()? {
+            let d = match record {
+                Record::Data(d) => d,
+                Record::StartSegmentAddr(s) => {
+                    self.side.kept.start = Some(StartAddr::Segment(s));
+                    continue;
+                }
+                Record::StartLinearAddr(addr) => {
+                    self.side.kept.start = Some(StartAddr::Linear(addr));
+                    continue;
+                }
+                _ => continue,
+            };
+            let range = d.addr_range();
+            if (range.start as u64) < self.next_addr {
+                return Ok(false);
+            }
+            self.next_addr = range.end as u64 + 1;
+            let Some(range) = self.window.map_or(Some(range), |w| range.intersection(w)) else {
+                continue;
+            };
+            push_range(&mut self.side.ranges, range);
+            for keep in self.keep.iter().filter_map(|k| k.intersection(range)) {
+                self.side.kept.data.push(d.sub(keep));
+            }
+            self.data = Some(d);
+            self.current = Some(range);
+            break;
+        }
+        Ok(true)
+    }
+
+    /// Marks the current record compared up to `end`, moving on to the next
+    /// one once it all is.
+    fn consume(&mut self, end: u32) -> eyre::Result<bool> {
+        match &mut self.current {
+            Some(current) if end < current.end => {
+                current.start = end + 1;
+                Ok(true)
+            }
+            _ => self.advance(),
+        }
+    }
+}
+
+/// Incremental Intel HEX parser over any buffered reader, so large inputs
+/// can be processed record by record without reading them in whole.
+///
+/// Parsing never panics, whatever the input: malformed records are reported
+/// as [`ParseError`]s carrying their line and column, so untrusted files can
+/// be fed in as they are.
+pub struct Context<R> {
+    reader: R,
+    line: Vec<u8>,
+    base: u32,
+    eof: bool,
+    eof_line: usize,
+    line_idx: usize,
+    line_offset: usize,
+    next_offset: usize,
+    lenient: bool,
+    allow_missing_eof: bool,
+    missing_eof: bool,
+    compress: bool,
+    trailing_policy: TrailingPolicy,
+    trailing: Option<Trailing>,
+    warnings: Vec<ParseError>,
+    counts: RecordCounts,
+    bad_checksums: Vec<usize>,
+}
+
+impl<R: BufRead> Context<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: Vec::new(),
+            base: 0,
+            eof: false,
+            eof_line: 0,
+            line_idx: 0,
+            line_offset: 0,
+            next_offset: 0,
+            lenient: false,
+            allow_missing_eof: false,
+            missing_eof: false,
+            compress: false,
+            trailing_policy: TrailingPolicy::Error,
+            trailing: None,
+            warnings: Vec::new(),
+            counts: RecordCounts::default(),
+            bad_checksums: Vec::new(),
+        }
+    }
+
+    /// In lenient mode malformed lines are skipped and collected as warnings
+    /// instead of aborting the parse.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Accept input that ends without an EOF record, as truncated captures
+    /// do, instead of failing. See [`Context::missing_eof`].
+    pub fn allow_missing_eof(mut self, allow: bool) -> Self {
+        self.allow_missing_eof = allow;
+        self
+    }
+
+    /// Compress images as they're parsed, as [`HexFile::compress`] does
+    /// afterwards, so that a huge mostly blank input is never held in
+    /// memory byte by byte.
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Whether the input ended without an EOF record. Only possible with
+    /// [`Context::allow_missing_eof`].
+    pub fn missing_eof(&self) -> bool {
+        self.missing_eof
+    }
+
+    /// What to do with lines following the EOF record, other than blank
+    /// ones and comments. By default they're an error.
+    pub fn trailing(mut self, policy: TrailingPolicy) -> Self {
+        self.trailing_policy = policy;
+        self
+    }
+
+    /// Content that followed the EOF record and was skipped under
+    /// [`TrailingPolicy::Ignore`].
+    pub fn ignored_trailing(&self) -> Option<&Trailing> {
+        self.trailing.as_ref()
+    }
+
+    /// Parses the remaining input into a [`HexFile`].
+    pub fn parse(&mut self) -> eyre::Result<HexFile> {
+        let compress = self.compress;
+        self.records()
+            .process_results(|records| HexFile::collect(records, compress))
+    }
+
+    /// Parses input holding several complete images back-to-back, each ended
+    /// by its own EOF record, into one [`HexFile`] per image. Start addresses
+    /// and extended addresses don't carry over from one image to the next.
+    pub fn parse_images(&mut self) -> eyre::Result<Vec<HexFile>> {
+        self.trailing_policy = TrailingPolicy::Concatenated;
+        let mut images = Vec::new();
+        let mut records = Vec::new();
+        while let Some(record) = self.next_record()? {
+            match record {
+                Record::Eof => images.push(HexFile::collect(records.drain(..), self.compress)),
+                record => records.push(record),
+            }
+        }
+        // Only reachable with a missing EOF record
+        if !records.is_empty() {
+            images.push(HexFile::collect(records, self.compress));
+        }
+        Ok(images)
+    }
+
+    /// Computes the address ranges without keeping any record data around.
+    pub fn address_ranges(&mut self) -> eyre::Result<Vec<AddrRange>> {
+        let mut ranges: Vec<AddrRange> = Vec::new();
+        while let Some(record) = self.next_record()? {
+            match record {
+                Record::Eof => {}
+                Record::Data(d) => {
+                    let range = d.addr_range();
+                    match ranges.last_mut() {
+                        Some(last) if last.end.checked_add(1) == Some(range.start) => {
+                            last.end = range.end;
+                        }
+                        _ => ranges.push(range),
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(coalesce(ranges))
+    }
+
+    /// Finds the start address, skipping over all data records.
+    pub fn start_addr(&mut self) -> eyre::Result<Option<u32>> {
+        let mut start = None;
+        while let Some(record) = self.next_record()? {
+            match record {
+                Record::Eof => {}
+                Record::StartSegmentAddr(s) => start = Some(s.addr()),
+                Record::StartLinearAddr(addr) => start = Some(addr),
+                _ => {}
+            }
+        }
+        Ok(start)
+    }
+
+    /// Writes records to `w` in file order as they are read.
+    pub fn pretty_print<W: io::Write>(&mut self, mut w: W) -> eyre::Result<()> {
+        while let Some(record) = self.next_record()? {
+            match record {
+                Record::Eof => {}
+                Record::Data(d) => d.pretty_print(&mut w)?,
+                Record::StartSegmentAddr(start) => {
+                    StartAddr::Segment(start).pretty_print(&mut w)?
+                }
+                Record::StartLinearAddr(addr) => StartAddr::Linear(addr).pretty_print(&mut w)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Lines skipped in lenient mode.
+    pub fn into_warnings(self) -> Vec<ParseError> {
+        self.warnings
+    }
+
+    /// Number of records of each kind read so far.
+    pub fn record_counts(&self) -> &RecordCounts {
+        &self.counts
+    }
+
+    /// Line numbers of the records read so far whose checksum doesn't match.
+    /// These are still parsed normally.
+    pub fn bad_checksums(&self) -> &[usize] {
+        &self.bad_checksums
+    }
+
+    /// Where the line last read starts, counting from where reading began,
+    /// along with its line number and the extended address in effect for it.
+    pub(crate) fn position(&self) -> (usize, usize, u32) {
+        (self.line_offset, self.line_idx, self.base)
+    }
+
+    pub(crate) fn reader_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Whether images are compressed as they're parsed.
+    pub(crate) fn compresses(&self) -> bool {
+        self.compress
+    }
+
+    /// Iterates over the remaining records one at a time. Iteration stops
+    /// after the first error.
+    pub fn records(&mut self) -> Records<'_, R> {
+        Records {
+            ctx: self,
+            done: false,
+        }
+    }
+
+    /// Iterates over the original lines of the records holding data in
+    /// `range`, so they can be relayed verbatim instead of re-encoded.
+    /// Records straddling the edges of `range` are yielded whole. Each
+    /// extended address record is yielded just before the first of those
+    /// it applies to, and the EOF record last.
+    pub fn records_in(&mut self, range: AddrRange) -> RecordsIn<'_, R> {
+        RecordsIn {
+            ctx: self,
+            range,
+            ext_addr: None,
+            queued: None,
+            done: false,
+        }
+    }
+
+    /// Returns the next record, or `None` once the input is exhausted.
+    pub fn next_record(&mut self) -> eyre::Result<Option<Record>> {
+        loop {
+            let idx = if self.eof {
+                let Some(idx) = self.next_content_line()? else {
+                    return Ok(None);
+                };
+                if self.trailing_policy != TrailingPolicy::Concatenated {
+                    return self.skip_trailing(idx);
+                }
+                // Another image follows, starting out with a clean slate
+                self.eof = false;
+                self.base = 0;
+                idx
+            } else if let Some(idx) = self.next_line()? {
+                idx
+            } else if self.allow_missing_eof {
+                self.missing_eof = true;
+                self.eof = true;
+                return Ok(None);
+            } else {
+                return Err(eyre!("Unexpected EOF"));
+            };
+
+            let line = mem::take(&mut self.line);
+            let result = self
+                .parse_record(idx, &line)
+                .map_err(|e| e.with_source(&line, self.line_offset));
+            if result.is_ok() && !checksum_ok(&line) {
+                self.bad_checksums.push(idx);
+            }
+            self.line = line;
+
+            match &result {
+                Ok(Some(record)) => self.counts.add(record),
+                // Only empty data records are dropped
+                Ok(None) => self.counts.data += 1,
+                Err(_) => {}
+            }
+
+            match result {
+                Ok(Some(record)) => return Ok(Some(record)),
+                Ok(None) => {}
+                Err(e) if self.lenient => self.warnings.push(e),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Reads up to the next line after the EOF record with any content.
+    /// Comments may follow the EOF record, such as the ones recording
+    /// provenance, and so may blank lines, which editors tend to add.
+    fn next_content_line(&mut self) -> eyre::Result<Option<usize>> {
+        while let Some(idx) = self.next_line()? {
+            let blank = self.line.iter().all(u8::is_ascii_whitespace);
+            if !blank && self.line.first() != Some(&b';') {
+                return Ok(Some(idx));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Consumes everything after the EOF record from line `idx`, the first
+    /// with content, failing with a summary of it unless it is to be
+    /// ignored.
+    fn skip_trailing(&mut self, idx: usize) -> eyre::Result<Option<Record>> {
+        let (line, offset) = (self.line.clone(), self.line_offset);
+        let mut trailing = Trailing {
+            eof_line: self.eof_line,
+            lines: 1,
+            bytes: self.next_offset - self.line_offset,
+            first_line: idx,
+            first: String::from_utf8_lossy(&line).trim_end().to_string(),
+        };
+        while self.next_line()?.is_some() {
+            trailing.lines += 1;
+            trailing.bytes += self.next_offset - self.line_offset;
+        }
+        if self.trailing_policy == TrailingPolicy::Ignore {
+            self.trailing = Some(trailing);
+            return Ok(None);
+        }
+        let message = format!(
+            "{} line(s) ({} bytes) follow the EOF record on line {}, starting with {:?}",
+            trailing.lines, trailing.bytes, trailing.eof_line, trailing.first
+        );
+        Err(ParseError::new(idx, message)
+            .at(0, line.len())
+            .with_source(&line, offset)
+            .into())
+    }
+
+    fn parse_record(&mut self, idx: usize, line: &[u8]) -> Result<Option<Record>, ParseError> {
+        let Some(&first) = line.first() else {
+            return Err(ParseError::new(idx, "empty line"));
+        };
+        if first != b':' {
+            return Err(ParseError::new(idx, "doesn't start with ':'").at(0, 1));
+        }
+
+        let kind = hex_field(line, 7..=8, idx, "kind")? as u8;
+
+        match kind {
+            0x00 => {
+                let len = hex_field(line, 1..=2, idx, "len")? as u8;
+                let addr = hex_field(line, 3..=6, idx, "addr")? as u16;
+
+                let mut data = Vec::with_capacity(len as usize);
+
+                let digits = line.get(9..).unwrap_or_default();
+                for (i, byte) in digits.chunks(2).take(len as usize).enumerate() {
+                    let byte = hex_byte(byte).ok_or_else(|| {
+                        ParseError::new(idx, "invalid data byte").at(9 + 2 * i, byte.len())
+                    })?;
+                    data.push(byte);
+                }
+                if data.len() < len as usize {
+                    return Err(ParseError::new(
+                        idx,
+                        format!(
+                            "too few data bytes, expected {} but got {}",
+                            len,
+                            data.len()
+                        ),
+                    )
+                    .at(9 + 2 * data.len(), 2 * (len as usize - data.len())));
+                }
+
+                let Some(last) = len.checked_sub(1) else {
+                    return Ok(None);
+                };
+                let Some(addr) = self
+                    .base
+                    .checked_add(addr as u32)
+                    .filter(|a| a.checked_add(last as u32).is_some())
+                else {
+                    return Err(ParseError::new(
+                        idx,
+                        "record runs past the end of the address space",
+                    )
+                    .at(3, 4));
+                };
+
+                Ok(Some(Record::Data(Data::new(addr, data))))
+            }
+            0x01 => {
+                self.eof = true;
+                self.eof_line = idx;
+                Ok(Some(Record::Eof))
+            }
+            0x03 => {
+                let cs = hex_field(line, 9..=12, idx, "CS")? as u16;
+                let ip = hex_field(line, 13..=16, idx, "IP")? as u16;
+                Ok(Some(Record::StartSegmentAddr(StartSegmentAddr { cs, ip })))
+            }
+            0x02 => {
+                let segment = hex_field(line, 9..=12, idx, "segment")? as u16;
+                self.base = (segment as u32) << 4;
+                Ok(Some(Record::ExtSegmentAddr(segment)))
+            }
+            0x04 => {
+                let addr_hi = hex_field(line, 9..=12, idx, "addr_hi")? as u16;
+                self.base = (addr_hi as u32) << 16;
+                Ok(Some(Record::ExtLinearAddr(addr_hi)))
+            }
+            0x05 => {
+                let addr = hex_field(line, 9..=16, idx, "start addr")?;
+                Ok(Some(Record::StartLinearAddr(addr)))
+            }
+            _ => Err(ParseError::new(idx, format!("Unknown kind {:02X}", kind)).at(7, 2)),
+        }
+    }
+
+    fn next_line(&mut self) -> eyre::Result<Option<usize>> {
+        self.line.clear();
+        let read = self.reader.read_until(b'\n', &mut self.line)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if self.line.last() == Some(&b'\n') {
+            self.line.pop();
+        }
+        self.line_idx += 1;
+        self.line_offset = self.next_offset;
+        self.next_offset += read;
+        Ok(Some(self.line_idx))
+    }
+}
+
+/// Sorts `ranges` and merges those overlapping or adjacent.
+pub(crate) fn coalesce(mut ranges: Vec<AddrRange>) -> Vec<AddrRange> {
+    ranges.sort_by_key(|r| r.start);
+    let mut coalesced: Vec<AddrRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match coalesced.last_mut() {
+            Some(last)
+                if last
+                    .end
+                    .checked_add(1)
+                    .is_none_or(|next| range.start <= next) =>
+            {
+                last.end = last.end.max(range.end);
+            }
+            _ => coalesced.push(range),
+        }
+    }
+    coalesced
+}
+
+/// Parses a single line on its own, given the extended address in effect
+/// before it. Returns the record along with the extended address in effect
+/// after it.
+pub(crate) fn parse_line(
+    base: u32,
+    idx: usize,
+    line: &[u8],
+    line_offset: usize,
+) -> (Result<Option<Record>, ParseError>, u32) {
+    let mut ctx = Context::new(io::empty());
+    ctx.base = base;
+    let result = ctx
+        .parse_record(idx, line)
+        .map_err(|e| e.with_source(line, line_offset));
+    (result, ctx.base)
+}
+
+/// How to treat lines after the EOF record. Blank lines and comments are
+/// always skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailingPolicy {
+    /// Fail, describing what follows. The default.
+    Error,
+    /// Skip it, see [`Context::ignored_trailing`].
+    Ignore,
+    /// Parse it as another image concatenated to the first, as naive build
+    /// scripts produce.
+    Concatenated,
+}
+
+/// Content found after the EOF record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trailing {
+    /// Line number of the EOF record.
+    pub eof_line: usize,
+    pub lines: usize,
+    pub bytes: usize,
+    /// Line number and text of the first line with content.
+    pub first_line: usize,
+    pub first: String,
+}
+
+/// Per-kind record tallies, see [`Context::record_counts`].
+#[derive(Debug, Clone, Default)]
+pub struct RecordCounts {
+    pub data: usize,
+    pub eof: usize,
+    pub ext_segment_addr: usize,
+    pub start_segment_addr: usize,
+    pub ext_linear_addr: usize,
+    pub start_linear_addr: usize,
+}
+
+impl RecordCounts {
+    fn add(&mut self, record: &Record) {
+        let count = match record {
+            Record::Data(_) => &mut self.data,
+            Record::Eof => &mut self.eof,
+            Record::ExtSegmentAddr(_) => &mut self.ext_segment_addr,
+            Record::StartSegmentAddr(_) => &mut self.start_segment_addr,
+            Record::ExtLinearAddr(_) => &mut self.ext_linear_addr,
+            Record::StartLinearAddr(_) => &mut self.start_linear_addr,
+        };
+        *count += 1;
+    }
+
+    /// `(record type, count)` pairs in record type order.
+    pub fn by_type(&self) -> [(u8, usize); 6] {
+        [
+            (0x00, self.data),
+            (0x01, self.eof),
+            (0x02, self.ext_segment_addr),
+            (0x03, self.start_segment_addr),
+            (0x04, self.ext_linear_addr),
+            (0x05, self.start_linear_addr),
+        ]
+    }
+}
+
+/// Iterator over the records of a [`Context`], see [`Context::records`].
+pub struct Records<'a, R> {
+    ctx: &'a mut Context<R>,
+    done: bool,
+}
+
+impl<R: BufRead> Iterator for Records<'_, R> {
+    type Item = eyre::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let record = self.ctx.next_record().transpose();
+        self.done = matches!(record, None | Some(Err(_)));
+        record
+    }
+}
+
+/// A record along with the line it was parsed from, see
+/// [`Context::records_in`].
+#[derive(Debug, Clone)]
+pub struct RawRecord {
+    /// 1-based line number.
+    pub line: usize,
+    /// The line as read, without its line terminator.
+    pub text: Vec<u8>,
+    pub record: Record,
+}
+
+/// Iterator over the records of a [`Context`] holding data in an address
+/// range, see [`Context::records_in`].
+pub struct RecordsIn<'a, R> {
+    ctx: &'a mut Context<R>,
+    range: AddrRange,
+    /// Extended address record not yet yielded
+    ext_addr: Option<RawRecord>,
+    /// Data record to yield after the extended address record before it
+    queued: Option<RawRecord>,
+    done: bool,
+}
+
+impl<R: BufRead> Iterator for RecordsIn<'_, R> {
+    type Item = eyre::Result<RawRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(raw) = self.queued.take() {
+            return Some(Ok(raw));
+        }
+        while !self.done {
+            let record = match self.ctx.next_record() {
+                Ok(Some(record)) => record,
+                Ok(None) => break,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+            let line = &self.ctx.line;
+            let raw = RawRecord {
+                line: self.ctx.line_idx,
+                text: line.strip_suffix(b"\r").unwrap_or(line).to_vec(),
+                record,
+            };
+            match &raw.record {
+                Record::Data(d) if d.addr_range().overlaps(self.range) => {
+                    let Some(ext_addr) = self.ext_addr.take() else {
+                        return Some(Ok(raw));
+                    };
+                    self.queued = Some(raw);
+                    return Some(Ok(ext_addr));
+                }
+                Record::ExtSegmentAddr(_) | Record::ExtLinearAddr(_) => self.ext_addr = Some(raw),
+                Record::Eof => return Some(Ok(raw)),
+                _ => {}
+            }
+        }
+        self.done = true;
+        None
+    }
+}
+
+/// Whether the bytes of a record, including its checksum, sum to zero.
+fn checksum_ok(line: &[u8]) -> bool {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let Some(len) = line.get(1..3).and_then(hex_byte) else {
+        return false;
+    };
+    let Some(record) = line.get(1..1 + 2 * (len as usize + 5)) else {
+        return false;
+    };
+    record
+        .chunks(2)
+        .try_fold(0u8, |sum, pair| Some(sum.wrapping_add(hex_byte(pair)?)))
+        == Some(0)
+}
+
+fn hex_field(
+    line: &[u8],
+    range: RangeInclusive<usize>,
+    idx: usize,
+    name: &str,
+) -> Result<u32, ParseError> {
+    let (start, width) = (*range.start(), range.end() - range.start() + 1);
+    let field = line.get(range).ok_or_else(|| {
+        ParseError::new(idx, format!("no {} field", name)).at(start.min(line.len()), width)
+    })?;
+    hex_value(field)
+        .ok_or_else(|| ParseError::new(idx, format!("invalid {} field", name)).at(start, width))
+}
+
+/// The value of up to 8 hex digits. Unlike `u32::from_str_radix` this
+/// takes nothing but digits, no sign.
+fn hex_value(digits: &[u8]) -> Option<u32> {
+    if digits.is_empty() || digits.len() > 8 {
+        return None;
+    }
+    digits.iter().try_fold(0u32, |acc, &d| {
+        let d = (d as char).to_digit(16)?;
+        Some(acc << 4 | d)
+    })
+}
+
+/// The byte a pair of hex digits stands for.
+fn hex_byte(pair: &[u8]) -> Option<u8> {
+    match pair {
+        [_, _] => hex_value(pair).map(|b| b as u8),
+        _ => None,
+    }
+}
+
+/// A malformed record, along with enough of the source to point at the
+/// offending field.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column of the offending field.
+    pub column: usize,
+    /// Number of characters the offending field spans.
+    pub width: usize,
+    /// Byte offset of the start of the line in the input.
+    pub line_offset: usize,
+    /// The offending line, without its line terminator.
+    pub content: String,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            column: 1,
+            width: 0,
+            line_offset: 0,
+            content: String::new(),
+            message: message.into(),
+        }
+    }
+
+    fn at(mut self, col_idx: usize, width: usize) -> Self {
+        self.column = col_idx + 1;
+        self.width = width;
+        self
+    }
+
+    fn with_source(mut self, line: &[u8], line_offset: usize) -> Self {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        self.content = String::from_utf8_lossy(line).into_owned();
+        self.line_offset = line_offset;
+        self
+    }
+
+    /// Byte offset of the offending field in the input.
+    pub fn offset(&self) -> usize {
+        self.line_offset + self.column.saturating_sub(1)
+    }
+
+    /// One-line description, without the source snippet.
+    pub fn headline(&self) -> String {
+        format!(
+            "Line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line_no = self.line.to_string();
+        let pad = " ".repeat(line_no.len());
+        writeln!(f, "{}", self.headline())?;
+        writeln!(
+            f,
+            "{}--> byte offset {} (0x{:x})",
+            pad,
+            self.offset(),
+            self.offset()
+        )?;
+        writeln!(f, "{} |", pad)?;
+        writeln!(f, "{} | {}", line_no, self.content)?;
+        write!(
+            f,
+            "{} | {}{}",
+            pad,
+            " ".repeat(self.column.saturating_sub(1)),
+            "^".repeat(self.width.max(1))
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Name of the Intel HEX record type `kind`.
+pub fn record_type_name(kind: u8) -> &'static str {
+    match kind {
+        0x00 => "Data",
+        0x01 => "End Of File",
+        0x02 => "Ext Segment Address",
+        0x03 => "Start Segment Address",
+        0x04 => "Ext Linear Address",
+        0x05 => "Start Linear Address",
+        _ => "Unknown",
+    }
+}
+
+/// A single parsed record. Data addresses already include the extended
+/// address in effect.
+#[derive(Debug, Clone)]
+pub enum Record {
+    Data(Data),
+    Eof,
+    ExtSegmentAddr(u16),
+    StartSegmentAddr(StartSegmentAddr),
+    ExtLinearAddr(u16),
+    StartLinearAddr(u32),
+}
+
+/// A non-empty run of bytes starting at an address.
+#[derive(Debug, Clone)]
+pub struct Data {
+    data: Bytes,
+    addr: u32,
+}
+
+/// The bytes of a data record, kept as they are or, when they're all the
+/// same, as that byte and their count.
+#[derive(Debug, Clone)]
+enum Bytes {
+    Literal(Vec<u8>),
+    Run {
+        byte: u8,
+        len: usize,
+        /// Length of the records the run was made from, which it's written
+        /// back out as
+        record_len: Option<u8>,
+    },
+}
+
+impl Data {
+    /// `data` must be non-empty and fit in the address space from `addr`.
+    pub(crate) fn new(addr: u32, data: Vec<u8>) -> Self {
+        debug_assert!(!data.is_empty());
+        Self {
+            data: Bytes::Literal(data),
+            addr,
+        }
+    }
+
+    /// `len` copies of `byte` from `addr`, stored in constant space.
+    fn run(addr: u32, byte: u8, len: usize, record_len: Option<u8>) -> Self {
+        debug_assert!(len > 0);
+        Self {
+            data: Bytes::Run {
+                byte,
+                len,
+                record_len,
+            },
+            addr,
+        }
+    }
+
+    pub fn pretty_print<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        write!(w, "Addr: 0x{:08x}, ", self.addr)?;
+        write!(w, "Data: [")?;
+        for (i, &byte) in self.bytes().enumerate() {
+            write!(w, "{:02x}", byte)?;
+            if i == self.len() - 1 {
+                writeln!(w, "]")?;
+            } else {
+                write!(w, ", ")?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn addr(&self) -> u32 {
+        self.addr
+    }
+
+    /// Number of bytes in the record.
+    pub fn len(&self) -> usize {
+        match &self.data {
+            Bytes::Literal(data) => data.len(),
+            Bytes::Run { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The byte repeated throughout the record, if it's stored as a run by
+    /// [`HexFile::compress`].
+    pub fn run_byte(&self) -> Option<u8> {
+        match self.data {
+            Bytes::Literal(_) => None,
+            Bytes::Run { byte, .. } => Some(byte),
+        }
+    }
+
+    /// Splits the data into chunks that fit in Intel HEX data records of at
+    /// most `max_len` bytes. Data that already fits is kept as one record,
+    /// and a run goes back to records as long as those it was made from.
+    fn records(&self, max_len: Option<u8>) -> impl Iterator<Item = AddrRange> {
+        let max_len = match self.data {
+            Bytes::Run { record_len, .. } => max_len.or(record_len),
+            Bytes::Literal(_) => max_len,
+        };
+        let chunk_len = max_len.map_or(DEFAULT_RECORD_LEN, |len| len as usize);
+        let max_len = max_len.unwrap_or(u8::MAX) as usize;
+        let fits =
+            |addr: u32, len: usize| len <= max_len && (addr & 0xFFFF) as usize + len <= 0x1_0000;
+        let whole = fits(self.addr, self.len());
+        let end = self.addr_range().end;
+        let mut next = Some(self.addr);
+        std::iter::from_fn(move || {
+            let addr = next?;
+            let to_boundary = 0x1_0000 - (addr & 0xFFFF) as usize;
+            let rest = (end - addr) as usize + 1;
+            let len = if whole {
+                rest
+            } else {
+                rest.min(chunk_len - addr as usize % chunk_len)
+                    .min(to_boundary)
+            };
+            let chunk = AddrRange {
+                start: addr,
+                end: addr + (len as u32 - 1),
+            };
+            next = chunk.end.checked_add(1).filter(|_| chunk.end < end);
+            Some(chunk)
+        })
+    }
+
+    pub fn addr_range(&self) -> AddrRange {
+        AddrRange {
+            start: self.addr,
+            end: self.addr + (self.len() as u32 - 1),
+        }
+    }
+
+    /// The bytes of the record one by one, without expanding a run.
+    pub fn bytes(&self) -> impl Iterator<Item = &u8> + '_ {
+        match &self.data {
+            Bytes::Literal(data) => Either::Left(data.iter()),
+            Bytes::Run { byte, len, .. } => Either::Right(std::iter::repeat_n(byte, *len)),
+        }
+    }
+
+    /// The bytes of the record as one slice, expanded if it's stored as a
+    /// run.
+    pub fn to_bytes(&self) -> Cow<'_, [u8]> {
+        match &self.data {
+            Bytes::Literal(data) => Cow::Borrowed(data),
+            &Bytes::Run { byte, len, .. } => Cow::Owned(vec![byte; len]),
+        }
+    }
+
+    /// The bytes at the addresses in `range`, which must lie in this record,
+    /// without expanding a run.
+    fn iter_range(&self, range: AddrRange) -> impl Iterator<Item = u8> + '_ {
+        match &self.data {
+            Bytes::Literal(data) => Either::Left(
+                data[(range.start - self.addr) as usize..=(range.end - self.addr) as usize]
+                    .iter()
+                    .copied(),
+            ),
+            &Bytes::Run { byte, .. } => {
+                Either::Right(std::iter::repeat_n(byte, range.size() as usize))
+            }
+        }
+    }
+
+    /// The bytes at the addresses in `range`, which must lie in this record.
+    fn slice(&self, range: AddrRange) -> Cow<'_, [u8]> {
+        match &self.data {
+            Bytes::Literal(data) => Cow::Borrowed(
+                &data[(range.start - self.addr) as usize..=(range.end - self.addr) as usize],
+            ),
+            &Bytes::Run { byte, .. } => Cow::Owned(vec![byte; range.size() as usize]),
+        }
+    }
+
+    /// The part of the record at the addresses in `range`, which must lie in
+    /// it, stored the same way.
+    fn sub(&self, range: AddrRange) -> Data {
+        match self.data {
+            Bytes::Literal(_) => Data::new(range.start, self.slice(range).into_owned()),
+            Bytes::Run {
+                byte, record_len, ..
+            } => Data::run(range.start, byte, range.size() as usize, record_len),
+        }
+    }
+
+    /// Copies the record into `out`, which must be as long as it.
+    fn copy_to(&self, out: &mut [u8]) {
+        match &self.data {
+            Bytes::Literal(data) => out.copy_from_slice(data),
+            &Bytes::Run { byte, .. } => out.fill(byte),
+        }
+    }
+
+    /// The bytes of the record for changing them, expanding a run.
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        if let Bytes::Run { byte, len, .. } = self.data {
+            self.data = Bytes::Literal(vec![byte; len]);
+        }
+        match &mut self.data {
+            Bytes::Literal(data) => data,
+            Bytes::Run { .. } => unreachable!(),
+        }
+    }
+
+    /// Splits the record into runs of one byte, for a record that's nothing
+    /// else or ones at least `min_run` long, and the literal bytes between
+    /// them.
+    fn split_runs(self, min_run: usize) -> Vec<Data> {
+        let Bytes::Literal(data) = &self.data else {
+            return vec![self];
+        };
+        if data.iter().all_equal() {
+            let record_len = u8::try_from(data.len()).ok();
+            return vec![Data::run(self.addr, data[0], data.len(), record_len)];
+        }
+        let mut pieces = Vec::new();
+        let (mut literal_start, mut pos) = (0, 0);
+        for run in data.chunk_by(|a, b| a == b) {
+            let len = run.len();
+            if len >= min_run {
+                if literal_start < pos {
+                    pieces.push(Data::new(
+                        self.addr + literal_start as u32,
+                        data[literal_start..pos].to_vec(),
+                    ));
+                }
+                pieces.push(Data::run(self.addr + pos as u32, run[0], len, None));
+                literal_start = pos + len;
+            }
+            pos += len;
+        }
+        if pieces.is_empty() {
+            return vec![self];
+        }
+        if literal_start < pos {
+            pieces.push(Data::new(
+                self.addr + literal_start as u32,
+                data[literal_start..].to_vec(),
+            ));
+        }
+        pieces
+    }
+
+    /// The byte at `addr`, if it falls inside this record.
+    pub fn get_byte(&self, addr: u32) -> Option<u8> {
+        let offset = addr.checked_sub(self.addr)? as usize;
+        match &self.data {
+            Bytes::Literal(data) => data.get(offset).copied(),
+            &Bytes::Run { byte, len, .. } => (offset < len).then_some(byte),
+        }
+    }
+}
+
+/// How to resolve bytes present in more than one image when combining them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    /// Refuse to combine overlapping images.
+    Error,
+    /// Keep the bytes that were there first.
+    FirstWins,
+    /// Overwrite with the bytes added last.
+    LastWins,
+}
+
+impl FromStr for OverlapPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(OverlapPolicy::Error),
+            "first-wins" => Ok(OverlapPolicy::FirstWins),
+            "last-wins" => Ok(OverlapPolicy::LastWins),
+            _ => Err(format!("Unknown overlap policy: {}", s)),
+        }
+    }
+}
+
+/// A bitwise operation combining image bytes with a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOp {
+    Xor,
+    And,
+    Or,
+}
+
+impl BitOp {
+    pub fn apply(&self, byte: u8, key: u8) -> u8 {
+        match self {
+            BitOp::Xor => byte ^ key,
+            BitOp::And => byte & key,
+            BitOp::Or => byte | key,
+        }
+    }
+}
+
+impl FromStr for BitOp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "xor" => Ok(BitOp::Xor),
+            "and" => Ok(BitOp::And),
+            "or" => Ok(BitOp::Or),
+            _ => Err(format!("Unknown operation: {}", s)),
+        }
+    }
+}
+
+/// The entry point of an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StartAddr {
+    Segment(StartSegmentAddr),
+    Linear(u32),
+}
+
+impl StartAddr {
+    pub fn addr(&self) -> u32 {
+        match self {
+            StartAddr::Segment(s) => s.addr(),
+            StartAddr::Linear(addr) => *addr,
+        }
+    }
+
+    /// The address execution starts at, a segment start's real-mode one.
+    pub fn entry(&self) -> u32 {
+        match self {
+            StartAddr::Segment(s) => s.physical(),
+            StartAddr::Linear(addr) => *addr,
+        }
+    }
+
+    pub fn pretty_print<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        match self {
+            StartAddr::Segment(s) => {
+                writeln!(w, "Start Addr: CS = 0x{:04x}, IP = 0x{:04x}\n", s.cs, s.ip)
+            }
+            StartAddr::Linear(addr) => writeln!(w, "Start Addr: 0x{:08x}\n", addr),
+        }
+    }
+}
+
+/// Start address given as an x86 real-mode CS:IP pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StartSegmentAddr {
+    pub cs: u16,
+    pub ip: u16,
+}
+
+impl StartSegmentAddr {
+    pub fn addr(&self) -> u32 {
+        ((self.cs as u32) << 16) | (self.ip as u32)
+    }
+
+    /// The address execution starts at, as real mode computes it.
+    pub fn physical(&self) -> u32 {
+        ((self.cs as u32) << 4) + self.ip as u32
+    }
+
+    /// A CS:IP pair reaching `addr`, keeping `cs` if it can.
+    fn from_physical(addr: u32, cs: u16) -> Option<Self> {
+        if let Some(ip) = addr
+            .checked_sub((cs as u32) << 4)
+            .and_then(|ip| u16::try_from(ip).ok())
+        {
+            return Some(Self { cs, ip });
+        }
+        Some(Self {
+            cs: u16::try_from(addr >> 4).ok()?,
+            ip: (addr & 0xF) as u16,
+        })
+    }
 }
@@ -0,0 +1,72 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use eyre::eyre;
+use eyre::Context;
+
+/// Names for addresses, read from a labels file.
+///
+/// Each line holds a name and an address, in either order and optionally
+/// separated by `=` as in a linker script assignment:
+///
+/// ```text
+/// # comment
+/// config_block = 0x0800F000;
+/// 0x08000000 vector_table
+/// ```
+#[derive(Debug, Default)]
+pub struct Labels {
+    addrs: HashMap<String, u32>,
+}
+
+impl Labels {
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Reading labels {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Parsing labels {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> eyre::Result<Self> {
+        let mut labels = Labels::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let line = line.trim().trim_end_matches(';');
+            if line.is_empty() {
+                continue;
+            }
+            let tokens = line
+                .split(|c: char| c.is_whitespace() || c == '=')
+                .filter(|t| !t.is_empty())
+                .collect::<Vec<_>>();
+            let [a, b] = tokens.as_slice() else {
+                return Err(eyre!("Line {}: expected a name and an address", idx + 1));
+            };
+            // Names can look like hex numbers too, so a `0x` prefix decides
+            let (name, addr) = if is_prefixed(a) && !is_prefixed(b) || parse_addr(b).is_none() {
+                (b, a)
+            } else {
+                (a, b)
+            };
+            let addr = parse_addr(addr)
+                .ok_or_else(|| eyre!("Line {}: invalid address {}", idx + 1, addr))?;
+            labels.addrs.insert(name.to_string(), addr);
+        }
+        Ok(labels)
+    }
+
+    pub fn get(&self, name: &str) -> Option<u32> {
+        self.addrs.get(name).copied()
+    }
+}
+
+fn is_prefixed(s: &str) -> bool {
+    s.starts_with("0x") || s.starts_with("0X")
+}
+
+/// Addresses in labels files are hex, with or without a `0x` prefix.
+fn parse_addr(s: &str) -> Option<u32> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u32::from_str_radix(digits, 16).ok()
+}
@@ -0,0 +1,143 @@
+//! Images indexed in one pass over their text and decoded only where they're
+//! read, for inputs too large to hold in memory whole.
+//!
+//! Instead of the data bytes, [`LazyHexFile`] keeps where in the input each
+//! run of records lies and which addresses it covers, a few bytes for every
+//! [`CHUNK_LEN`] bytes of data. Reading a range seeks back to the records
+//! holding it and parses them again.
+
+use std::io::{BufRead, Seek, SeekFrom};
+
+use eyre::eyre;
+
+use crate::hex::{self, AddrRange, Context, HexFile, Record};
+
+/// Most data bytes indexed as one chunk, and so about the most decoded
+/// beyond what was asked for.
+pub const CHUNK_LEN: u64 = 0x4000;
+
+/// Consecutive data records holding contiguous addresses.
+#[derive(Debug, Clone, Copy)]
+struct Chunk {
+    range: AddrRange,
+    /// Extended address in effect for the records.
+    base: u32,
+    /// Start of the first record, from where indexing began.
+    offset: usize,
+    /// Line number of the first record.
+    line: usize,
+    lines: usize,
+}
+
+/// An Intel HEX image indexed by [`LazyHexFile::index`], whose data is read
+/// back from the input on demand.
+pub struct LazyHexFile<R> {
+    ctx: Context<R>,
+    /// Where indexing began in the reader.
+    origin: u64,
+    /// In input order.
+    chunks: Vec<Chunk>,
+    start: Option<Record>,
+}
+
+impl<R: BufRead + Seek> LazyHexFile<R> {
+    /// Reads all of `ctx`'s input once, keeping only where its data records
+    /// are. The options of `ctx` apply as when parsing.
+    pub fn index(mut ctx: Context<R>) -> eyre::Result<Self> {
+        let origin = ctx.reader_mut().stream_position()?;
+        let mut chunks: Vec<Chunk> = Vec::new();
+        let mut start = None;
+        while let Some(record) = ctx.next_record()? {
+            match record {
+                Record::Data(d) => {
+                    let range = d.addr_range();
+                    let (offset, line, base) = ctx.position();
+                    match chunks.last_mut() {
+                        Some(last)
+                            if last.line + last.lines == line
+                                && last.base == base
+                                && last.range.end.checked_add(1) == Some(range.start)
+                                && last.range.size() < CHUNK_LEN =>
+                        {
+                            last.range.end = range.end;
+                            last.lines += 1;
+                        }
+                        _ => chunks.push(Chunk {
+                            range,
+                            base,
+                            offset,
+                            line,
+                            lines: 1,
+                        }),
+                    }
+                }
+                record @ (Record::StartSegmentAddr(_) | Record::StartLinearAddr(_)) => {
+                    start = Some(record)
+                }
+                _ => {}
+            }
+        }
+        Ok(Self {
+            ctx,
+            origin,
+            chunks,
+            start,
+        })
+    }
+
+    /// The context indexing went through, for its record counts and
+    /// warnings.
+    pub fn context(&self) -> &Context<R> {
+        &self.ctx
+    }
+
+    pub fn into_context(self) -> Context<R> {
+        self.ctx
+    }
+
+    /// Same as [`HexFile::address_ranges`], without decoding anything.
+    pub fn address_ranges(&self) -> Vec<AddrRange> {
+        hex::coalesce(self.chunks.iter().map(|c| c.range).collect())
+    }
+
+    /// Same as [`HexFile::start_addr`].
+    pub fn start_addr(&self) -> Option<u32> {
+        match self.start {
+            Some(Record::StartSegmentAddr(s)) => Some(s.addr()),
+            Some(Record::StartLinearAddr(addr)) => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// Decodes the data inside `range`, along with the start address. Only
+    /// the records holding it are read again, but the image returned holds
+    /// all of it, so ranges should be kept to what's needed at once.
+    pub fn extract(&mut self, range: AddrRange) -> eyre::Result<HexFile> {
+        let mut records = Vec::new();
+        let mut line = Vec::new();
+        for chunk in self.chunks.iter().filter(|c| c.range.overlaps(range)) {
+            let reader = self.ctx.reader_mut();
+            reader.seek(SeekFrom::Start(self.origin + chunk.offset as u64))?;
+            let mut offset = chunk.offset;
+            for idx in chunk.line..chunk.line + chunk.lines {
+                line.clear();
+                let line_offset = offset;
+                offset += reader.read_until(b'\n', &mut line)?;
+                if line.last() == Some(&b'\n') {
+                    line.pop();
+                }
+                match hex::parse_line(chunk.base, idx, &line, line_offset).0? {
+                    Some(Record::Data(d)) if d.addr_range().overlaps(range) => {
+                        records.push(Record::Data(d))
+                    }
+                    Some(Record::Data(_)) => {}
+                    _ => return Err(eyre!("Line {} changed since it was indexed", idx)),
+                }
+            }
+        }
+        records.extend(self.start.clone());
+        let mut hex_file = HexFile::collect(records, self.ctx.compresses());
+        hex_file.crop(&[range]);
+        Ok(hex_file)
+    }
+}
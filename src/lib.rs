@@ -0,0 +1,41 @@
+//! Parsing, inspection and conversion of Intel HEX firmware images.
+//!
+//! [`hex::Context`] parses an image into a [`HexFile`], which can be queried,
+//! modified and written back out as Intel HEX or converted with `elf`.
+//!
+//! Only the parser and what builds on it are in by default for library use;
+//! the `elf` feature adds ELF conversion and `cli` the `hex-reader` binary,
+//! which is on by default. Embed with `default-features = false` to leave
+//! out the command line dependencies.
+
+pub mod cache;
+pub mod checksum;
+pub mod chips;
+pub mod config;
+pub mod doctor;
+pub mod eeprom;
+#[cfg(feature = "elf")]
+pub mod elf;
+pub mod explain;
+pub mod fields;
+pub mod flash;
+pub mod format;
+pub mod hex;
+pub mod json;
+pub mod labels;
+pub mod lazy;
+pub mod memory_map;
+pub mod provenance;
+pub mod range_set;
+pub mod snapshot;
+pub mod svd;
+pub mod symbols;
+pub mod trace32;
+pub mod vcdiff;
+
+pub use hex::{
+    AddrRange, BitOp, ClusterOrder, Context, Data, HexFile, LineEnding, OverlapPolicy, ParseError,
+    PrintOptions, RawRecord, Record, RecordCounts, Records, RecordsIn, ValueFormat, WriteOptions,
+};
+pub use lazy::LazyHexFile;
+pub use range_set::AddrRangeSet;
@@ -0,0 +1,259 @@
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use eyre::eyre;
+use eyre::Context;
+
+use crate::config::{self, Value};
+use crate::hex::AddrRange;
+
+/// What a region may hold besides data to read, as the attributes of a
+/// linker script's `MEMORY` command.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Access {
+    pub write: bool,
+    pub exec: bool,
+}
+
+impl FromStr for Access {
+    type Err = String;
+
+    /// `r` followed by any of `w` and `x`, such as `rx` for flash.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let flags = s
+            .to_ascii_lowercase()
+            .strip_prefix('r')
+            .map(str::to_string)
+            .ok_or_else(|| format!("Access {} must start with r", s))?;
+        let mut access = Access::default();
+        for flag in flags.chars() {
+            match flag {
+                'w' if !access.write => access.write = true,
+                'x' if !access.exec => access.exec = true,
+                _ => return Err(format!("Access {} isn't r followed by w and/or x", s)),
+            }
+        }
+        Ok(access)
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "r{}{}",
+            if self.write { "w" } else { "" },
+            if self.exec { "x" } else { "" }
+        )
+    }
+}
+
+/// A named stretch of the address space, such as `FLASH` or `SRAM`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: String,
+    pub range: AddrRange,
+    /// Read-only unless given.
+    pub access: Access,
+    /// ELF section for the data in the region, see [`Region::section`].
+    pub section: Option<String>,
+    /// Whether tools must not move data into the region, as for option
+    /// bytes or a bootloader. Set by `reserved = true` or by giving
+    /// `access = "r"` explicitly.
+    pub reserved: bool,
+}
+
+impl Region {
+    /// A read-only region with the default section.
+    pub fn new(name: impl Into<String>, range: AddrRange) -> Self {
+        Region {
+            name: name.into(),
+            range,
+            access: Access::default(),
+            section: None,
+            reserved: false,
+        }
+    }
+
+    /// The ELF section data in the region goes into: the one given, else
+    /// `.text` for executable regions, `.data` for writable ones and the
+    /// region's name in lowercase for the rest.
+    pub fn section(&self) -> String {
+        match &self.section {
+            Some(section) => section.clone(),
+            None if self.access.exec => ".text".to_string(),
+            None if self.access.write => ".data".to_string(),
+            None => format!(".{}", self.name.to_ascii_lowercase()),
+        }
+    }
+}
+
+/// The keys of a region table as read, before its bounds are checked.
+pub(crate) struct RegionTable {
+    pub name: String,
+    start: Option<u32>,
+    end: Option<u32>,
+    size: Option<u32>,
+    access: Option<Access>,
+    section: Option<String>,
+    reserved: Option<bool>,
+    /// Line of the table's first key.
+    line: usize,
+}
+
+impl RegionTable {
+    pub fn new(name: &str, line: usize) -> Self {
+        RegionTable {
+            name: name.to_string(),
+            start: None,
+            end: None,
+            size: None,
+            access: None,
+            section: None,
+            reserved: None,
+            line,
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: &Value, line: usize) -> eyre::Result<()> {
+        match key {
+            "start" => self.start = Some(value.as_u32(line)?),
+            "end" => self.end = Some(value.as_u32(line)?),
+            "size" => self.size = Some(value.as_u32(line)?),
+            "access" => {
+                let access = value.as_str(line)?;
+                self.access = Some(access.parse().map_err(|e| eyre!("Line {}: {}", line, e))?);
+            }
+            "section" => self.section = Some(value.as_str(line)?.to_string()),
+            "reserved" => self.reserved = Some(value.as_bool(line)?),
+            key => return Err(eyre!("Line {}: unknown region key {}", line, key)),
+        }
+        Ok(())
+    }
+
+    pub fn build(self) -> eyre::Result<Region> {
+        let RegionTable {
+            name,
+            start,
+            end,
+            size,
+            access,
+            section,
+            reserved,
+            line,
+        } = self;
+        let range = match (start, end, size) {
+            (Some(start), Some(end), None) if end >= start => AddrRange { start, end },
+            (Some(start), None, Some(size)) => size
+                .checked_sub(1)
+                .and_then(|len| start.checked_add(len))
+                .map(|end| AddrRange { start, end })
+                .ok_or_else(|| eyre!("Line {}: region {} has an invalid size", line, name))?,
+            _ => {
+                return Err(eyre!(
+                    "Line {}: region {} needs a start and either an end at or after it or a size",
+                    line,
+                    name
+                ))
+            }
+        };
+        Ok(Region {
+            name,
+            range,
+            access: access.unwrap_or_default(),
+            section,
+            reserved: reserved.unwrap_or(access == Some(Access::default())),
+        })
+    }
+}
+
+/// The named regions of a device, as given by its profile or a memory map
+/// file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryMap {
+    pub regions: Vec<Region>,
+}
+
+impl MemoryMap {
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Reading memory map {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Parsing memory map {}", path.display()))
+    }
+
+    /// Reads a memory map file, holding one `[region.NAME]` table per region
+    /// with the same keys as those of a profile:
+    ///
+    /// ```toml
+    /// [region.FLASH]
+    /// start = 0x08000000
+    /// size = 0x80000
+    /// access = "rx"
+    ///
+    /// [region.SRAM]
+    /// start = 0x20000000
+    /// end = 0x2001FFFF
+    /// access = "rw"
+    ///
+    /// [region.OPTION_BYTES]
+    /// start = 0x1FFFC000
+    /// size = 0x10
+    /// reserved = true
+    /// ```
+    pub fn parse(text: &str) -> eyre::Result<Self> {
+        let mut tables = Vec::<RegionTable>::new();
+        for entry in config::parse_toml(text)? {
+            let [section, name] = entry.table.as_slice() else {
+                return Err(eyre!("Line {}: expected a [region.NAME] table", entry.line));
+            };
+            if section != "region" {
+                return Err(eyre!("Line {}: expected a [region.NAME] table", entry.line));
+            }
+            let pos = match tables.iter().position(|t| t.name == *name) {
+                Some(pos) => pos,
+                None => {
+                    tables.push(RegionTable::new(name, entry.line));
+                    tables.len() - 1
+                }
+            };
+            tables[pos].set(&entry.key, &entry.value, entry.line)?;
+        }
+        let regions = tables
+            .into_iter()
+            .map(RegionTable::build)
+            .collect::<eyre::Result<_>>()?;
+        Ok(MemoryMap { regions })
+    }
+
+    /// The first reserved region `range` reaches into, if any.
+    pub fn reserved_overlap(&self, range: AddrRange) -> Option<&Region> {
+        self.regions
+            .iter()
+            .find(|r| r.reserved && r.range.overlaps(range))
+    }
+
+    /// Looks up a region by name, ignoring case.
+    pub fn region(&self, name: &str) -> eyre::Result<&Region> {
+        self.regions
+            .iter()
+            .find(|r| r.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                if self.regions.is_empty() {
+                    eyre!(
+                        "No region named {}, the memory map has no regions (select a profile \
+                         defining them with --profile, or give a --memory-map)",
+                        name
+                    )
+                } else {
+                    eyre!(
+                        "No region named {}, known regions are {}",
+                        name,
+                        self.regions
+                            .iter()
+                            .map(|r| r.name.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                }
+            })
+    }
+}
@@ -0,0 +1,54 @@
+use std::io;
+
+use crate::hex::LineEnding;
+
+/// Marks the provenance comment naming the tool that wrote the file.
+const TOOL_KEY: &str = "; tool: ";
+/// Marks a provenance comment describing one operation.
+const OP_KEY: &str = "; op: ";
+
+/// How a file came to be: the operations applied to produce it, oldest
+/// first. Kept as `;` comment lines after the EOF record, which
+/// [`crate::hex::Context`] skips:
+///
+/// ```text
+/// :00000001FF
+/// ; tool: hex-reader 0.1.0
+/// ; op: hex-reader app.hex merge boot.hex merged.hex
+/// ; op: hex-reader merged.hex crc-patch --range 0x08000000..0x0801FFFB --store-at 0x0801FFFC signed.hex
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    pub ops: Vec<String>,
+}
+
+impl Provenance {
+    /// Reads the operations recorded in a file's text, if any.
+    pub fn parse(text: &[u8]) -> Self {
+        let ops = text
+            .split(|&b| b == b'\n')
+            .filter_map(|line| {
+                let line = String::from_utf8_lossy(line);
+                let op = line.trim_end().strip_prefix(OP_KEY)?;
+                Some(op.to_string())
+            })
+            .collect();
+        Provenance { ops }
+    }
+
+    /// Writes the comment lines, to follow the EOF record.
+    pub fn write<W: io::Write>(&self, mut w: W, line_ending: LineEnding) -> io::Result<()> {
+        let version = env!("CARGO_PKG_VERSION");
+        write!(
+            w,
+            "{}hex-reader {}{}",
+            TOOL_KEY,
+            version,
+            line_ending.as_str()
+        )?;
+        for op in &self.ops {
+            write!(w, "{}{}{}", OP_KEY, op, line_ending.as_str())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+
+use itertools::Itertools;
+
+use crate::hex::AddrRange;
+
+/// A set of addresses, kept as sorted ranges that neither overlap nor touch.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct AddrRangeSet {
+    ranges: Vec<AddrRange>,
+}
+
+impl AddrRangeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The ranges making up the set, in ascending order.
+    pub fn ranges(&self) -> &[AddrRange] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Number of addresses in the set.
+    pub fn size(&self) -> u64 {
+        self.ranges.iter().map(|r| r.size()).sum()
+    }
+
+    /// Adds `range`, merging it with the ranges it overlaps or touches.
+    pub fn insert(&mut self, range: AddrRange) {
+        // First range that could merge with `range`, i.e. doesn't end
+        // before the address preceding it
+        let first = self
+            .ranges
+            .partition_point(|r| r.end.checked_add(1).is_some_and(|next| next < range.start));
+        let mut merged = range;
+        let mut last = first;
+        while let Some(r) = self.ranges.get(last) {
+            if merged.end.checked_add(1).is_some_and(|next| r.start > next) {
+                break;
+            }
+            merged.start = merged.start.min(r.start);
+            merged.end = merged.end.max(r.end);
+            last += 1;
+        }
+        self.ranges.splice(first..last, [merged]);
+    }
+
+    /// Index of the range holding `addr`, if any.
+    fn find(&self, addr: u32) -> Option<usize> {
+        let idx = self.ranges.partition_point(|r| r.end < addr);
+        self.ranges
+            .get(idx)
+            .is_some_and(|r| r.start <= addr)
+            .then_some(idx)
+    }
+
+    pub fn contains(&self, addr: u32) -> bool {
+        self.find(addr).is_some()
+    }
+
+    /// Whether every address of `range` is in the set.
+    pub fn contains_range(&self, range: AddrRange) -> bool {
+        self.find(range.start)
+            .is_some_and(|idx| self.ranges[idx].end >= range.end)
+    }
+
+    /// Whether any address of `range` is in the set.
+    pub fn overlaps(&self, range: AddrRange) -> bool {
+        let idx = self.ranges.partition_point(|r| r.end < range.start);
+        self.ranges.get(idx).is_some_and(|r| r.start <= range.end)
+    }
+
+    pub fn union(&self, other: &AddrRangeSet) -> AddrRangeSet {
+        self.ranges
+            .iter()
+            .merge_by(&other.ranges, |a, b| a.start <= b.start)
+            .copied()
+            .collect()
+    }
+
+    pub fn intersection(&self, other: &AddrRangeSet) -> AddrRangeSet {
+        let mut result = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while let (Some(a), Some(b)) = (self.ranges.get(i), other.ranges.get(j)) {
+            let start = a.start.max(b.start);
+            let end = a.end.min(b.end);
+            if start <= end {
+                result.push(AddrRange { start, end });
+            }
+            if a.end < b.end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        AddrRangeSet { ranges: result }
+    }
+
+    /// The addresses of `self` that aren't in `other`.
+    pub fn difference(&self, other: &AddrRangeSet) -> AddrRangeSet {
+        let ranges = self
+            .ranges
+            .iter()
+            .flat_map(|r| r.subtract(&other.ranges))
+            .collect();
+        AddrRangeSet { ranges }
+    }
+}
+
+impl From<AddrRange> for AddrRangeSet {
+    fn from(range: AddrRange) -> Self {
+        AddrRangeSet {
+            ranges: vec![range],
+        }
+    }
+}
+
+impl FromIterator<AddrRange> for AddrRangeSet {
+    fn from_iter<I: IntoIterator<Item = AddrRange>>(iter: I) -> Self {
+        let mut ranges = iter.into_iter().collect::<Vec<_>>();
+        ranges.sort_by_key(|r| r.start);
+        let mut set = AddrRangeSet::new();
+        for range in ranges {
+            match set.ranges.last_mut() {
+                Some(last)
+                    if last
+                        .end
+                        .checked_add(1)
+                        .is_none_or(|next| range.start <= next) =>
+                {
+                    last.end = last.end.max(range.end);
+                }
+                _ => set.ranges.push(range),
+            }
+        }
+        set
+    }
+}
+
+impl fmt::Display for AddrRangeSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.ranges.iter().join(", "))
+    }
+}
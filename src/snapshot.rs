@@ -0,0 +1,191 @@
+//! Incremental re-parsing for editors and file watchers.
+//!
+//! A [`Snapshot`] keeps the parse result of every line together with the
+//! extended address in effect before it. After an edit only the touched
+//! lines are parsed again, plus any following lines whose extended address
+//! changed as a result.
+
+use std::ops::Range;
+
+use eyre::eyre;
+
+use crate::hex::{self, HexFile, ParseError, Record};
+
+/// Per-line parse state of a whole Intel HEX text.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    lines: Vec<Line>,
+    len: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    /// Byte offset of the line in the text.
+    offset: usize,
+    /// Length including the line terminator.
+    len: usize,
+    /// Extended address in effect before the line.
+    base: u32,
+    result: Result<Option<Record>, ParseError>,
+}
+
+impl Line {
+    fn end(&self) -> usize {
+        self.offset + self.len
+    }
+}
+
+impl Snapshot {
+    /// Parses all of `text`.
+    pub fn parse(text: &[u8]) -> Self {
+        Snapshot {
+            lines: parse_lines(text, 0, text.len(), 0, 0),
+            len: text.len(),
+        }
+    }
+
+    /// Brings the snapshot up to date with `text`, in which the bytes at
+    /// `edit` of the previously parsed text were replaced by `inserted` new
+    /// bytes. Returns the indices of the lines that were parsed again.
+    pub fn update(&mut self, text: &[u8], edit: Range<usize>, inserted: usize) -> Range<usize> {
+        let delta = inserted as isize - edit.len() as isize;
+        if self.lines.is_empty()
+            || edit.end > self.len
+            || text.len() as isize != self.len as isize + delta
+        {
+            // Nothing to reuse, or the edit doesn't describe this text
+            *self = Snapshot::parse(text);
+            return 0..self.lines.len();
+        }
+
+        // Lines overlapping the edit, plus the ones it touches at either end
+        // since the new bytes may join onto them
+        let first = self
+            .lines
+            .partition_point(|l| l.end() < edit.start)
+            .min(self.lines.len() - 1);
+        let last = self
+            .lines
+            .partition_point(|l| l.offset <= edit.end)
+            .max(first + 1);
+        let (start, base) = (self.lines[first].offset, self.lines[first].base);
+        let end = (self.lines[last - 1].end() as isize + delta) as usize;
+        let mut reparsed = parse_lines(text, start, end, first, base);
+        let mut next_base = reparsed.last().map_or(base, after_base);
+
+        let mut tail = self.lines.split_off(last);
+        for line in &mut tail {
+            line.offset = (line.offset as isize + delta) as usize;
+        }
+        // Following lines need parsing again until one starts with the same
+        // extended address as before
+        let mut resume = 0;
+        while let Some(line) = tail.get(resume) {
+            if line.base == next_base {
+                break;
+            }
+            let idx = first + reparsed.len() + 1;
+            let content = &text[line.offset..line.end()];
+            let (result, after) = parse_one(next_base, idx, content, line.offset);
+            reparsed.push(Line {
+                base: next_base,
+                result,
+                ..*line
+            });
+            next_base = after;
+            resume += 1;
+        }
+        let line_delta = (first + reparsed.len()) as isize - (last + resume) as isize;
+        for line in &mut tail[resume..] {
+            if let Err(e) = &mut line.result {
+                e.line = (e.line as isize + line_delta) as usize;
+                e.line_offset = line.offset;
+            }
+        }
+
+        let reparsed_lines = first..first + reparsed.len();
+        self.lines.truncate(first);
+        self.lines.extend(reparsed);
+        self.lines.extend(tail.drain(resume..));
+        self.len = text.len();
+        reparsed_lines
+    }
+
+    /// Number of lines in the text.
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Malformed lines, in file order.
+    pub fn errors(&self) -> impl Iterator<Item = &ParseError> {
+        self.lines.iter().filter_map(|l| l.result.as_ref().err())
+    }
+
+    /// Builds the image, failing like [`hex::Context::parse`] would.
+    pub fn hex_file(&self) -> eyre::Result<HexFile> {
+        let mut records = Vec::new();
+        for (i, line) in self.lines.iter().enumerate() {
+            match &line.result {
+                Err(e) => return Err(e.clone().into()),
+                Ok(Some(Record::Eof)) => {
+                    let rest = &self.lines[i + 1..];
+                    if !rest.is_empty() {
+                        return Err(eyre!(
+                            "{} line(s) ({} bytes) follow the EOF record on line {}",
+                            rest.len(),
+                            rest.iter().map(|l| l.len).sum::<usize>(),
+                            i + 1
+                        ));
+                    }
+                    return Ok(HexFile::from_records(records));
+                }
+                Ok(Some(record)) => records.push(record.clone()),
+                Ok(None) => {}
+            }
+        }
+        Err(eyre!("Unexpected EOF"))
+    }
+}
+
+/// Parses the lines of `text[start..end]`, numbering them from `first + 1`.
+fn parse_lines(text: &[u8], start: usize, end: usize, first: usize, base: u32) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut base = base;
+    let mut offset = start;
+    while offset < end {
+        let len = text[offset..end]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(end - offset, |pos| pos + 1);
+        let idx = first + lines.len() + 1;
+        let (result, after) = parse_one(base, idx, &text[offset..offset + len], offset);
+        lines.push(Line {
+            offset,
+            len,
+            base,
+            result,
+        });
+        base = after;
+        offset += len;
+    }
+    lines
+}
+
+fn parse_one(
+    base: u32,
+    idx: usize,
+    content: &[u8],
+    offset: usize,
+) -> (Result<Option<Record>, ParseError>, u32) {
+    let content = content.strip_suffix(b"\n").unwrap_or(content);
+    hex::parse_line(base, idx, content, offset)
+}
+
+/// Extended address in effect after `line`.
+fn after_base(line: &Line) -> u32 {
+    match line.result {
+        Ok(Some(Record::ExtSegmentAddr(seg))) => (seg as u32) << 4,
+        Ok(Some(Record::ExtLinearAddr(hi))) => (hi as u32) << 16,
+        _ => line.base,
+    }
+}
@@ -0,0 +1,435 @@
+//! Peripheral and register names read from a CMSIS-SVD device description,
+//! for naming the addresses an image initializes.
+//!
+//! Only what places registers is read: peripherals with their base address,
+//! address blocks and `derivedFrom`, and registers and clusters with their
+//! offsets, sizes and `dim` arrays. Fields, enumerations and access rights
+//! are skipped.
+
+use std::{fs, path::Path};
+
+use eyre::eyre;
+use eyre::Context;
+
+use crate::hex::AddrRange;
+
+/// A register of a [`Peripheral`], at an absolute address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Register {
+    /// Prefixed with the clusters holding it, as in `CH0.CTRL`.
+    pub name: String,
+    pub addr: u32,
+    /// In bytes.
+    pub size: u32,
+}
+
+impl Register {
+    pub fn range(&self) -> AddrRange {
+        AddrRange {
+            start: self.addr,
+            end: self.addr.saturating_add(self.size.max(1) - 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Peripheral {
+    pub name: String,
+    pub base: u32,
+    /// The address blocks of the peripheral, or the span of its registers
+    /// when it gives none.
+    pub blocks: Vec<AddrRange>,
+    /// In address order.
+    pub registers: Vec<Register>,
+}
+
+impl Peripheral {
+    pub fn contains(&self, addr: u32) -> bool {
+        self.blocks.iter().any(|b| b.contains(addr))
+    }
+
+    pub fn overlaps(&self, range: AddrRange) -> bool {
+        self.blocks.iter().any(|b| b.overlaps(range))
+    }
+}
+
+/// The peripherals of a device, as its SVD file lists them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Device {
+    pub name: String,
+    pub peripherals: Vec<Peripheral>,
+}
+
+impl Device {
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let text =
+            fs::read_to_string(path).with_context(|| format!("Reading SVD {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Parsing SVD {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> eyre::Result<Self> {
+        let root = Element::parse(text)?;
+        if root.name != "device" {
+            return Err(eyre!(
+                "Expected a <device> root element, found <{}>",
+                root.name
+            ));
+        }
+        let defaults = Defaults::default().inherit(&root)?;
+        let mut peripherals: Vec<Peripheral> = Vec::new();
+        let listed = root
+            .child("peripherals")
+            .map(|p| p.children_named("peripheral").collect::<Vec<_>>())
+            .unwrap_or_default();
+        for element in listed {
+            let name = element.required_text("name")?.to_string();
+            let base = parse_number(element.required_text("baseAddress")?)?;
+            // A derived peripheral repeats the registers of another at its
+            // own base address, unless it lists its own
+            let derived = match element.attr("derivedFrom") {
+                Some(from) => {
+                    Some(peripherals.iter().find(|p| p.name == from).ok_or_else(|| {
+                        eyre!("{} derives from unknown peripheral {}", name, from)
+                    })?)
+                }
+                None => None,
+            };
+            let defaults = defaults.inherit(element)?;
+            let mut registers = Vec::new();
+            if let Some(listed) = element.child("registers") {
+                collect_registers(listed, base, "", &defaults, &mut registers)?;
+            } else if let Some(derived) = derived {
+                registers = derived
+                    .registers
+                    .iter()
+                    .map(|r| Register {
+                        addr: r.addr.wrapping_sub(derived.base).wrapping_add(base),
+                        ..r.clone()
+                    })
+                    .collect();
+            }
+            registers.sort_by_key(|r| r.addr);
+            let mut blocks = Vec::new();
+            for block in element.children_named("addressBlock") {
+                let offset = parse_number(block.required_text("offset")?)?;
+                let size = parse_number(block.required_text("size")?)?;
+                if let Some(end) = size
+                    .checked_sub(1)
+                    .and_then(|len| base.checked_add(offset)?.checked_add(len))
+                {
+                    blocks.push(AddrRange {
+                        start: base + offset,
+                        end,
+                    });
+                }
+            }
+            if blocks.is_empty() {
+                match derived {
+                    Some(derived) if element.child("registers").is_none() => {
+                        blocks = derived
+                            .blocks
+                            .iter()
+                            .map(|b| AddrRange {
+                                start: b.start.wrapping_sub(derived.base).wrapping_add(base),
+                                end: b.end.wrapping_sub(derived.base).wrapping_add(base),
+                            })
+                            .collect()
+                    }
+                    _ => {
+                        let start = registers.iter().map(|r| r.addr).min();
+                        let end = registers.iter().map(|r| r.range().end).max();
+                        if let (Some(start), Some(end)) = (start, end) {
+                            blocks.push(AddrRange { start, end });
+                        }
+                    }
+                }
+            }
+            peripherals.push(Peripheral {
+                name,
+                base,
+                blocks,
+                registers,
+            });
+        }
+        Ok(Device {
+            name: root.text("name").unwrap_or_default().to_string(),
+            peripherals,
+        })
+    }
+
+    /// The peripheral whose address blocks hold `addr`.
+    pub fn peripheral_at(&self, addr: u32) -> Option<&Peripheral> {
+        self.peripherals.iter().find(|p| p.contains(addr))
+    }
+
+    /// The peripherals with address blocks overlapping `range`.
+    pub fn peripherals_in(&self, range: AddrRange) -> impl Iterator<Item = &Peripheral> {
+        self.peripherals.iter().filter(move |p| p.overlaps(range))
+    }
+
+    /// The registers inside `range`, with their peripherals, in address
+    /// order.
+    pub fn registers_in(&self, range: AddrRange) -> Vec<(&Peripheral, &Register)> {
+        let mut found = self
+            .peripherals_in(range)
+            .flat_map(|p| p.registers.iter().map(move |r| (p, r)))
+            .filter(|(_, r)| r.range().overlaps(range))
+            .collect::<Vec<_>>();
+        found.sort_by_key(|(_, r)| r.addr);
+        found
+    }
+
+    /// Names `addr` as `PERIPHERAL.REGISTER`, with `+N` for a byte past the
+    /// register's first, or as `PERIPHERAL+0xN` between registers.
+    pub fn describe(&self, addr: u32) -> Option<String> {
+        let peripheral = self.peripheral_at(addr)?;
+        let register = peripheral
+            .registers
+            .iter()
+            .find(|r| r.range().contains(addr));
+        Some(match register {
+            Some(r) if r.addr == addr => format!("{}.{}", peripheral.name, r.name),
+            Some(r) => format!("{}.{}+{}", peripheral.name, r.name, addr - r.addr),
+            None => format!("{}+0x{:x}", peripheral.name, addr - peripheral.base),
+        })
+    }
+}
+
+/// Register properties inherited from the device, peripheral and clusters
+/// down to the registers.
+#[derive(Debug, Clone, Copy)]
+struct Defaults {
+    /// In bits.
+    size: u32,
+}
+
+impl Default for Defaults {
+    fn default() -> Self {
+        Defaults { size: 32 }
+    }
+}
+
+impl Defaults {
+    fn inherit(&self, element: &Element) -> eyre::Result<Self> {
+        Ok(Defaults {
+            size: match element.text("size") {
+                Some(size) => parse_number(size)?,
+                None => self.size,
+            },
+        })
+    }
+}
+
+/// Adds the registers and clusters of `parent`, which sits at `base`, to
+/// `registers`, with their names after `prefix`.
+fn collect_registers(
+    parent: &Element,
+    base: u32,
+    prefix: &str,
+    defaults: &Defaults,
+    registers: &mut Vec<Register>,
+) -> eyre::Result<()> {
+    for element in &parent.children {
+        let is_cluster = match element.name.as_str() {
+            "register" => false,
+            "cluster" => true,
+            _ => continue,
+        };
+        let offset = parse_number(element.required_text("addressOffset")?)?;
+        let defaults = defaults.inherit(element)?;
+        for (name, step) in instances(element)? {
+            let addr = base.wrapping_add(offset).wrapping_add(step);
+            let name = format!("{}{}", prefix, name);
+            if is_cluster {
+                collect_registers(element, addr, &format!("{}.", name), &defaults, registers)?;
+            } else {
+                registers.push(Register {
+                    name,
+                    addr,
+                    size: defaults.size.div_ceil(8),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The names of a register or cluster and their distances from the first,
+/// one for each element of a `dim` array.
+fn instances(element: &Element) -> eyre::Result<Vec<(String, u32)>> {
+    let name = element.required_text("name")?;
+    let Some(dim) = element.text("dim") else {
+        return Ok(vec![(name.to_string(), 0)]);
+    };
+    let dim = parse_number(dim)?;
+    let increment = parse_number(element.required_text("dimIncrement")?)?;
+    let indices = match element.text("dimIndex") {
+        Some(index) => match index.split_once('-') {
+            Some((first, last)) if !index.contains(',') => {
+                let (first, last) = (parse_number(first)?, parse_number(last)?);
+                (first..=last).map(|i| i.to_string()).collect()
+            }
+            _ => index.split(',').map(|i| i.trim().to_string()).collect(),
+        },
+        None => (0..dim).map(|i| i.to_string()).collect::<Vec<_>>(),
+    };
+    Ok(indices
+        .into_iter()
+        .take(dim as usize)
+        .enumerate()
+        .map(|(i, index)| (name.replace("%s", &index), i as u32 * increment))
+        .collect())
+}
+
+/// A number as SVD writes them: decimal, `0x` hex or `#` binary.
+fn parse_number(s: &str) -> eyre::Result<u32> {
+    let s = s.trim();
+    let parsed = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16)
+    } else if let Some(binary) = s.strip_prefix('#') {
+        u32::from_str_radix(binary, 2)
+    } else {
+        s.parse()
+    };
+    parsed.map_err(|_| eyre!("Invalid number {}", s))
+}
+
+/// An XML element with its attributes, child elements and text, which is
+/// all of XML an SVD file needs.
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    /// Reads the root element of an XML document.
+    fn parse(text: &str) -> eyre::Result<Self> {
+        // Elements still open, innermost last
+        let mut open: Vec<Element> = Vec::new();
+        let mut rest = text;
+        loop {
+            let Some(lt) = rest.find('<') else {
+                return Err(match open.last() {
+                    Some(element) => eyre!("The document ends inside <{}>", element.name),
+                    None => eyre!("The document holds no elements"),
+                });
+            };
+            if let Some(element) = open.last_mut() {
+                element.text.push_str(&unescape(&rest[..lt]));
+            }
+            rest = &rest[lt..];
+            if let Some(after) = rest.strip_prefix("<!--") {
+                rest = skip_past(after, "-->")?;
+            } else if let Some(after) = rest.strip_prefix("<![CDATA[") {
+                let end = after
+                    .find("]]>")
+                    .ok_or_else(|| eyre!("Unterminated CDATA"))?;
+                if let Some(element) = open.last_mut() {
+                    element.text.push_str(&after[..end]);
+                }
+                rest = &after[end + 3..];
+            } else if rest.starts_with("<?") || rest.starts_with("<!") {
+                rest = skip_past(rest, ">")?;
+            } else if let Some(after) = rest.strip_prefix("</") {
+                let end = after
+                    .find('>')
+                    .ok_or_else(|| eyre!("Unterminated closing tag"))?;
+                let name = after[..end].trim();
+                let element = open
+                    .pop()
+                    .filter(|e| e.name == name)
+                    .ok_or_else(|| eyre!("Unexpected </{}>", name))?;
+                rest = &after[end + 1..];
+                match open.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            } else {
+                let end = rest.find('>').ok_or_else(|| eyre!("Unterminated tag"))?;
+                let tag = &rest[1..end];
+                rest = &rest[end + 1..];
+                let (tag, closed) = match tag.strip_suffix('/') {
+                    Some(tag) => (tag, true),
+                    None => (tag, false),
+                };
+                let element = Element::open(tag)?;
+                match (closed, open.last_mut()) {
+                    (false, _) => open.push(element),
+                    (true, Some(parent)) => parent.children.push(element),
+                    (true, None) => return Ok(element),
+                }
+            }
+        }
+    }
+
+    /// An element from the inside of its opening tag, `name attr="value"`.
+    fn open(tag: &str) -> eyre::Result<Self> {
+        let tag = tag.trim();
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let mut element = Element {
+            name: tag[..name_end].to_string(),
+            ..Default::default()
+        };
+        let mut rest = tag[name_end..].trim_start();
+        while !rest.is_empty() {
+            let eq = rest
+                .find('=')
+                .ok_or_else(|| eyre!("Invalid attributes in <{}>", element.name))?;
+            let key = rest[..eq].trim().to_string();
+            let value = rest[eq + 1..].trim_start();
+            let quote = value
+                .chars()
+                .next()
+                .filter(|&q| q == '"' || q == '\'')
+                .ok_or_else(|| eyre!("Unquoted attribute {} in <{}>", key, element.name))?;
+            let end = value[1..]
+                .find(quote)
+                .ok_or_else(|| eyre!("Unterminated attribute {} in <{}>", key, element.name))?;
+            element.attrs.push((key, unescape(&value[1..end + 1])));
+            rest = value[end + 2..].trim_start();
+        }
+        Ok(element)
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    /// The trimmed text of the child element `name`.
+    fn text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|c| c.text.trim())
+    }
+
+    fn required_text(&self, name: &str) -> eyre::Result<&str> {
+        self.text(name)
+            .ok_or_else(|| eyre!("<{}> is missing <{}>", self.name, name))
+    }
+}
+
+fn skip_past<'a>(text: &'a str, end: &str) -> eyre::Result<&'a str> {
+    text.find(end)
+        .map(|i| &text[i + end.len()..])
+        .ok_or_else(|| eyre!("Expected {} before the end of the document", end))
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
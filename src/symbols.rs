@@ -0,0 +1,319 @@
+//! Symbol listings as the various toolchains print them, read into one
+//! list of [`Symbol`]s.
+//!
+//! Understood are `nm` output (with or without `-S` sizes), GNU ld map
+//! files, Keil/armlink map files and a plain CSV of `name,address[,size]`
+//! with an optional header naming the columns. Addresses and sizes in CSV
+//! are hex, with or without `0x`, as in labels files.
+
+use std::str::FromStr;
+
+use eyre::eyre;
+
+/// What a symbol names, as far as the listing tells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Object,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: u32,
+    pub size: Option<u32>,
+    pub kind: SymbolKind,
+}
+
+/// The flavor of a symbol listing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolFormat {
+    Nm,
+    GnuMap,
+    KeilMap,
+    Csv,
+}
+
+impl SymbolFormat {
+    /// Guesses the flavor of `text` from the headings the linkers write, or
+    /// else from the shape of its lines.
+    pub fn detect(text: &str) -> Option<Self> {
+        if text.contains("Image Symbol Table") {
+            return Some(SymbolFormat::KeilMap);
+        }
+        if text.contains("Linker script and memory map") {
+            return Some(SymbolFormat::GnuMap);
+        }
+        let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+        let first = lines.next()?;
+        if first.contains(',') {
+            Some(SymbolFormat::Csv)
+        } else if parse_nm_line(first).is_some() {
+            Some(SymbolFormat::Nm)
+        } else {
+            None
+        }
+    }
+}
+
+impl FromStr for SymbolFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nm" => Ok(SymbolFormat::Nm),
+            "gnu-map" | "ld-map" => Ok(SymbolFormat::GnuMap),
+            "keil-map" | "armlink-map" => Ok(SymbolFormat::KeilMap),
+            "csv" => Ok(SymbolFormat::Csv),
+            _ => Err(format!(
+                "Unknown symbol format: {}, expected nm, gnu-map, keil-map or csv",
+                s
+            )),
+        }
+    }
+}
+
+/// Reads the symbols listed in `text`, which is in `format` or, if `None`,
+/// whichever [`SymbolFormat::detect`] finds. Entries that aren't symbols
+/// with an address, such as undefined ones or section names, are skipped.
+pub fn parse(text: &str, format: Option<SymbolFormat>) -> eyre::Result<Vec<Symbol>> {
+    let format = format
+        .or_else(|| SymbolFormat::detect(text))
+        .ok_or_else(|| {
+            eyre!("Can't tell which format the symbols are in, nm, gnu-map, keil-map or csv")
+        })?;
+    match format {
+        SymbolFormat::Nm => parse_nm(text),
+        SymbolFormat::GnuMap => Ok(parse_gnu_map(text)),
+        SymbolFormat::KeilMap => Ok(parse_keil_map(text)),
+        SymbolFormat::Csv => parse_csv(text),
+    }
+}
+
+/// `ADDR [SIZE] TYPE NAME`, as printed by `nm` and `nm -S`. `None` for
+/// lines of another shape; undefined symbols come out with no address.
+fn parse_nm_line(line: &str) -> Option<Option<Symbol>> {
+    let tokens = line.split_whitespace().collect::<Vec<_>>();
+    let (addr, size, kind, name) = match tokens[..] {
+        [kind, _] if kind.len() == 1 => return Some(None),
+        [addr, kind, name] => (addr, None, kind, name),
+        [addr, size, kind, name] => (addr, Some(size), kind, name),
+        _ => return None,
+    };
+    let kind = match kind {
+        "T" | "t" | "W" | "w" => SymbolKind::Function,
+        "D" | "d" | "B" | "b" | "R" | "r" | "G" | "g" | "S" | "s" | "C" | "V" | "v" => {
+            SymbolKind::Object
+        }
+        _ if kind.len() == 1 => SymbolKind::Other,
+        _ => return None,
+    };
+    let symbol = Symbol {
+        name: name.to_string(),
+        addr: parse_hex(addr)?,
+        size: match size {
+            Some(size) => Some(parse_hex(size)?),
+            None => None,
+        },
+        kind,
+    };
+    Some(Some(symbol))
+}
+
+fn parse_nm(text: &str) -> eyre::Result<Vec<Symbol>> {
+    let mut symbols = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.ends_with(':') {
+            // Blank lines and the archive member headings of `nm lib.a`
+            continue;
+        }
+        match parse_nm_line(line) {
+            Some(symbol) => symbols.extend(symbol),
+            None => return Err(eyre!("Line {}: expected ADDR [SIZE] TYPE NAME", idx + 1)),
+        }
+    }
+    Ok(symbols)
+}
+
+/// Symbols are the lines of the memory map holding just an address and a
+/// name, or an assignment to one, under the section they belong to:
+///
+/// ```text
+///  .text          0x08000000      0x1a4 build/main.o
+///                 0x08000000                Reset_Handler
+///                 0x20020000                _estack = 0x20020000
+/// ```
+fn parse_gnu_map(text: &str) -> Vec<Symbol> {
+    let map = match text.split_once("Linker script and memory map") {
+        Some((_, map)) => map,
+        None => text,
+    };
+    let mut symbols = Vec::new();
+    // Whether the lines are in a code section
+    let mut code = false;
+    for line in map.lines() {
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        if !line.starts_with(char::is_whitespace) {
+            // An output section, such as `.text 0x08000000 0x1a4`
+            if let Some(section) = tokens.first() {
+                code = section.starts_with(".text");
+            }
+            continue;
+        }
+        let name = match tokens[..] {
+            [addr, name] if addr.starts_with("0x") => name,
+            [addr, name, "=", ..] if addr.starts_with("0x") => name,
+            [section, ..] if section.starts_with('.') => {
+                code = section.starts_with(".text");
+                continue;
+            }
+            _ => continue,
+        };
+        if !is_identifier(name) {
+            continue;
+        }
+        let Some(addr) = parse_hex(tokens[0]) else {
+            continue;
+        };
+        symbols.push(Symbol {
+            name: name.to_string(),
+            addr,
+            size: None,
+            kind: if code {
+                SymbolKind::Function
+            } else {
+                SymbolKind::Other
+            },
+        });
+    }
+    symbols
+}
+
+/// Symbols come from the image symbol table, local and global:
+///
+/// ```text
+///     Symbol Name                Value     Ov Type        Size  Object(Section)
+///     Reset_Handler              0x08000101   Thumb Code     8  startup.o(RESET)
+///     SystemCoreClock            0x20000000   Data           4  system.o(.data)
+/// ```
+fn parse_keil_map(text: &str) -> Vec<Symbol> {
+    let table = match text.split_once("Image Symbol Table") {
+        Some((_, table)) => table,
+        None => text,
+    };
+    // The memory map of the image follows the symbol table
+    let table = table
+        .split("Memory Map of the image")
+        .next()
+        .unwrap_or_default();
+    let mut symbols = Vec::new();
+    for line in table.lines() {
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        // Source files are listed as local symbols too
+        let [name, addr, rest @ ..] = &tokens[..] else {
+            continue;
+        };
+        if !is_identifier(name) {
+            continue;
+        }
+        let Some(addr) = addr.starts_with("0x").then(|| parse_hex(addr)).flatten() else {
+            continue;
+        };
+        let kind = if rest.contains(&"Code") {
+            SymbolKind::Function
+        } else if rest.contains(&"Data") {
+            SymbolKind::Object
+        } else if rest.first() == Some(&"Number") {
+            SymbolKind::Other
+        } else {
+            // Sections and other entries that aren't symbols
+            continue;
+        };
+        let size = rest.iter().find_map(|t| t.parse().ok());
+        symbols.push(Symbol {
+            name: name.to_string(),
+            addr,
+            size,
+            kind,
+        });
+    }
+    symbols
+}
+
+fn parse_csv(text: &str) -> eyre::Result<Vec<Symbol>> {
+    let lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty() && !l.starts_with('#'));
+    let fields = |line: &str| -> Vec<String> {
+        line.split(',')
+            .map(|f| f.trim().trim_matches('"').to_string())
+            .collect()
+    };
+    // Columns of the name, address and size, from the header if there is one
+    let mut columns = (0, 1, Some(2));
+    let mut symbols = Vec::new();
+    let mut first = true;
+    for (idx, line) in lines {
+        let fields = fields(line);
+        if first {
+            first = false;
+            let column = |names: &[&str]| {
+                fields
+                    .iter()
+                    .position(|f| names.contains(&f.to_ascii_lowercase().as_str()))
+            };
+            let name = column(&["name", "symbol"]);
+            let addr = column(&["address", "addr", "value"]);
+            if let (Some(name), Some(addr)) = (name, addr) {
+                columns = (name, addr, column(&["size", "length"]));
+                continue;
+            }
+            if fields.get(1).is_some_and(|f| parse_hex(f).is_none()) {
+                return Err(eyre!(
+                    "Line {}: the header needs name and address columns",
+                    idx + 1
+                ));
+            }
+        }
+        let (name, addr, size) = columns;
+        let field = |i: usize| fields.get(i).map(String::as_str).filter(|f| !f.is_empty());
+        let (Some(name), Some(addr)) = (field(name), field(addr)) else {
+            return Err(eyre!("Line {}: expected a name and an address", idx + 1));
+        };
+        let addr =
+            parse_hex(addr).ok_or_else(|| eyre!("Line {}: invalid address {}", idx + 1, addr))?;
+        let size = match size.and_then(field) {
+            Some(size) => Some(
+                parse_hex(size).ok_or_else(|| eyre!("Line {}: invalid size {}", idx + 1, size))?,
+            ),
+            None => None,
+        };
+        symbols.push(Symbol {
+            name: name.to_string(),
+            addr,
+            size,
+            kind: SymbolKind::Other,
+        });
+    }
+    Ok(symbols)
+}
+
+/// A hex number, with or without a `0x` prefix, that fits in an address.
+/// 64-bit tools print addresses with leading zeros.
+fn parse_hex(s: &str) -> Option<u32> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u64::from_str_radix(digits, 16)
+        .ok()
+        .and_then(|n| u32::try_from(n).ok())
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '.'))
+}
@@ -0,0 +1,141 @@
+use std::{fs, path::Path};
+
+use eyre::eyre;
+use eyre::Context;
+
+use crate::hex::HexFile;
+
+/// A binary file transferred to or from target memory by a script.
+#[derive(Debug, PartialEq, Eq)]
+struct Transfer {
+    file: String,
+    addr: u32,
+    len: Option<u64>,
+}
+
+/// Builds an image from the binary files named by the `Data.SAVE.Binary` and
+/// `Data.LOAD.Binary` commands of a TRACE32 PRACTICE script, placing each at
+/// its address. Paths are relative to the script.
+pub fn import_cmm(path: &Path) -> eyre::Result<HexFile> {
+    let script = fs::read_to_string(path).with_context(|| format!("Reading {}", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+
+    let mut hex = HexFile::new();
+    for (idx, line) in script.lines().enumerate() {
+        let Some(transfer) = parse_line(line).map_err(|e| eyre!("Line {}: {}", idx + 1, e))? else {
+            continue;
+        };
+        let file = dir.join(&transfer.file);
+        let mut bytes = fs::read(&file).with_context(|| format!("Reading {}", file.display()))?;
+        if let Some(len) = transfer.len {
+            if (bytes.len() as u64) < len {
+                return Err(eyre!(
+                    "Line {}: {} has {} bytes but the script saved {}",
+                    idx + 1,
+                    file.display(),
+                    bytes.len(),
+                    len
+                ));
+            }
+            bytes.truncate(len as usize);
+        }
+        hex.add_data(transfer.addr, bytes)
+            .with_context(|| format!("Line {}: placing {}", idx + 1, file.display()))?;
+    }
+
+    if hex.data().is_empty() {
+        return Err(eyre!(
+            "{} doesn't reference any binary data",
+            path.display()
+        ));
+    }
+    Ok(hex)
+}
+
+fn parse_line(line: &str) -> Result<Option<Transfer>, String> {
+    let words = split_words(strip_comment(line))?;
+    let Some((cmd, args)) = words.split_first() else {
+        return Ok(None);
+    };
+
+    let parts = cmd.to_ascii_lowercase();
+    let parts = parts.split('.').collect::<Vec<_>>();
+    let [data, op, format] = parts.as_slice() else {
+        return Ok(None);
+    };
+    let is_binary = !format.is_empty() && "binary".starts_with(format);
+    if !matches!(*data, "data" | "d") || !matches!(*op, "save" | "load") || !is_binary {
+        return Ok(None);
+    }
+
+    let [file, location, ..] = args else {
+        return Err(format!("{} needs a file and an address", cmd));
+    };
+    let location = location
+        .split_once(':')
+        .map_or(location.as_str(), |(_, addr)| addr);
+    let (addr, len) = if let Some((start, end)) = location.split_once("--") {
+        let (start, end) = (parse_num(start)?, parse_num(end)?);
+        if end < start {
+            return Err(format!("empty range {}", location));
+        }
+        (start, Some((end - start) as u64 + 1))
+    } else if let Some((start, offset)) = location.split_once("++") {
+        (parse_num(start)?, Some(parse_num(offset)? as u64 + 1))
+    } else {
+        (parse_num(location)?, None)
+    };
+
+    Ok(Some(Transfer {
+        file: file.clone(),
+        addr,
+        len,
+    }))
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_str = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_str = !in_str,
+            ';' if !in_str => return &line[..i],
+            '/' if !in_str && line[i..].starts_with("//") => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted
+                .find('"')
+                .ok_or_else(|| "unterminated string".to_string())?;
+            words.push(quoted[..end].to_string());
+            rest = &quoted[end + 1..];
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            words.push(rest[..end].to_string());
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(words)
+}
+
+/// Parses a PRACTICE number: hex by default, `0x`-prefixed hex, or decimal
+/// with a trailing `.`.
+fn parse_num(s: &str) -> Result<u32, String> {
+    let s = s.trim();
+    let parsed = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16)
+    } else if let Some(dec) = s.strip_suffix('.') {
+        dec.parse()
+    } else {
+        u32::from_str_radix(s, 16)
+    };
+    parsed.map_err(|_| format!("invalid number {}", s))
+}
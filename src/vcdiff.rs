@@ -0,0 +1,460 @@
+//! VCDIFF (RFC 3284) delta encoding, compatible with `xdelta3 -S none`.
+//!
+//! Only the default code table is supported and no secondary compressor is
+//! used, which is what xdelta3 decodes without extra flags. Windows carry the
+//! xdelta3 Adler-32 extension so corrupted deltas are caught on apply.
+
+use std::collections::HashMap;
+
+use eyre::eyre;
+
+const MAGIC: [u8; 4] = [0xD6, 0xC3, 0xC4, 0x00];
+
+const VCD_DECOMPRESS: u8 = 0x01;
+const VCD_CODETABLE: u8 = 0x02;
+const VCD_APPHEADER: u8 = 0x04;
+
+const VCD_SOURCE: u8 = 0x01;
+const VCD_TARGET: u8 = 0x02;
+const VCD_ADLER32: u8 = 0x04;
+
+/// Target bytes encoded per window, well below xdelta3's window limit.
+const WINDOW_SIZE: usize = 1 << 16;
+const MIN_MATCH: usize = 4;
+const MIN_RUN: usize = 8;
+const MAX_CANDIDATES: usize = 16;
+
+const NEAR_SIZE: usize = 4;
+const SAME_SIZE: usize = 3;
+const MODE_SELF: u8 = 0;
+const MODE_HERE: u8 = 1;
+
+const OP_RUN: u8 = 0;
+const OP_ADD: u8 = 1;
+const OP_COPY_SELF: u8 = 19;
+const OP_COPY_HERE: u8 = 35;
+
+/// A decoded delta: the reconstructed target and the application header, if
+/// the encoder stored one.
+#[derive(Debug)]
+pub struct Decoded {
+    pub target: Vec<u8>,
+    pub app_header: Option<Vec<u8>>,
+}
+
+/// Encodes `target` as a delta against `source`, optionally storing
+/// `app_header` in the file header.
+pub fn encode(source: &[u8], target: &[u8], app_header: Option<&[u8]>) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    match app_header {
+        Some(header) => {
+            out.push(VCD_APPHEADER);
+            write_varint(&mut out, header.len() as u64);
+            out.extend_from_slice(header);
+        }
+        None => out.push(0),
+    }
+
+    let index = BlockIndex::new(source);
+    for (i, window) in target.chunks(WINDOW_SIZE).enumerate() {
+        encode_window(&mut out, source, &index, window, i * WINDOW_SIZE);
+    }
+    out
+}
+
+/// Applies `delta` to `source`, reconstructing the target.
+pub fn decode(source: &[u8], delta: &[u8]) -> eyre::Result<Decoded> {
+    let mut r = Reader::new(delta);
+    if r.bytes(4)? != MAGIC {
+        return Err(eyre!("Not a VCDIFF file"));
+    }
+    let indicator = r.byte()?;
+    if indicator & VCD_DECOMPRESS != 0 {
+        return Err(eyre!(
+            "Secondary compression isn't supported, encode with `xdelta3 -S none`"
+        ));
+    }
+    if indicator & VCD_CODETABLE != 0 {
+        return Err(eyre!("Custom code tables aren't supported"));
+    }
+    let app_header = if indicator & VCD_APPHEADER != 0 {
+        let len = r.size()?;
+        Some(r.bytes(len)?.to_vec())
+    } else {
+        None
+    };
+
+    let table = default_code_table();
+    let mut target = Vec::new();
+    while !r.is_empty() {
+        decode_window(&mut r, &table, source, &mut target)?;
+    }
+    Ok(Decoded { target, app_header })
+}
+
+fn encode_window(
+    out: &mut Vec<u8>,
+    source: &[u8],
+    index: &BlockIndex,
+    window: &[u8],
+    window_pos: usize,
+) {
+    let mut data = Vec::new();
+    let mut inst = Vec::new();
+    let mut addr = Vec::new();
+    let src_len = source.len() as u64;
+
+    let mut pending = 0..0;
+    let mut last_copy_end = window_pos;
+    let mut pos = 0;
+    while pos < window.len() {
+        let rest = &window[pos..];
+        let run = rest.iter().take_while(|&&b| b == rest[0]).count();
+        let (copy_at, copy_len) = index.longest_match(source, rest, last_copy_end);
+        if copy_len >= MIN_MATCH && copy_len >= run {
+            flush_add(&mut data, &mut inst, &window[pending.clone()]);
+            let here = src_len + pos as u64;
+            let self_addr = copy_at as u64;
+            if here - self_addr < self_addr {
+                inst.push(OP_COPY_HERE);
+                write_varint(&mut addr, here - self_addr);
+            } else {
+                inst.push(OP_COPY_SELF);
+                write_varint(&mut addr, self_addr);
+            }
+            write_varint(&mut inst, copy_len as u64);
+            last_copy_end = copy_at + copy_len;
+            pos += copy_len;
+            pending = pos..pos;
+        } else if run >= MIN_RUN {
+            flush_add(&mut data, &mut inst, &window[pending.clone()]);
+            inst.push(OP_RUN);
+            write_varint(&mut inst, run as u64);
+            data.push(rest[0]);
+            pos += run;
+            pending = pos..pos;
+        } else {
+            pos += 1;
+            pending.end = pos;
+        }
+    }
+    flush_add(&mut data, &mut inst, &window[pending]);
+
+    let mut body = Vec::new();
+    write_varint(&mut body, window.len() as u64);
+    body.push(0); // Delta_Indicator: no compressed sections
+    write_varint(&mut body, data.len() as u64);
+    write_varint(&mut body, inst.len() as u64);
+    write_varint(&mut body, addr.len() as u64);
+    body.extend_from_slice(&adler32(window).to_be_bytes());
+    body.extend_from_slice(&data);
+    body.extend_from_slice(&inst);
+    body.extend_from_slice(&addr);
+
+    if source.is_empty() {
+        out.push(VCD_ADLER32);
+    } else {
+        out.push(VCD_SOURCE | VCD_ADLER32);
+        write_varint(out, src_len);
+        write_varint(out, 0);
+    }
+    write_varint(out, body.len() as u64);
+    out.extend_from_slice(&body);
+}
+
+fn flush_add(data: &mut Vec<u8>, inst: &mut Vec<u8>, bytes: &[u8]) {
+    if !bytes.is_empty() {
+        inst.push(OP_ADD);
+        write_varint(inst, bytes.len() as u64);
+        data.extend_from_slice(bytes);
+    }
+}
+
+/// Source offsets keyed by the `MIN_MATCH` bytes found there.
+struct BlockIndex {
+    blocks: HashMap<[u8; MIN_MATCH], Vec<usize>>,
+}
+
+impl BlockIndex {
+    fn new(source: &[u8]) -> Self {
+        let mut blocks: HashMap<_, Vec<_>> = HashMap::new();
+        for (i, block) in source.windows(MIN_MATCH).enumerate() {
+            let offsets = blocks.entry(block.try_into().unwrap()).or_default();
+            if offsets.len() < MAX_CANDIDATES {
+                offsets.push(i);
+            }
+        }
+        Self { blocks }
+    }
+
+    /// The longest prefix of `target` found in `source`, preferring `hint`
+    /// (where the previous copy left off) since edits tend to keep offsets.
+    fn longest_match(&self, source: &[u8], target: &[u8], hint: usize) -> (usize, usize) {
+        let match_len = |at: usize| {
+            source[at..]
+                .iter()
+                .zip(target)
+                .take_while(|(a, b)| a == b)
+                .count()
+        };
+        let mut best = (0, 0);
+        if hint < source.len() {
+            best = (hint, match_len(hint));
+        }
+        let Some(key) = target.get(..MIN_MATCH) else {
+            return best;
+        };
+        for &at in self.blocks.get(key).into_iter().flatten() {
+            let len = match_len(at);
+            if len > best.1 {
+                best = (at, len);
+            }
+        }
+        best
+    }
+}
+
+fn decode_window(
+    r: &mut Reader,
+    table: &[CodeEntry; 256],
+    source: &[u8],
+    target: &mut Vec<u8>,
+) -> eyre::Result<()> {
+    let win_indicator = r.byte()?;
+    let segment = if win_indicator & (VCD_SOURCE | VCD_TARGET) != 0 {
+        let len = r.size()?;
+        let pos = r.size()?;
+        let base = if win_indicator & VCD_SOURCE != 0 {
+            source
+        } else {
+            &target[..]
+        };
+        base.get(pos..pos.saturating_add(len))
+            .ok_or_else(|| eyre!("Window references bytes past the end of the source"))?
+            .to_vec()
+    } else {
+        Vec::new()
+    };
+
+    let _enc_len = r.size()?;
+    let window_len = r.size()?;
+    if r.byte()? != 0 {
+        return Err(eyre!(
+            "Compressed sections aren't supported, encode with `xdelta3 -S none`"
+        ));
+    }
+    let data_len = r.size()?;
+    let inst_len = r.size()?;
+    let addr_len = r.size()?;
+    let checksum = if win_indicator & VCD_ADLER32 != 0 {
+        Some(u32::from_be_bytes(r.bytes(4)?.try_into().unwrap()))
+    } else {
+        None
+    };
+    let mut data = Reader::new(r.bytes(data_len)?);
+    let mut inst = Reader::new(r.bytes(inst_len)?);
+    let mut addr = Reader::new(r.bytes(addr_len)?);
+
+    let mut cache = AddrCache::new();
+    let mut window = Vec::with_capacity(window_len);
+    while !inst.is_empty() {
+        let entry = &table[inst.byte()? as usize];
+        for (kind, size, mode) in [entry.first, entry.second] {
+            let size = match (kind, size) {
+                (InstKind::Noop, _) => continue,
+                (_, 0) => inst.size()?,
+                (_, size) => size as usize,
+            };
+            match kind {
+                InstKind::Noop => unreachable!(),
+                InstKind::Add => window.extend_from_slice(data.bytes(size)?),
+                InstKind::Run => {
+                    let b = data.byte()?;
+                    window.resize(window.len() + size, b);
+                }
+                InstKind::Copy => {
+                    let here = segment.len() + window.len();
+                    let at = cache.decode(&mut addr, here, mode)?;
+                    if at >= here {
+                        return Err(eyre!("COPY address {} is ahead of {}", at, here));
+                    }
+                    // Copies may overlap the bytes they produce, so go one at a time
+                    for i in at..at + size {
+                        let b = match i.checked_sub(segment.len()) {
+                            None => segment[i],
+                            Some(i) => window[i],
+                        };
+                        window.push(b);
+                    }
+                }
+            }
+        }
+    }
+
+    if window.len() != window_len {
+        return Err(eyre!(
+            "Window decoded to {} bytes, expected {}",
+            window.len(),
+            window_len
+        ));
+    }
+    if let Some(checksum) = checksum {
+        if adler32(&window) != checksum {
+            return Err(eyre!("Window checksum mismatch, the delta is corrupt"));
+        }
+    }
+    target.extend_from_slice(&window);
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InstKind {
+    Noop,
+    Add,
+    Run,
+    Copy,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CodeEntry {
+    first: (InstKind, u8, u8),
+    second: (InstKind, u8, u8),
+}
+
+/// The default instruction code table from RFC 3284, section 5.6.
+fn default_code_table() -> [CodeEntry; 256] {
+    const NOOP: (InstKind, u8, u8) = (InstKind::Noop, 0, 0);
+    let mut table = Vec::with_capacity(256);
+    let mut push = |first, second| table.push(CodeEntry { first, second });
+    push((InstKind::Run, 0, 0), NOOP);
+    for size in 0..=17 {
+        push((InstKind::Add, size, 0), NOOP);
+    }
+    for mode in 0..9 {
+        push((InstKind::Copy, 0, mode), NOOP);
+        for size in 4..=18 {
+            push((InstKind::Copy, size, mode), NOOP);
+        }
+    }
+    for mode in 0..6 {
+        for add_size in 1..=4 {
+            for copy_size in 4..=6 {
+                push(
+                    (InstKind::Add, add_size, 0),
+                    (InstKind::Copy, copy_size, mode),
+                );
+            }
+        }
+    }
+    for mode in 6..9 {
+        for add_size in 1..=4 {
+            push((InstKind::Add, add_size, 0), (InstKind::Copy, 4, mode));
+        }
+    }
+    for mode in 0..9 {
+        push((InstKind::Copy, 4, mode), (InstKind::Add, 1, 0));
+    }
+    table.try_into().unwrap()
+}
+
+/// The NEAR/SAME address caches, reset at the start of every window.
+struct AddrCache {
+    near: [usize; NEAR_SIZE],
+    next_slot: usize,
+    same: [usize; SAME_SIZE * 256],
+}
+
+impl AddrCache {
+    fn new() -> Self {
+        Self {
+            near: [0; NEAR_SIZE],
+            next_slot: 0,
+            same: [0; SAME_SIZE * 256],
+        }
+    }
+
+    fn decode(&mut self, r: &mut Reader, here: usize, mode: u8) -> eyre::Result<usize> {
+        let near_modes = 2..2 + NEAR_SIZE as u8;
+        let addr = match mode {
+            MODE_SELF => r.size()?,
+            MODE_HERE => here
+                .checked_sub(r.size()?)
+                .ok_or_else(|| eyre!("COPY address before the start of the window"))?,
+            m if near_modes.contains(&m) => self.near[(m - 2) as usize].saturating_add(r.size()?),
+            m => {
+                let m = (m - near_modes.end) as usize;
+                self.same[m * 256 + r.byte()? as usize]
+            }
+        };
+        self.near[self.next_slot] = addr;
+        self.next_slot = (self.next_slot + 1) % NEAR_SIZE;
+        self.same[addr % (SAME_SIZE * 256)] = addr;
+        Ok(addr)
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn bytes(&mut self, len: usize) -> eyre::Result<&'a [u8]> {
+        if len > self.buf.len() {
+            return Err(eyre!("Delta is truncated"));
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> eyre::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// A base-128 integer, most significant group first.
+    fn size(&mut self) -> eyre::Result<usize> {
+        let mut value: usize = 0;
+        loop {
+            let b = self.byte()?;
+            value = value
+                .checked_mul(128)
+                .ok_or_else(|| eyre!("Integer overflows in delta"))?
+                | (b & 0x7F) as usize;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    let mut groups = vec![(value & 0x7F) as u8];
+    value >>= 7;
+    while value != 0 {
+        groups.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.extend(groups.iter().rev());
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    b << 16 | a
+}
@@ -0,0 +1,51 @@
+use hex_reader::AddrRange;
+
+fn range(start: u32, end: u32) -> AddrRange {
+    AddrRange { start, end }
+}
+
+#[test]
+fn overlaps_is_symmetric() {
+    let cases = [
+        (range(0x10, 0x1F), range(0x00, 0x0F), false),
+        (range(0x10, 0x1F), range(0x20, 0x2F), false),
+        (range(0x10, 0x1F), range(0x0F, 0x10), true),
+        (range(0x10, 0x1F), range(0x1F, 0x30), true),
+        (range(0x10, 0x1F), range(0x14, 0x18), true),
+        // The argument swallowing `self` entirely
+        (range(0x10, 0x1F), range(0x00, 0xFF), true),
+        (range(0x10, 0x10), range(0x10, 0x10), true),
+        (range(0, u32::MAX), range(u32::MAX, u32::MAX), true),
+    ];
+    for (a, b, expected) in cases {
+        assert_eq!(a.overlaps(b), expected, "{} / {}", a, b);
+        assert_eq!(b.overlaps(a), expected, "{} / {}", b, a);
+    }
+}
+
+#[test]
+fn intersection() {
+    let a = range(0x10, 0x1F);
+    assert_eq!(a.intersection(range(0x00, 0x0F)), None);
+    assert_eq!(a.intersection(range(0x18, 0x2F)), Some(range(0x18, 0x1F)));
+    assert_eq!(a.intersection(range(0x00, 0xFF)), Some(a));
+    assert_eq!(a.intersection(range(0x12, 0x14)), Some(range(0x12, 0x14)));
+    assert_eq!(range(0x00, 0xFF).intersection(a), Some(a));
+}
+
+#[test]
+fn union() {
+    let a = range(0x10, 0x1F);
+    assert_eq!(a.union(range(0x00, 0x0E)), None);
+    assert_eq!(a.union(range(0x21, 0x2F)), None);
+    // Adjacent ranges join up
+    assert_eq!(a.union(range(0x00, 0x0F)), Some(range(0x00, 0x1F)));
+    assert_eq!(a.union(range(0x20, 0x2F)), Some(range(0x10, 0x2F)));
+    assert_eq!(a.union(range(0x18, 0x2F)), Some(range(0x10, 0x2F)));
+    assert_eq!(a.union(range(0x00, 0xFF)), Some(range(0x00, 0xFF)));
+    assert_eq!(range(0x20, 0x2F).union(a), Some(range(0x10, 0x2F)));
+    assert_eq!(
+        range(0x10, u32::MAX).union(range(0, 0x0F)),
+        Some(range(0, u32::MAX))
+    );
+}
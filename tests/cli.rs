@@ -0,0 +1,206 @@
+//! Integration tests running the `hex-reader` binary against the images in
+//! `tests/fixtures`.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command, Output},
+};
+
+use hex_reader::{AddrRange, Context};
+
+fn fixture(name: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// A path in the temp directory unique to this test run.
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("hex-reader-cli-{}-{}", process::id(), name))
+}
+
+fn hex_reader(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_hex-reader"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Runs `hex-reader` expecting success, returning its stdout.
+fn run_ok(args: &[&str]) -> Vec<u8> {
+    let output = hex_reader(args);
+    assert!(
+        output.status.success(),
+        "hex-reader {} failed:\n{}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output.stdout
+}
+
+fn run_text(args: &[&str]) -> String {
+    String::from_utf8(run_ok(args)).unwrap()
+}
+
+#[test]
+fn ranges() {
+    let out = run_text(&[&fixture("basic.hex"), "ranges"]);
+    for range in [
+        "0x00000000-0x0000001f",
+        "0x00000100-0x00000107",
+        "0x40000000-0x40000007",
+    ] {
+        assert!(out.contains(range), "{} missing from:\n{}", range, out);
+    }
+}
+
+#[test]
+fn entry() {
+    assert_eq!(run_text(&[&fixture("basic.hex"), "entry"]), "0x00000101\n");
+}
+
+#[test]
+fn print_ranges() {
+    let out = run_text(&[
+        &fixture("basic.hex"),
+        "print",
+        "--endian",
+        "none",
+        "--range",
+        "0x4..0x7",
+        "--range",
+        "0x100..0x103",
+    ]);
+    assert!(out.contains("00000004  04 05 06 07"), "{}", out);
+    assert!(out.contains("00000100  de ad be ef"), "{}", out);
+}
+
+#[test]
+fn dump_stdout() {
+    let out = run_ok(&[
+        &fixture("basic.hex"),
+        "dump",
+        "--offset",
+        "0x100",
+        "--len",
+        "4",
+        "--stdout",
+    ]);
+    assert_eq!(out, [0xDE, 0xAD, 0xBE, 0xEF]);
+}
+
+#[test]
+fn dump_fill_spans_gap() {
+    let out = run_ok(&[
+        &fixture("basic.hex"),
+        "dump",
+        "--offset",
+        "0x1E",
+        "--len",
+        "0xE4",
+        "--fill",
+        "0xAA",
+        "--stdout",
+    ]);
+    assert_eq!(out.len(), 0xE4);
+    assert_eq!(out[..2], [0x1E, 0x1F]);
+    assert!(out[2..0xE2].iter().all(|&b| b == 0xAA));
+    assert_eq!(out[0xE2..], [0xDE, 0xAD]);
+}
+
+#[test]
+fn dump_without_fill_rejects_gap() {
+    let output = hex_reader(&[
+        &fixture("basic.hex"),
+        "dump",
+        "--offset",
+        "0x1E",
+        "--len",
+        "0xE4",
+        "--stdout",
+    ]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn crop() {
+    let path = temp_path("crop.hex");
+    run_ok(&[
+        &fixture("basic.hex"),
+        "crop",
+        "--range",
+        "0x10..0x10F",
+        path.to_str().unwrap(),
+    ]);
+    let text = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let hex = Context::new(text.as_slice()).parse().unwrap();
+    assert_eq!(
+        hex.address_ranges(),
+        [
+            AddrRange {
+                start: 0x10,
+                end: 0x1F
+            },
+            AddrRange {
+                start: 0x100,
+                end: 0x107
+            }
+        ]
+    );
+}
+
+#[test]
+fn malformed_input_fails() {
+    let path = temp_path("malformed.hex");
+    fs::write(&path, ":10000000000102\n:00000001FF\n").unwrap();
+    let output = hex_reader(&[path.to_str().unwrap(), "ranges"]);
+    fs::remove_file(&path).unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Line 1"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn unknown_subcommand_fails() {
+    let output = hex_reader(&[&fixture("basic.hex"), "no-such-command"]);
+    assert!(!output.status.success());
+}
+
+/// Runs `hex-reader` expecting failure, returning its stderr.
+fn run_err(args: &[&str]) -> String {
+    let output = hex_reader(args);
+    assert!(
+        !output.status.success(),
+        "hex-reader {} succeeded",
+        args.join(" ")
+    );
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn missing_profile_settings_name_the_flags() {
+    let stderr = run_err(&[&fixture("basic.hex"), "usage"]);
+    assert!(
+        stderr.contains(
+            "No memories to report on, select a profile defining regions with --profile, \
+             give a --memory-map or give them with --memory"
+        ),
+        "{}",
+        stderr
+    );
+    let stderr = run_err(&[&fixture("basic.hex"), "estimate"]);
+    assert!(
+        stderr
+            .contains("No programming speed, give --rate or a profile with program_bytes_per_sec"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,161 @@
+//! Golden tests over the images in `tests/fixtures`. Every `NAME.hex` there
+//! is run through each output below and compared with
+//! `tests/fixtures/expected/NAME.<output>`.
+//!
+//! To add a fixture, drop it in `tests/fixtures` and run the tests with
+//! `UPDATE_GOLDEN=1` to write its expected outputs, then review them.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use hex_reader::{elf, Context, HexFile, PrintOptions, WriteOptions};
+
+fn fixtures() -> Vec<(String, Vec<u8>)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut fixtures = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "hex"))
+        .map(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            (name, fs::read(&path).unwrap())
+        })
+        .collect::<Vec<_>>();
+    fixtures.sort();
+    assert!(!fixtures.is_empty(), "no fixtures in {}", dir.display());
+    fixtures
+}
+
+fn expected_path(name: &str, output: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/expected")
+        .join(format!("{}.{}", name, output))
+}
+
+fn parse(text: &[u8]) -> HexFile {
+    Context::new(text).parse().unwrap()
+}
+
+/// Compares `render`'s output for every fixture with its golden file.
+fn check(output: &str, render: impl Fn(&[u8]) -> Vec<u8>) {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let mut mismatches = Vec::new();
+    for (name, text) in fixtures() {
+        let actual = render(&text);
+        let path = expected_path(&name, output);
+        if update {
+            fs::write(&path, &actual).unwrap();
+            continue;
+        }
+        match fs::read(&path) {
+            Ok(expected) if expected == actual => {}
+            Ok(_) => mismatches.push(format!("{} differs from {}", name, path.display())),
+            Err(_) => mismatches.push(format!("{} has no {}", name, path.display())),
+        }
+    }
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+#[test]
+fn pretty() {
+    check("pretty.txt", |text| {
+        let mut out = Vec::new();
+        parse(text).pretty_print(&mut out).unwrap();
+        out
+    });
+}
+
+#[test]
+fn records() {
+    check("records.txt", |text| {
+        let mut out = Vec::new();
+        Context::new(text).pretty_print(&mut out).unwrap();
+        out
+    });
+}
+
+#[test]
+fn bytes() {
+    check("bytes.txt", |text| {
+        let hex = parse(text);
+        let mut out = Vec::new();
+        for range in hex.address_ranges() {
+            hex.print_bytes(&mut out, range.start, range.end, &PrintOptions::default())
+                .unwrap();
+        }
+        out
+    });
+}
+
+#[test]
+fn words32() {
+    check("words32.txt", |text| {
+        let hex = parse(text);
+        let mut out = Vec::new();
+        for range in hex.address_ranges() {
+            hex.print_words32(&mut out, range.start, range.end, &PrintOptions::default())
+                .unwrap();
+        }
+        out
+    });
+}
+
+#[test]
+fn ranges() {
+    check("ranges.txt", |text| {
+        parse(text)
+            .address_ranges()
+            .iter()
+            .map(|range| format!("{}\n", range))
+            .collect::<String>()
+            .into_bytes()
+    });
+}
+
+#[test]
+fn rewrite() {
+    check("out.hex", |text| {
+        let mut out = Vec::new();
+        parse(text)
+            .write(&mut out, &WriteOptions::default())
+            .unwrap();
+        out
+    });
+}
+
+#[test]
+fn to_elf() {
+    check("elf", |text| {
+        elf::to_elf(&parse(text), &elf::ElfOptions::default()).unwrap()
+    });
+}
+
+#[test]
+fn rewrite_round_trips() {
+    for (name, text) in fixtures() {
+        let hex = parse(&text);
+        let mut out = Vec::new();
+        hex.write(&mut out, &WriteOptions::default()).unwrap();
+        assert_eq!(parse(&out), hex, "{}", name);
+    }
+}
+
+#[test]
+fn elf_round_trips() {
+    for (name, text) in fixtures() {
+        let hex = parse(&text);
+        let elf_data = elf::to_elf(&hex, &elf::ElfOptions::default()).unwrap();
+        let (loaded, _) = elf::from_elf(&elf_data).unwrap();
+        assert_eq!(loaded.address_ranges(), hex.address_ranges(), "{}", name);
+        for range in hex.address_ranges() {
+            assert_eq!(
+                loaded.flatten_range(range, 0),
+                hex.flatten_range(range, 0),
+                "{}",
+                name
+            );
+        }
+    }
+}
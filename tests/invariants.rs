@@ -0,0 +1,256 @@
+//! Property tests running random sequences of mutating operations on an
+//! image and checking after each one that it's still valid and holds the
+//! same bytes as a plain map from address to byte.
+
+use std::collections::BTreeMap;
+
+use hex_reader::{AddrRange, HexFile, OverlapPolicy};
+
+/// Operations per sequence.
+const STEPS: usize = 60;
+/// Sequences per test, each from its own seed.
+const RUNS: u64 = 200;
+
+/// xorshift64*, so failures reproduce from the seed alone.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    /// An address, mostly in a small window so that operations collide,
+    /// sometimes right below the end of the address space.
+    fn addr(&mut self) -> u32 {
+        if self.below(8) == 0 {
+            u32::MAX - self.below(0x40) as u32
+        } else {
+            self.below(0x200) as u32
+        }
+    }
+
+    fn bytes(&mut self) -> Vec<u8> {
+        (0..1 + self.below(0x30))
+            .map(|_| self.next() as u8)
+            .collect()
+    }
+
+    /// A range starting at [`Rng::addr`], cut short at the end of the
+    /// address space.
+    fn range(&mut self) -> AddrRange {
+        let start = self.addr();
+        let end = start.saturating_add(self.below(0x40) as u32);
+        AddrRange { start, end }
+    }
+
+    fn policy(&mut self) -> OverlapPolicy {
+        match self.below(3) {
+            0 => OverlapPolicy::Error,
+            1 => OverlapPolicy::FirstWins,
+            _ => OverlapPolicy::LastWins,
+        }
+    }
+}
+
+type Model = BTreeMap<u32, u8>;
+
+fn contents(hex: &HexFile) -> Model {
+    hex.data()
+        .iter()
+        .flat_map(|d| (0..).zip(d.bytes()).map(|(i, &b)| (d.addr() + i, b)))
+        .collect()
+}
+
+/// Addresses `bytes` would occupy from `addr`, or `None` if they don't fit.
+fn span(addr: u32, bytes: &[u8]) -> Option<impl Iterator<Item = (u32, u8)> + '_> {
+    addr.checked_add(bytes.len() as u32 - 1)?;
+    Some((0..).zip(bytes).map(move |(i, &b)| (addr + i, b)))
+}
+
+/// Contiguous ranges of the addresses in `model`.
+fn model_ranges(model: &Model) -> Vec<AddrRange> {
+    let mut ranges = Vec::<AddrRange>::new();
+    for &addr in model.keys() {
+        match ranges.last_mut() {
+            Some(last) if last.end.checked_add(1) == Some(addr) => last.end = addr,
+            _ => ranges.push(AddrRange {
+                start: addr,
+                end: addr,
+            }),
+        }
+    }
+    ranges
+}
+
+/// Merges `other` into `model` as [`HexFile::merge`] would, returning
+/// whether it succeeds. `other` is a single record, so a failing merge
+/// changes nothing.
+fn merge(model: &mut Model, other: &Model, policy: OverlapPolicy) -> bool {
+    match policy {
+        OverlapPolicy::Error => {
+            if other.keys().any(|addr| model.contains_key(addr)) {
+                return false;
+            }
+            model.extend(other);
+        }
+        OverlapPolicy::FirstWins => {
+            for (&addr, &b) in other {
+                model.entry(addr).or_insert(b);
+            }
+        }
+        OverlapPolicy::LastWins => model.extend(other),
+    }
+    true
+}
+
+/// Applies one random operation to both `hex` and `model`, returning its
+/// description for failure messages.
+fn step(rng: &mut Rng, hex: &mut HexFile, model: &mut Model) -> String {
+    match rng.below(8) {
+        0 => {
+            let (addr, bytes) = (rng.addr(), rng.bytes());
+            let ok = hex.add_data(addr, bytes.clone()).is_ok();
+            let expected =
+                span(addr, &bytes).is_some_and(|mut s| s.all(|(a, _)| !model.contains_key(&a)));
+            assert_eq!(ok, expected, "add_data 0x{:08X}", addr);
+            if ok {
+                model.extend(span(addr, &bytes).unwrap());
+            }
+            format!("add_data 0x{:08X} {} bytes", addr, bytes.len())
+        }
+        1 => {
+            let (addr, bytes, create) = (rng.addr(), rng.bytes(), rng.below(2) == 0);
+            let ok = hex.patch(addr, &bytes, create).is_ok();
+            let expected = span(addr, &bytes)
+                .is_some_and(|mut s| create || s.all(|(a, _)| model.contains_key(&a)));
+            assert_eq!(ok, expected, "patch 0x{:08X}", addr);
+            if ok {
+                model.extend(span(addr, &bytes).unwrap());
+            }
+            format!(
+                "patch 0x{:08X} {} bytes create={}",
+                addr,
+                bytes.len(),
+                create
+            )
+        }
+        2 => {
+            let ranges = [rng.range(), rng.range()];
+            hex.erase(&ranges);
+            model.retain(|&a, _| !ranges.iter().any(|r| r.contains(a)));
+            format!("erase {} {}", ranges[0], ranges[1])
+        }
+        3 => {
+            let ranges = [rng.range(), rng.range(), rng.range()];
+            hex.crop(&ranges);
+            model.retain(|&a, _| ranges.iter().any(|r| r.contains(a)));
+            format!("crop {} {} {}", ranges[0], ranges[1], ranges[2])
+        }
+        4 => {
+            let (range, pattern) = (rng.range(), rng.bytes());
+            hex.fill(range, &pattern).unwrap();
+            for addr in range.start..=range.end {
+                let phase = (addr - range.start) as usize % pattern.len();
+                model.entry(addr).or_insert(pattern[phase]);
+            }
+            format!("fill {} {} bytes", range, pattern.len())
+        }
+        5 => {
+            let (addr, bytes, policy) = (rng.addr(), rng.bytes(), rng.policy());
+            let Some(other_model) = span(addr, &bytes).map(Model::from_iter) else {
+                return "merge skipped".to_string();
+            };
+            let mut other = HexFile::new();
+            other.add_data(addr, bytes).unwrap();
+            let ok = hex.merge(&other, policy).is_ok();
+            assert_eq!(ok, merge(model, &other_model, policy), "merge");
+            format!("merge {:?} 0x{:08X}", policy, addr)
+        }
+        6 => {
+            let ranges = model_ranges(model);
+            if ranges.is_empty() {
+                return "transpose skipped".to_string();
+            }
+            let src = ranges[rng.below(ranges.len() as u64) as usize];
+            let (dest, policy) = (rng.addr(), rng.policy());
+            let ok = hex.transpose(src.start, dest, false, policy).is_ok();
+            let expected = src.transpose(dest).is_some_and(|dest_range| {
+                policy != OverlapPolicy::Error
+                    || !ranges.iter().any(|r| *r != src && r.overlaps(dest_range))
+            });
+            assert_eq!(ok, expected, "transpose {} to 0x{:08X}", src, dest);
+            if ok {
+                let moving = model
+                    .range(src.start..=src.end)
+                    .map(|(&a, &b)| (a - src.start + dest, b))
+                    .collect::<Model>();
+                model.retain(|&a, _| !src.contains(a));
+                assert!(merge(model, &moving, policy));
+            }
+            format!("transpose {} to 0x{:08X} {:?}", src, dest, policy)
+        }
+        _ => {
+            let len = 1 + rng.below(0x20) as u8;
+            hex.normalize(len).unwrap();
+            format!("normalize {}", len)
+        }
+    }
+}
+
+fn run_sequence(seed: u64) {
+    let mut rng = Rng::new(seed);
+    let mut hex = HexFile::new();
+    let mut model = Model::new();
+    let mut history = Vec::new();
+    for _ in 0..STEPS {
+        history.push(step(&mut rng, &mut hex, &mut model));
+        let context = || format!("seed {} after:\n  {}", seed, history.join("\n  "));
+        if let Err(e) = hex.validate() {
+            panic!("{}\n{}", e, context());
+        }
+        assert!(contents(&hex) == model, "contents differ, {}", context());
+        assert_eq!(hex.address_ranges(), model_ranges(&model), "{}", context());
+    }
+}
+
+#[test]
+fn mutations_keep_invariants() {
+    for seed in 0..RUNS {
+        run_sequence(seed);
+    }
+}
+
+#[test]
+fn written_image_parses_back_valid() {
+    for seed in RUNS..RUNS + 20 {
+        let mut rng = Rng::new(seed);
+        let mut hex = HexFile::new();
+        let mut model = Model::new();
+        for _ in 0..STEPS {
+            step(&mut rng, &mut hex, &mut model);
+        }
+        let mut text = Vec::new();
+        hex.write(&mut text, &Default::default()).unwrap();
+        let parsed = hex_reader::Context::new(text.as_slice()).parse().unwrap();
+        parsed.validate().unwrap();
+        assert_eq!(contents(&parsed), model, "seed {}", seed);
+    }
+}
+
+#[test]
+fn overlapping_records_are_invalid() {
+    let text = b":0400000011223344EE\n:02000200556641\n:00000001FF\n";
+    let hex = hex_reader::Context::new(&text[..]).parse().unwrap();
+    assert!(hex.validate().is_err());
+}